@@ -0,0 +1,195 @@
+use crate::error::{Forbidden, Unauthorized};
+use crate::registry::DEFAULT_USER;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::Arc;
+use warp::Filter;
+
+/// The level of access granted to a token. Each scope includes every scope
+/// below it: `Admin` can do anything `Write` can, which can do anything
+/// `Read` can.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
+#[serde(rename_all = "lowercase")]
+pub enum Scope {
+    /// Fetching tasks, the active task, and scheduler information
+    Read,
+    /// Adding, updating, completing, and deleting tasks
+    Write,
+    /// Changing the priority, enabling/disabling the scheduler, and managing
+    /// webhooks
+    Admin,
+}
+
+/// An API token defined in the server configuration. Only the SHA-256 hash of
+/// the token is stored; use `hash_token()` to compute it.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Token {
+    /// A name identifying the token's owner
+    pub name: String,
+
+    /// The hex-encoded SHA-256 hash of the token
+    pub hash: String,
+
+    /// The access granted to the token
+    pub scope: Scope,
+
+    /// The user whose queue the token accesses. Tokens without a user access
+    /// the default user's queue
+    #[serde(default)]
+    pub user: Option<String>,
+}
+
+/// An `Identity` is the user and scope a request was authenticated as.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Identity {
+    pub user: String,
+    pub scope: Scope,
+}
+
+/// Returns the hex-encoded SHA-256 hash of a token, suitable for the `hash`
+/// field of a `Token`.
+pub fn hash_token(token: &str) -> String {
+    Sha256::digest(token.as_bytes())
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// `Auth` checks the bearer token sent with each request against the
+/// configured tokens. If no tokens are configured, authentication is disabled
+/// and every request is allowed.
+#[derive(Clone)]
+pub struct Auth {
+    tokens: Arc<Vec<Token>>,
+}
+
+impl Auth {
+    /// Creates a new `Auth` accepting the given tokens.
+    pub fn new(tokens: Vec<Token>) -> Self {
+        Self {
+            tokens: Arc::new(tokens),
+        }
+    }
+
+    /// Returns `true` if any tokens are configured.
+    pub fn is_enabled(&self) -> bool {
+        !self.tokens.is_empty()
+    }
+
+    /// Determines the identity of a request from the contents of its
+    /// `Authorization` header. Returns an `Unauthorized` rejection if the
+    /// token is missing or unknown. If authentication is disabled, every
+    /// request is the default user with the `Admin` scope.
+    pub fn identify(&self, header: Option<&str>) -> Result<Identity, warp::Rejection> {
+        if !self.is_enabled() {
+            return Ok(Identity {
+                user: DEFAULT_USER.to_string(),
+                scope: Scope::Admin,
+            });
+        }
+
+        let token = header
+            .and_then(|h| h.strip_prefix("Bearer "))
+            .ok_or(warp::reject::custom(Unauthorized))?;
+        let hash = hash_token(token.trim());
+        let token = self
+            .tokens
+            .iter()
+            .find(|t| t.hash.eq_ignore_ascii_case(&hash))
+            .ok_or(warp::reject::custom(Unauthorized))?;
+
+        Ok(Identity {
+            user: token.user.clone().unwrap_or(DEFAULT_USER.to_string()),
+            scope: token.scope,
+        })
+    }
+
+    /// Checks the contents of an `Authorization` header. Returns an
+    /// `Unauthorized` rejection if the token is missing or unknown, and a
+    /// `Forbidden` rejection if it lacks the required scope.
+    pub fn check(&self, header: Option<&str>, scope: Scope) -> Result<(), warp::Rejection> {
+        if self.identify(header)?.scope >= scope {
+            Ok(())
+        } else {
+            Err(warp::reject::custom(Forbidden))
+        }
+    }
+
+    /// Returns a filter that extracts the identity of a request, rejecting it
+    /// if it isn't authenticated.
+    pub fn identity(&self) -> impl Filter<Extract = (Identity,), Error = warp::Rejection> + Clone {
+        let auth = self.clone();
+
+        warp::header::optional::<String>("authorization").and_then(move |header: Option<String>| {
+            let auth = auth.clone();
+            async move { auth.identify(header.as_deref()) }
+        })
+    }
+
+    /// Returns a filter that rejects requests without a token of at least the
+    /// given scope.
+    pub fn require(
+        &self,
+        scope: Scope,
+    ) -> impl Filter<Extract = (), Error = warp::Rejection> + Clone {
+        let auth = self.clone();
+
+        warp::header::optional::<String>("authorization")
+            .and_then(move |header: Option<String>| {
+                let auth = auth.clone();
+                async move { auth.check(header.as_deref(), scope) }
+            })
+            .untuple_one()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_scopes() {
+        let auth = Auth::new(vec![
+            Token {
+                name: "reader".to_string(),
+                hash: hash_token("read-token"),
+                scope: Scope::Read,
+                user: Some("alice".to_string()),
+            },
+            Token {
+                name: "admin".to_string(),
+                hash: hash_token("admin-token"),
+                scope: Scope::Admin,
+                user: None,
+            },
+        ]);
+
+        let unauthorized = |r: Result<(), warp::Rejection>| {
+            r.is_err_and(|e| e.find::<Unauthorized>().is_some())
+        };
+        let forbidden = |r: Result<(), warp::Rejection>| {
+            r.is_err_and(|e| e.find::<Forbidden>().is_some())
+        };
+
+        assert!(unauthorized(auth.check(None, Scope::Read)));
+        assert!(unauthorized(auth.check(Some("Bearer wrong"), Scope::Read)));
+        assert!(unauthorized(auth.check(Some("read-token"), Scope::Read)));
+
+        assert!(auth.check(Some("Bearer read-token"), Scope::Read).is_ok());
+        assert!(forbidden(auth.check(Some("Bearer read-token"), Scope::Write)));
+        assert!(auth.check(Some("Bearer admin-token"), Scope::Write).is_ok());
+        assert!(auth.check(Some("Bearer admin-token"), Scope::Admin).is_ok());
+
+        assert_eq!(
+            auth.identify(Some("Bearer read-token")).unwrap().user,
+            "alice"
+        );
+        assert_eq!(
+            auth.identify(Some("Bearer admin-token")).unwrap().user,
+            DEFAULT_USER
+        );
+
+        // no tokens, no authentication
+        assert!(Auth::new(Vec::new()).check(None, Scope::Admin).is_ok());
+    }
+}
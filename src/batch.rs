@@ -0,0 +1,156 @@
+use crate::error::TaskNotFound;
+use crate::{NaiveTask, Task, TaskQueue, UpdateTask};
+use serde::{Deserialize, Serialize};
+
+/// A single operation in a batch.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+    /// Adds a task to the queue
+    Add { task: NaiveTask },
+
+    /// Updates the fields given in the `UpdateTask`
+    Update { task: UpdateTask },
+
+    /// Deletes a task from the queue
+    Delete { id: usize },
+
+    /// Marks a task as complete
+    Complete { id: usize },
+}
+
+/// The outcome of a single operation in a batch.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct OperationResult {
+    /// The task the operation produced: the added or updated task, the task
+    /// as it was before it was deleted, or the completed task with its new
+    /// ID
+    pub task: Option<Task>,
+
+    /// Why the operation failed, if it did
+    pub error: Option<String>,
+}
+
+/// The outcome of a batch. Either every operation was applied, or, if any of
+/// them failed, none of them were.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct BatchResult {
+    pub committed: bool,
+
+    /// The outcome of each operation, in the order they were given. If the
+    /// batch was not committed, these describe what would have happened.
+    pub results: Vec<OperationResult>,
+}
+
+impl TaskQueue {
+    /// Applies the operations in order, as if they were a single change. If
+    /// any operation fails, the queue is left exactly as it was. A committed
+    /// batch is recorded in the history as one change, so a single undo
+    /// reverts all of it.
+    pub fn apply(&mut self, operations: &[Operation]) -> BatchResult {
+        let snapshot = self.clone();
+        self.begin_batch();
+
+        let results: Vec<OperationResult> = operations
+            .iter()
+            .map(|operation| match self.apply_operation(operation) {
+                Ok(task) => OperationResult {
+                    task: Some(task),
+                    error: None,
+                },
+                Err(error) => OperationResult {
+                    task: None,
+                    error: Some(error.to_string()),
+                },
+            })
+            .collect();
+
+        let committed = results.iter().all(|r| r.error.is_none());
+        if committed {
+            self.end_batch();
+        } else {
+            *self = snapshot;
+        }
+
+        BatchResult { committed, results }
+    }
+
+    /// Applies a single operation, and returns the task it produced.
+    pub fn apply_operation(&mut self, operation: &Operation) -> Result<Task, TaskNotFound> {
+        match operation {
+            Operation::Add { task } => {
+                let task = Task::from_naive(task.clone(), self.new_id());
+                self.add(task);
+                self.tasks.last().cloned().ok_or(TaskNotFound)
+            }
+            Operation::Update { task } => self.update(task.clone()).cloned(),
+            Operation::Delete { id } => {
+                let task = self.get(*id).cloned().ok_or(TaskNotFound)?;
+                self.delete(*id)?;
+                Ok(task)
+            }
+            Operation::Complete { id } => self.complete(*id),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::naive_task;
+    use crate::PriorityLevel;
+
+    #[test]
+    fn test_apply() {
+        let mut queue = TaskQueue::new();
+
+        let mut update = UpdateTask::new(1);
+        update.priority = Some(PriorityLevel::High);
+        let result = queue.apply(&[
+            Operation::Add {
+                task: naive_task("Task 1"),
+            },
+            Operation::Add {
+                task: naive_task("Task 2"),
+            },
+            Operation::Update { task: update },
+            Operation::Complete { id: 2 },
+        ]);
+        assert!(result.committed);
+        let ids: Vec<usize> = result
+            .results
+            .iter()
+            .map(|r| r.task.as_ref().unwrap().id())
+            .collect();
+        assert_eq!(ids, vec![1, 2, 1, 1]);
+        assert_eq!(queue.get(1).unwrap().priority, PriorityLevel::High);
+        assert_eq!(queue.get_completed(1).unwrap().title, "Task 2");
+        assert_eq!(queue.history().undo.len(), 1);
+
+        // a failing operation rolls back the whole batch
+        let revision = queue.revision();
+        let result = queue.apply(&[
+            Operation::Add {
+                task: naive_task("Task 3"),
+            },
+            Operation::Delete { id: 1 },
+            Operation::Delete { id: 1 },
+        ]);
+        assert!(!result.committed);
+        assert!(result.results[0].error.is_none());
+        assert!(result.results[1].error.is_none());
+        assert!(result.results[2].error.is_some());
+        assert_eq!(queue.iter().count(), 1);
+        assert!(queue.get(1).is_some());
+        assert_eq!(queue.revision(), revision);
+        assert_eq!(queue.history().undo.len(), 1);
+
+        // and a committed batch is undone and redone as a whole
+        queue.undo().unwrap();
+        assert_eq!(queue.iter().count(), 0);
+        assert!(queue.get_completed(1).is_none());
+        queue.redo().unwrap();
+        assert_eq!(queue.get(1).unwrap().priority, PriorityLevel::High);
+        assert_eq!(queue.get_completed(1).unwrap().title, "Task 2");
+    }
+}
@@ -0,0 +1,640 @@
+use crate::batch::{BatchResult, Operation};
+use crate::error::{ClientError, ErrorResponse};
+use crate::events::TaggedEvent;
+use crate::forecast::Slot;
+use crate::history::{Entry, History};
+use crate::priority::Priority;
+use crate::query::{TaskPage, TaskQuery, TaskStatus};
+use crate::registry::{NewWorkspace, WorkspaceInfo};
+use crate::scheduler::Drift;
+use crate::search::{SearchQuery, SearchResults};
+use crate::trash::Trashed;
+use crate::webhooks::{NaiveWebhook, WebhookInfo};
+use crate::{ActiveTask, Hold, NaivePin, NaiveTask, Task, UpdateTask};
+use futures_util::Stream;
+use hyper::body::HttpBody;
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Method, Request, Response, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+
+#[cfg(feature = "https")]
+type Connector = hyper_rustls::HttpsConnector<HttpConnector>;
+
+#[cfg(not(feature = "https"))]
+type Connector = HttpConnector;
+
+/// `TaskClient` is a typed client for the REST API served by `Server`. Every
+/// method sends a single request and returns the deserialized reply, or the
+/// error the server replied with as a `ClientError`.
+///
+/// Conditional requests take the revision of the task (or the priority) they
+/// expect to modify, and fail with
+/// `ClientError::PreconditionFailed` if it has changed since.
+#[derive(Clone)]
+pub struct TaskClient {
+    client: Client<Connector>,
+    address: String,
+    token: Option<String>,
+    user: Option<String>,
+    workspace: Option<String>,
+}
+
+impl TaskClient {
+    /// Creates a new `TaskClient` for the server at the given address, e.g.
+    /// `http://127.0.0.1:8080`.
+    pub fn new(address: &str) -> Self {
+        #[cfg(feature = "https")]
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+
+        #[cfg(not(feature = "https"))]
+        let connector = HttpConnector::new();
+
+        Self {
+            client: Client::builder().build(connector),
+            address: address.trim_end_matches('/').to_string(),
+            token: None,
+            user: None,
+            workspace: None,
+        }
+    }
+
+    /// Sets the API token sent with every request, and returns the client.
+    pub fn with_token(mut self, token: &str) -> Self {
+        self.token = Some(token.to_string());
+        self
+    }
+
+    /// Sets the user whose queues the client operates on, and returns the
+    /// client. By default, the client operates on the token's own user.
+    pub fn with_user(mut self, user: &str) -> Self {
+        self.user = Some(user.to_string());
+        self
+    }
+
+    /// Sets the workspace whose tasks the client operates on, and returns the
+    /// client. By default, the client operates on the active workspace.
+    pub fn with_workspace(mut self, workspace: &str) -> Self {
+        self.workspace = Some(workspace.to_string());
+        self
+    }
+
+    /// Adds a task to the queue.
+    pub async fn add_task(&self, task: &NaiveTask) -> Result<(), ClientError> {
+        self.send(Method::POST, self.url("tasks"), Some(json(task)?), None)
+            .await
+            .map(drop)
+    }
+
+    /// Fetches one page of the tasks matching a query.
+    pub async fn tasks(&self, query: &TaskQuery) -> Result<TaskPage, ClientError> {
+        let query = serde_urlencoded::to_string(query)
+            .map_err(|e| ClientError::Serialization(e.to_string()))?;
+        self.get(format!("{}?{query}", self.url("tasks"))).await
+    }
+
+    /// Applies a batch of operations atomically. A batch that was rolled back
+    /// is not an error; check `BatchResult::committed`.
+    pub async fn batch(&self, operations: &[Operation]) -> Result<BatchResult, ClientError> {
+        let body = Some(json(&operations)?);
+        let response = self
+            .request(Method::POST, self.url("tasks/batch"), body, None)
+            .await?;
+
+        // a rolled back batch replies `409 Conflict` with the same body
+        if response.status() == StatusCode::CONFLICT {
+            return parse(response).await;
+        }
+        parse(check(response).await?).await
+    }
+
+    /// Fetches the changes that can be undone and redone.
+    pub async fn history(&self) -> Result<History, ClientError> {
+        self.get(self.url("tasks/history")).await
+    }
+
+    /// Reverts the most recent change, and returns it.
+    pub async fn undo(&self) -> Result<Entry, ClientError> {
+        self.send_parse(Method::POST, self.url("tasks/undo"), None, None)
+            .await
+    }
+
+    /// Reapplies the most recently undone change, and returns it.
+    pub async fn redo(&self) -> Result<Entry, ClientError> {
+        self.send_parse(Method::POST, self.url("tasks/redo"), None, None)
+            .await
+    }
+
+    /// Fetches the deleted tasks in the trash.
+    pub async fn trash(&self) -> Result<Vec<Trashed>, ClientError> {
+        self.get(self.url("tasks/trash")).await
+    }
+
+    /// Restores a deleted task from the trash, and returns it.
+    pub async fn restore(&self, status: TaskStatus, id: usize) -> Result<Task, ClientError> {
+        let status = match status {
+            TaskStatus::Open => "open",
+            TaskStatus::Completed => "completed",
+        };
+        let url = self.url(&format!("tasks/trash/{status}/{id}"));
+        self.send_parse(Method::PUT, url, None, None).await
+    }
+
+    /// Permanently deletes every task in the trash, and returns how many
+    /// there were.
+    pub async fn empty_trash(&self) -> Result<usize, ClientError> {
+        self.send_parse(Method::DELETE, self.url("tasks/trash"), None, None)
+            .await
+    }
+
+    /// Searches the titles of open and completed tasks.
+    pub async fn search(&self, query: &SearchQuery) -> Result<SearchResults, ClientError> {
+        let query = serde_urlencoded::to_string(query)
+            .map_err(|e| ClientError::Serialization(e.to_string()))?;
+        self.get(format!("{}?{query}", self.url("tasks/search")))
+            .await
+    }
+
+    /// Fetches the task with the given ID.
+    pub async fn task(&self, id: usize) -> Result<Task, ClientError> {
+        self.get(self.url(&format!("tasks/{id}"))).await
+    }
+
+    /// Fetches the completed task with the given ID.
+    pub async fn completed_task(&self, id: usize) -> Result<Task, ClientError> {
+        self.get(self.url(&format!("tasks/complete/{id}"))).await
+    }
+
+    /// Updates a task, and returns its new revision. If `revision` is given,
+    /// the task is only updated if it is still at that revision.
+    pub async fn update_task(
+        &self,
+        update: &UpdateTask,
+        revision: Option<u64>,
+    ) -> Result<u64, ClientError> {
+        let response = self
+            .send(
+                Method::PUT,
+                self.url("tasks"),
+                Some(json(update)?),
+                revision,
+            )
+            .await?;
+        revision_of(&response)
+    }
+
+    /// Deletes a task. If `revision` is given, the task is only deleted if it
+    /// is still at that revision.
+    pub async fn delete_task(&self, id: usize, revision: Option<u64>) -> Result<(), ClientError> {
+        let url = self.url(&format!("tasks/{id}"));
+        self.send(Method::DELETE, url, None, revision)
+            .await
+            .map(drop)
+    }
+
+    /// Enables the scheduler.
+    pub async fn enable(&self) -> Result<(), ClientError> {
+        self.send(Method::POST, self.url("tasks/enable"), None, None)
+            .await
+            .map(drop)
+    }
+
+    /// Disables the scheduler.
+    pub async fn disable(&self) -> Result<(), ClientError> {
+        self.send(Method::POST, self.url("tasks/disable"), None, None)
+            .await
+            .map(drop)
+    }
+
+    /// Fetches the task the scheduler is currently working on.
+    pub async fn active(&self) -> Result<ActiveTask, ClientError> {
+        self.get(self.url("tasks/active")).await
+    }
+
+    /// Pins the task with the given ID as the active task.
+    pub async fn pin(&self, id: usize, pin: &NaivePin) -> Result<(), ClientError> {
+        let url = self.url(&format!("tasks/active/{id}"));
+        self.send(Method::PUT, url, Some(json(pin)?), None)
+            .await
+            .map(drop)
+    }
+
+    /// Removes the pin on the active task.
+    pub async fn unpin(&self) -> Result<(), ClientError> {
+        self.send(Method::DELETE, self.url("tasks/active"), None, None)
+            .await
+            .map(drop)
+    }
+
+    /// Fetches whether the scheduler is enabled.
+    pub async fn status(&self) -> Result<bool, ClientError> {
+        self.get(self.url("tasks/status")).await
+    }
+
+    /// Sets the queue priority, and returns the priority's new revision. If
+    /// `revision` is given, the priority is only set if it is still at that
+    /// revision.
+    pub async fn set_priority(
+        &self,
+        priority: &dyn Priority,
+        revision: Option<u64>,
+    ) -> Result<u64, ClientError> {
+        let body = Some(json(&priority)?);
+        let response = self
+            .send(Method::PUT, self.url("tasks/priority"), body, revision)
+            .await?;
+        revision_of(&response)
+    }
+
+    /// Fetches the queue priority.
+    pub async fn priority(&self) -> Result<Box<dyn Priority>, ClientError> {
+        self.get(self.url("tasks/priority")).await
+    }
+
+    /// Fetches the predicted schedule of the tasks in the queue.
+    pub async fn forecast(&self) -> Result<Vec<Slot>, ClientError> {
+        self.get(self.url("tasks/forecast")).await
+    }
+
+    /// Fetches the long gaps between updates the scheduler recorded.
+    pub async fn drift(&self) -> Result<Vec<Drift>, ClientError> {
+        self.get(self.url("tasks/drift")).await
+    }
+
+    /// Puts the task with the given ID on hold.
+    pub async fn hold(&self, id: usize, hold: &Hold) -> Result<(), ClientError> {
+        let url = self.url(&format!("tasks/hold/{id}"));
+        self.send(Method::PUT, url, Some(json(hold)?), None)
+            .await
+            .map(drop)
+    }
+
+    /// Releases the hold on the task with the given ID.
+    pub async fn release(&self, id: usize) -> Result<(), ClientError> {
+        let url = self.url(&format!("tasks/hold/{id}"));
+        self.send(Method::DELETE, url, None, None).await.map(drop)
+    }
+
+    /// Marks the task with the given ID as complete. If `revision` is given,
+    /// the task is only completed if it is still at that revision.
+    pub async fn complete(&self, id: usize, revision: Option<u64>) -> Result<(), ClientError> {
+        let url = self.url(&format!("tasks/complete/{id}"));
+        self.send(Method::PUT, url, None, revision).await.map(drop)
+    }
+
+    /// Deletes a task from the completed list.
+    pub async fn delete_completed(&self, id: usize) -> Result<(), ClientError> {
+        let url = self.url(&format!("tasks/complete/{id}"));
+        self.send(Method::DELETE, url, None, None).await.map(drop)
+    }
+
+    /// Moves a task to another of the user's workspaces, and returns its ID in
+    /// that workspace.
+    pub async fn move_task(&self, id: usize, workspace: &str) -> Result<usize, ClientError> {
+        let url = self.url(&format!("tasks/move/{id}"));
+        self.send_parse(Method::PUT, url, Some(json(&workspace)?), None)
+            .await
+    }
+
+    /// Subscribes to the events of all of the user's workspaces, and returns
+    /// a stream of them. The stream ends when the server closes the
+    /// connection.
+    pub async fn events(
+        &self,
+    ) -> Result<impl Stream<Item = Result<TaggedEvent, ClientError>>, ClientError> {
+        let response = self
+            .send(Method::GET, self.url("events"), None, None)
+            .await?;
+
+        let state = (response.into_body(), Vec::new());
+        Ok(futures_util::stream::unfold(
+            state,
+            |(mut body, mut buffer)| async move {
+                loop {
+                    if let Some(end) = buffer.windows(2).position(|w| w == b"\n\n") {
+                        let message: Vec<u8> = buffer.drain(..end + 2).collect();
+                        match parse_event(&message) {
+                            Some(event) => return Some((event, (body, buffer))),
+                            None => continue,
+                        }
+                    }
+
+                    match body.data().await? {
+                        Ok(chunk) => buffer.extend_from_slice(&chunk),
+                        Err(e) => {
+                            return Some((Err(ClientError::Http(e.to_string())), (body, buffer)))
+                        }
+                    }
+                }
+            },
+        ))
+    }
+
+    /// Registers a webhook, and returns its ID.
+    pub async fn add_webhook(&self, webhook: &NaiveWebhook) -> Result<usize, ClientError> {
+        self.send_parse(
+            Method::POST,
+            self.url("webhooks"),
+            Some(json(webhook)?),
+            None,
+        )
+        .await
+    }
+
+    /// Fetches the registered webhooks.
+    pub async fn webhooks(&self) -> Result<Vec<WebhookInfo>, ClientError> {
+        self.get(self.url("webhooks")).await
+    }
+
+    /// Deletes a webhook.
+    pub async fn delete_webhook(&self, id: usize) -> Result<(), ClientError> {
+        let url = self.url(&format!("webhooks/{id}"));
+        self.send(Method::DELETE, url, None, None).await.map(drop)
+    }
+
+    /// Fetches a summary of the user's workspaces.
+    pub async fn workspaces(&self) -> Result<Vec<WorkspaceInfo>, ClientError> {
+        self.get(self.user_url("workspaces")).await
+    }
+
+    /// Creates a new workspace.
+    pub async fn add_workspace(&self, workspace: &NewWorkspace) -> Result<(), ClientError> {
+        let body = Some(json(workspace)?);
+        self.send(Method::POST, self.user_url("workspaces"), body, None)
+            .await
+            .map(drop)
+    }
+
+    /// Fetches the name of the user's active workspace.
+    pub async fn active_workspace(&self) -> Result<String, ClientError> {
+        self.get(self.user_url("workspaces/active")).await
+    }
+
+    /// Makes a workspace the user's active workspace.
+    pub async fn set_active_workspace(&self, workspace: &str) -> Result<(), ClientError> {
+        let body = Some(json(&workspace)?);
+        self.send(Method::PUT, self.user_url("workspaces/active"), body, None)
+            .await
+            .map(drop)
+    }
+
+    /// Deletes one of the user's workspaces, along with its tasks.
+    pub async fn delete_workspace(&self, workspace: &str) -> Result<(), ClientError> {
+        let url = self.user_url(&format!("workspaces/{workspace}"));
+        self.send(Method::DELETE, url, None, None).await.map(drop)
+    }
+
+    /// Fetches the names of every user with a queue. Requires an admin token
+    /// of the default user.
+    pub async fn users(&self) -> Result<Vec<String>, ClientError> {
+        self.get(format!("{}/api/v1/users", self.address)).await
+    }
+
+    /// Returns the URL of a path under the client's user and workspace.
+    fn url(&self, path: &str) -> String {
+        match &self.workspace {
+            Some(workspace) => self.user_url(&format!("workspaces/{workspace}/{path}")),
+            None => self.user_url(path),
+        }
+    }
+
+    /// Returns the URL of a path under the client's user.
+    fn user_url(&self, path: &str) -> String {
+        match &self.user {
+            Some(user) => format!("{}/api/v1/users/{user}/{path}", self.address),
+            None => format!("{}/api/v1/{path}", self.address),
+        }
+    }
+
+    /// Sends a `GET` request and deserializes the reply.
+    async fn get<T: DeserializeOwned>(&self, url: String) -> Result<T, ClientError> {
+        self.send_parse(Method::GET, url, None, None).await
+    }
+
+    /// Sends a request and deserializes the reply.
+    async fn send_parse<T: DeserializeOwned>(
+        &self,
+        method: Method,
+        url: String,
+        body: Option<Vec<u8>>,
+        revision: Option<u64>,
+    ) -> Result<T, ClientError> {
+        parse(self.send(method, url, body, revision).await?).await
+    }
+
+    /// Sends a request, and returns the reply if it was successful.
+    async fn send(
+        &self,
+        method: Method,
+        url: String,
+        body: Option<Vec<u8>>,
+        revision: Option<u64>,
+    ) -> Result<Response<Body>, ClientError> {
+        check(self.request(method, url, body, revision).await?).await
+    }
+
+    /// Sends a request, and returns the reply whatever its status.
+    async fn request(
+        &self,
+        method: Method,
+        url: String,
+        body: Option<Vec<u8>>,
+        revision: Option<u64>,
+    ) -> Result<Response<Body>, ClientError> {
+        let mut request = Request::builder().method(method).uri(url);
+        if let Some(token) = &self.token {
+            request = request.header("Authorization", format!("Bearer {token}"));
+        }
+        if let Some(revision) = revision {
+            request = request.header("If-Match", format!("\"{revision}\""));
+        }
+        let request = match body {
+            Some(body) => request
+                .header("Content-Type", "application/json")
+                .body(Body::from(body)),
+            None => request.body(Body::empty()),
+        }
+        .map_err(|e| ClientError::Http(e.to_string()))?;
+
+        self.client
+            .request(request)
+            .await
+            .map_err(|e| ClientError::Http(e.to_string()))
+    }
+}
+
+/// Serializes a request body.
+fn json<T: Serialize + ?Sized>(body: &T) -> Result<Vec<u8>, ClientError> {
+    serde_json::to_vec(body).map_err(|e| ClientError::Serialization(e.to_string()))
+}
+
+/// Deserializes the body of a reply.
+async fn parse<T: DeserializeOwned>(response: Response<Body>) -> Result<T, ClientError> {
+    let body = hyper::body::to_bytes(response.into_body())
+        .await
+        .map_err(|e| ClientError::Http(e.to_string()))?;
+    serde_json::from_slice(&body).map_err(|e| ClientError::Serialization(e.to_string()))
+}
+
+/// Returns the reply if it was successful, otherwise the error it describes.
+async fn check(response: Response<Body>) -> Result<Response<Body>, ClientError> {
+    let status = response.status();
+    if status.is_success() {
+        return Ok(response);
+    }
+
+    match parse::<ErrorResponse>(response).await {
+        Ok(error) => Err(ClientError::from_response(status.as_u16(), error)),
+        Err(_) => Err(ClientError::Http(format!("Unexpected reply {status}"))),
+    }
+}
+
+/// Reads the revision from the `ETag` header of a reply.
+fn revision_of(response: &Response<Body>) -> Result<u64, ClientError> {
+    response
+        .headers()
+        .get("etag")
+        .and_then(|etag| etag.to_str().ok())
+        .and_then(|etag| etag.trim_matches('"').parse().ok())
+        .ok_or(ClientError::Http("The reply has no valid ETag".to_string()))
+}
+
+/// Parses a Server-Sent Events message. Returns `None` for messages without
+/// data, such as keep-alive comments.
+fn parse_event(message: &[u8]) -> Option<Result<TaggedEvent, ClientError>> {
+    let message = String::from_utf8_lossy(message);
+    let data: Vec<&str> = message
+        .lines()
+        .filter_map(|line| line.strip_prefix("data:"))
+        .map(|data| data.strip_prefix(' ').unwrap_or(data))
+        .collect();
+    if data.is_empty() {
+        return None;
+    }
+
+    Some(
+        serde_json::from_str(&data.join("\n"))
+            .map_err(|e| ClientError::Serialization(e.to_string())),
+    )
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::events::Event;
+    use crate::priority::Shortest;
+    use crate::registry::DEFAULT_WORKSPACE;
+    use crate::server::{Server, ServerConfig};
+    use crate::test::naive_task;
+    use crate::TaskQueue;
+    use futures_util::StreamExt;
+    use std::sync::atomic::AtomicBool;
+    use std::sync::{Arc, Mutex};
+
+    /// Starts a server on a free port, and returns a client for it once it is
+    /// listening.
+    async fn serve() -> TaskClient {
+        let address = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let config: ServerConfig =
+            serde_json::from_value(serde_json::json!({ "address": address })).unwrap();
+        let mut server = Server::with_queue(Arc::new(Mutex::new(TaskQueue::new())));
+        let sigterm = Arc::new(AtomicBool::new(false));
+        tokio::spawn(async move { server.run(sigterm, config).await });
+
+        let client = TaskClient::new(&format!("http://{address}"));
+        while client.status().await.is_err() {
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        client
+    }
+
+    #[tokio::test]
+    async fn test_client() {
+        let client = serve().await;
+
+        client.add_task(&naive_task("Task 1")).await.unwrap();
+        client.add_task(&naive_task("Task 2")).await.unwrap();
+        assert_eq!(client.tasks(&TaskQuery::default()).await.unwrap().total, 2);
+
+        let revision = client.task(1).await.unwrap().revision();
+        let update = UpdateTask::new(1).with_title(Some("Renamed".to_string()));
+        let new_revision = client.update_task(&update, Some(revision)).await.unwrap();
+        assert!(new_revision > revision);
+        assert_eq!(client.task(1).await.unwrap().title, "Renamed");
+        assert!(matches!(
+            client.delete_task(1, Some(revision)).await,
+            Err(ClientError::PreconditionFailed)
+        ));
+
+        // error replies map back to typed errors
+        assert!(matches!(
+            client.task(9).await,
+            Err(ClientError::TaskNotFound)
+        ));
+        assert!(matches!(
+            client.active().await,
+            Err(ClientError::SchedulerDisabled)
+        ));
+        assert!(matches!(
+            client.delete_webhook(9).await,
+            Err(ClientError::WebhookNotFound)
+        ));
+        match client.add_task(&naive_task("")).await {
+            Err(ClientError::Validation(fields)) => assert_eq!(fields[0].field, "title"),
+            other => panic!("expected a validation error, got {other:?}"),
+        }
+
+        client.set_priority(&Shortest, None).await.unwrap();
+        assert_eq!(client.priority().await.unwrap().string(), Shortest.string());
+
+        client.complete(2, None).await.unwrap();
+        assert_eq!(client.completed_task(1).await.unwrap().title, "Task 2");
+        client.undo().await.unwrap();
+        assert_eq!(client.task(2).await.unwrap().title, "Task 2");
+
+        let result = client
+            .batch(&[Operation::Delete { id: 2 }, Operation::Delete { id: 2 }])
+            .await
+            .unwrap();
+        assert!(!result.committed);
+        assert!(client.task(2).await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn test_events() {
+        let client = serve().await;
+        let mut events = Box::pin(client.events().await.unwrap());
+
+        client.add_task(&naive_task("Task 1")).await.unwrap();
+        client.enable().await.unwrap();
+
+        match events.next().await {
+            Some(Ok(TaggedEvent {
+                workspace,
+                event: Event::TaskAdded(task),
+                ..
+            })) => {
+                assert_eq!(workspace, DEFAULT_WORKSPACE);
+                assert_eq!(task.title, "Task 1");
+            }
+            _ => panic!("expected a task_added event"),
+        }
+        assert!(matches!(
+            events.next().await.map(|e| e.map(|e| e.event)),
+            Some(Ok(Event::SchedulerEnabled))
+        ));
+
+        // the default workspace can't be deleted while it's active
+        assert!(matches!(
+            client.delete_workspace(DEFAULT_WORKSPACE).await,
+            Err(ClientError::WorkspaceActive)
+        ));
+    }
+}
@@ -1,3 +1,4 @@
+use serde::{Deserialize, Serialize};
 use std::error::Error;
 use std::fmt::{Debug, Display};
 use std::sync::PoisonError;
@@ -58,6 +59,192 @@ impl Debug for TaskNotFound {
 impl Error for TaskNotFound {}
 impl warp::reject::Reject for TaskNotFound {}
 
+/// An error that occurs when an operation requested access to a user's queue
+/// but the user does not exist.
+pub struct UserNotFound;
+
+impl Display for UserNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The requested user does not exist")
+    }
+}
+
+impl Debug for UserNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The requested user does not exist")
+    }
+}
+
+impl Error for UserNotFound {}
+impl warp::reject::Reject for UserNotFound {}
+
+/// An error that occurs when a user or workspace name contains characters
+/// that are not allowed.
+pub struct InvalidName;
+
+impl Display for InvalidName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Names may only contain letters, digits, '-' and '_'")
+    }
+}
+
+impl Debug for InvalidName {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Names may only contain letters, digits, '-' and '_'")
+    }
+}
+
+impl Error for InvalidName {}
+impl warp::reject::Reject for InvalidName {}
+
+/// An error that occurs when a requested workspace does not exist.
+pub struct WorkspaceNotFound;
+
+impl Display for WorkspaceNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The requested workspace does not exist")
+    }
+}
+
+impl Debug for WorkspaceNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The requested workspace does not exist")
+    }
+}
+
+impl Error for WorkspaceNotFound {}
+impl warp::reject::Reject for WorkspaceNotFound {}
+
+/// An error that occurs when an operation requested access to a webhook but
+/// the webhook does not exist.
+pub struct WebhookNotFound;
+
+impl Display for WebhookNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The requested webhook does not exist")
+    }
+}
+
+impl Debug for WebhookNotFound {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The requested webhook does not exist")
+    }
+}
+
+impl Error for WebhookNotFound {}
+impl warp::reject::Reject for WebhookNotFound {}
+
+/// An error that occurs when creating a workspace that already exists.
+pub struct WorkspaceExists;
+
+impl Display for WorkspaceExists {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "A workspace with that name already exists")
+    }
+}
+
+impl Debug for WorkspaceExists {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "A workspace with that name already exists")
+    }
+}
+
+impl Error for WorkspaceExists {}
+impl warp::reject::Reject for WorkspaceExists {}
+
+/// An error that occurs when deleting a user's active workspace.
+pub struct WorkspaceActive;
+
+impl Display for WorkspaceActive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The active workspace can't be deleted")
+    }
+}
+
+impl Debug for WorkspaceActive {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The active workspace can't be deleted")
+    }
+}
+
+impl Error for WorkspaceActive {}
+impl warp::reject::Reject for WorkspaceActive {}
+
+/// An error that occurs when a request's path is served, but not with its
+/// method.
+pub struct MethodNotAllowed;
+
+impl Display for MethodNotAllowed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The method is not allowed for this resource")
+    }
+}
+
+impl Debug for MethodNotAllowed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The method is not allowed for this resource")
+    }
+}
+
+impl Error for MethodNotAllowed {}
+impl warp::reject::Reject for MethodNotAllowed {}
+
+/// An error that occurs when a request is missing a valid API token.
+pub struct Unauthorized;
+
+impl Display for Unauthorized {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "A valid API token is required")
+    }
+}
+
+impl Debug for Unauthorized {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "A valid API token is required")
+    }
+}
+
+impl Error for Unauthorized {}
+impl warp::reject::Reject for Unauthorized {}
+
+/// An error that occurs when a request's API token lacks the scope required
+/// for the operation.
+pub struct Forbidden;
+
+impl Display for Forbidden {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The API token does not have permission for this request")
+    }
+}
+
+impl Debug for Forbidden {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The API token does not have permission for this request")
+    }
+}
+
+impl Error for Forbidden {}
+impl warp::reject::Reject for Forbidden {}
+
+/// An error that occurs when an operation requires the scheduler to be enabled
+/// but it is disabled.
+pub struct SchedulerDisabled;
+
+impl Display for SchedulerDisabled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The scheduler is disabled")
+    }
+}
+
+impl Debug for SchedulerDisabled {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The scheduler is disabled")
+    }
+}
+
+impl Error for SchedulerDisabled {}
+impl warp::reject::Reject for SchedulerDisabled {}
+
 /// An error that occurs in the scheduling logic.
 pub struct SchedulingError(pub String);
 
@@ -109,3 +296,235 @@ impl Debug for ServerError {
 }
 
 impl Error for ServerError {}
+
+/// An error that occurs while delivering a webhook.
+pub struct WebhookError(pub String);
+
+impl Display for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Debug for WebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for WebhookError {}
+
+/// An error that occurs while delivering a notification.
+pub struct NotifyError(pub String);
+
+impl Display for NotifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Debug for NotifyError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for NotifyError {}
+
+/// An error that occurs when a task query is invalid, e.g. because of a
+/// malformed cursor.
+pub struct QueryError(pub String);
+
+impl Display for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Debug for QueryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for QueryError {}
+impl warp::reject::Reject for QueryError {}
+
+/// An error that occurs when a request's `If-Match` header doesn't match the
+/// current revision of the resource it modifies.
+pub struct PreconditionFailed;
+
+impl Display for PreconditionFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The resource has been modified since it was fetched")
+    }
+}
+
+impl Debug for PreconditionFailed {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "The resource has been modified since it was fetched")
+    }
+}
+
+impl Error for PreconditionFailed {}
+impl warp::reject::Reject for PreconditionFailed {}
+
+/// An error that occurs when a change can't be undone or redone.
+pub struct HistoryError(pub String);
+
+impl Display for HistoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Debug for HistoryError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl Error for HistoryError {}
+impl warp::reject::Reject for HistoryError {}
+
+/// A problem with a single field of a request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FieldError {
+    pub field: String,
+    pub message: String,
+}
+
+impl FieldError {
+    /// Creates a new `FieldError` for the given field.
+    pub fn new(field: &str, message: &str) -> Self {
+        Self {
+            field: field.to_string(),
+            message: message.to_string(),
+        }
+    }
+}
+
+/// An error that occurs when a request contains invalid values, with the
+/// problem with each field.
+pub struct ValidationError(pub Vec<FieldError>);
+
+impl Display for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let fields: Vec<String> =
+            self.0.iter().map(|e| format!("{}: {}", e.field, e.message)).collect();
+        write!(f, "Invalid request ({})", fields.join(", "))
+    }
+}
+
+impl Debug for ValidationError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Error for ValidationError {}
+impl warp::reject::Reject for ValidationError {}
+
+/// The body of every error response sent by the server.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ErrorResponse {
+    /// A short machine-readable name for the error, e.g. `task_not_found`
+    pub code: String,
+
+    /// A human-readable description of the error
+    pub message: String,
+
+    /// More information about the error, e.g. the invalid fields of a request
+    #[serde(default)]
+    pub details: Option<serde_json::Value>,
+
+    /// An ID identifying the request in the server logs
+    pub request_id: String,
+}
+
+/// An error returned by the `TaskClient`. Error replies from the server are
+/// mapped back to the error that caused them where possible.
+pub enum ClientError {
+    /// The request could not be sent, or the reply could not be read
+    Http(String),
+
+    /// A request or reply body could not be serialized or deserialized
+    Serialization(String),
+
+    TaskNotFound,
+    UserNotFound,
+    WorkspaceNotFound,
+    WebhookNotFound,
+    WorkspaceExists,
+    WorkspaceActive,
+    InvalidName,
+    Unauthorized,
+    Forbidden,
+    SchedulerDisabled,
+    PreconditionFailed,
+
+    /// A change could not be undone or redone
+    History(String),
+
+    /// The request contained invalid values
+    Validation(Vec<FieldError>),
+
+    /// Any other error reply, along with its status code
+    Response { status: u16, error: ErrorResponse },
+}
+
+impl ClientError {
+    /// Maps an error reply from the server to the error it describes.
+    pub fn from_response(status: u16, error: ErrorResponse) -> Self {
+        match error.code.as_str() {
+            "task_not_found" => Self::TaskNotFound,
+            "user_not_found" => Self::UserNotFound,
+            "workspace_not_found" => Self::WorkspaceNotFound,
+            "webhook_not_found" => Self::WebhookNotFound,
+            "workspace_exists" => Self::WorkspaceExists,
+            "workspace_active" => Self::WorkspaceActive,
+            "invalid_name" => Self::InvalidName,
+            "unauthorized" => Self::Unauthorized,
+            "forbidden" => Self::Forbidden,
+            "scheduler_disabled" => Self::SchedulerDisabled,
+            "precondition_failed" => Self::PreconditionFailed,
+            "history_error" => Self::History(error.message),
+            "validation_error" => Self::Validation(
+                error
+                    .details
+                    .and_then(|details| serde_json::from_value(details).ok())
+                    .unwrap_or_default(),
+            ),
+            _ => Self::Response { status, error },
+        }
+    }
+}
+
+impl Display for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Http(e) | Self::Serialization(e) | Self::History(e) => write!(f, "{e}"),
+            Self::TaskNotFound => Display::fmt(&TaskNotFound, f),
+            Self::UserNotFound => Display::fmt(&UserNotFound, f),
+            Self::WorkspaceNotFound => Display::fmt(&WorkspaceNotFound, f),
+            Self::WebhookNotFound => Display::fmt(&WebhookNotFound, f),
+            Self::WorkspaceExists => Display::fmt(&WorkspaceExists, f),
+            Self::WorkspaceActive => Display::fmt(&WorkspaceActive, f),
+            Self::InvalidName => Display::fmt(&InvalidName, f),
+            Self::Unauthorized => Display::fmt(&Unauthorized, f),
+            Self::Forbidden => Display::fmt(&Forbidden, f),
+            Self::SchedulerDisabled => Display::fmt(&SchedulerDisabled, f),
+            Self::PreconditionFailed => Display::fmt(&PreconditionFailed, f),
+            Self::Validation(fields) => Display::fmt(&ValidationError(fields.clone()), f),
+            Self::Response { status, error } => write!(f, "{status}: {}", error.message),
+        }
+    }
+}
+
+impl Debug for ClientError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        Display::fmt(self, f)
+    }
+}
+
+impl Error for ClientError {}
@@ -0,0 +1,206 @@
+use crate::registry::{DEFAULT_USER, DEFAULT_WORKSPACE};
+use crate::scheduler::Drift;
+use crate::{ActiveTask, Task};
+use futures_util::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use tokio::sync::broadcast;
+use tokio::sync::broadcast::error::RecvError;
+
+/// The number of events a subscriber may fall behind before it starts missing
+/// them.
+const CAPACITY: usize = 256;
+
+/// An `Event` describes a change to the task queue or the scheduler. Events
+/// are published by the server and scheduler, and forwarded to any connected
+/// clients.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", content = "data")]
+pub enum Event {
+    TaskAdded(Task),
+    TaskUpdated(Task),
+    TaskDeleted(usize),
+    TaskCompleted(Task),
+    CompletedTaskDeleted(usize),
+    DeadlineApproaching { task: Task, minutes: usize },
+    DeadlineMissed(Task),
+    ActiveChanged(Option<ActiveTask>),
+    SchedulerEnabled,
+    SchedulerDisabled,
+    PriorityChanged(String),
+    WorkspaceChanged(String),
+    ClockDrift(Drift),
+}
+
+impl Event {
+    /// Returns the name of the event, used as the SSE event type.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::TaskAdded(_) => "task_added",
+            Event::TaskUpdated(_) => "task_updated",
+            Event::TaskDeleted(_) => "task_deleted",
+            Event::TaskCompleted(_) => "task_completed",
+            Event::CompletedTaskDeleted(_) => "completed_task_deleted",
+            Event::DeadlineApproaching { .. } => "deadline_approaching",
+            Event::DeadlineMissed(_) => "deadline_missed",
+            Event::ActiveChanged(_) => "active_changed",
+            Event::SchedulerEnabled => "scheduler_enabled",
+            Event::SchedulerDisabled => "scheduler_disabled",
+            Event::PriorityChanged(_) => "priority_changed",
+            Event::WorkspaceChanged(_) => "workspace_changed",
+            Event::ClockDrift(_) => "clock_drift",
+        }
+    }
+}
+
+/// An `Event` along with the user and workspace it happened in. Streams and
+/// webhooks send the workspace alongside the event, e.g.
+/// `{"workspace": "work", "type": "TaskDeleted", "data": 3}`, since task IDs
+/// are only unique within a workspace.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TaggedEvent {
+    #[serde(skip)]
+    pub user: String,
+    pub workspace: String,
+    #[serde(flatten)]
+    pub event: Event,
+}
+
+/// An `EventBus` broadcasts `Event`s to every subscriber. Cloning an
+/// `EventBus` yields a handle to the same bus, so the server and scheduler
+/// should be given clones of a single bus.
+///
+/// Every event belongs to a user's workspace. A bus returned by `scoped()`
+/// tags the events it publishes with that user and workspace, and only
+/// streams that user's events, from every workspace.
+#[derive(Clone)]
+pub struct EventBus {
+    sender: broadcast::Sender<TaggedEvent>,
+    scope: Option<(String, String)>,
+}
+
+impl EventBus {
+    /// Creates a new `EventBus` with no subscribers.
+    pub fn new() -> Self {
+        let (sender, _) = broadcast::channel(CAPACITY);
+        Self {
+            sender,
+            scope: None,
+        }
+    }
+
+    /// Returns a handle to the same bus that is scoped to the given user and
+    /// workspace.
+    pub fn scoped(&self, user: &str, workspace: &str) -> Self {
+        Self {
+            sender: self.sender.clone(),
+            scope: Some((user.to_string(), workspace.to_string())),
+        }
+    }
+
+    /// Publishes an event to every current subscriber. Events published while
+    /// nobody is subscribed are dropped. Events published on an unscoped bus
+    /// belong to the default user's default workspace.
+    pub fn publish(&self, event: Event) {
+        let (user, workspace) = match &self.scope {
+            Some((user, workspace)) => (user.as_str(), workspace.as_str()),
+            None => (DEFAULT_USER, DEFAULT_WORKSPACE),
+        };
+        let _ = self.sender.send(TaggedEvent {
+            user: user.to_string(),
+            workspace: workspace.to_string(),
+            event,
+        });
+    }
+
+    /// Subscribes to the bus. Only events published after subscribing will
+    /// be received.
+    pub fn subscribe(&self) -> broadcast::Receiver<TaggedEvent> {
+        self.sender.subscribe()
+    }
+
+    /// Returns the number of current subscribers, across every scope.
+    pub fn subscribers(&self) -> usize {
+        self.sender.receiver_count()
+    }
+
+    /// Subscribes to the bus and returns the events as a stream, along with
+    /// the user and workspace each event belongs to. If the bus is scoped,
+    /// only that user's events are returned. If the subscriber falls too far
+    /// behind, the missed events are skipped.
+    pub fn stream_tagged(&self) -> impl Stream<Item = TaggedEvent> + Send + 'static {
+        let scope = self.scope.clone().map(|(user, _)| user);
+
+        futures_util::stream::unfold(self.subscribe(), move |mut receiver| {
+            let scope = scope.clone();
+            async move {
+                loop {
+                    match receiver.recv().await {
+                        Ok(tagged) => {
+                            if scope.as_ref().is_none_or(|s| *s == tagged.user) {
+                                return Some((tagged, receiver));
+                            }
+                        }
+                        Err(RecvError::Lagged(n)) => {
+                            piglog::error!("Event subscriber lagged behind, {n} events were dropped");
+                        }
+                        Err(RecvError::Closed) => return None,
+                    }
+                }
+            }
+        })
+    }
+
+    /// Same as `stream_tagged()`, but without the users and workspaces.
+    pub fn stream(&self) -> impl Stream<Item = Event> + Send + 'static {
+        self.stream_tagged().map(|tagged| tagged.event)
+    }
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_stream() {
+        let bus = EventBus::new();
+        let mut stream = Box::pin(bus.stream());
+
+        bus.publish(Event::TaskDeleted(3));
+        bus.publish(Event::SchedulerEnabled);
+
+        assert!(matches!(stream.next().await, Some(Event::TaskDeleted(3))));
+        assert!(matches!(stream.next().await, Some(Event::SchedulerEnabled)));
+    }
+
+    #[tokio::test]
+    async fn test_scoped() {
+        let bus = EventBus::new();
+        let alice = bus.scoped("alice", "work");
+        let mut all = Box::pin(bus.stream_tagged());
+        let mut stream = Box::pin(alice.stream_tagged());
+
+        bus.scoped("bob", "work").publish(Event::TaskDeleted(1));
+        alice.publish(Event::TaskDeleted(2));
+        bus.scoped("alice", "chores").publish(Event::TaskDeleted(2));
+
+        // a user's stream carries the events of every one of their
+        // workspaces, each tagged with its workspace
+        let event = stream.next().await.unwrap();
+        assert!(matches!(event.event, Event::TaskDeleted(2)) && event.workspace == "work");
+        let event = stream.next().await.unwrap();
+        assert!(matches!(event.event, Event::TaskDeleted(2)) && event.workspace == "chores");
+        assert_eq!(
+            serde_json::to_value(&event).unwrap(),
+            serde_json::json!({ "workspace": "chores", "type": "TaskDeleted", "data": 2 })
+        );
+
+        let event = all.next().await.unwrap();
+        assert!(matches!(event.event, Event::TaskDeleted(1)) && event.user == "bob");
+    }
+}
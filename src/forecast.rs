@@ -0,0 +1,206 @@
+use crate::{Task, TaskKind, TaskQueue};
+use chrono::{Duration, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+/// A `Slot` is a block of time in a forecast during which a single task is
+/// expected to be worked on.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Slot {
+    pub id: usize,
+    pub start: NaiveDateTime,
+    pub end: NaiveDateTime,
+}
+
+impl TaskQueue {
+    /// Predicts the order in which the tasks in the queue will be worked on,
+    /// starting at `from`. Flexible tasks are repeatedly selected by the queue
+    /// priority and run until they are finished or until a fixed task's
+    /// window opens, since that time is unavailable for flexible work.
+    ///
+    /// Tasks on hold at `from` are left out of the forecast entirely. This is
+    /// only an estimate; priorities that depend on the current time may make
+    /// different decisions once the time actually comes.
+    pub fn forecast(&self, from: NaiveDateTime) -> Vec<Slot> {
+        self.forecast_with_switch_cost(from, Duration::zero())
+    }
+
+    /// Same as `forecast()`, but models the cost of a context switch: every
+    /// time work moves to a different flexible task, `switch_cost` passes
+    /// before any progress is made on it.
+    pub fn forecast_with_switch_cost(
+        &self,
+        from: NaiveDateTime,
+        switch_cost: Duration,
+    ) -> Vec<Slot> {
+        let mut windows: Vec<(usize, NaiveDateTime, NaiveDateTime)> = self
+            .tasks
+            .iter()
+            .filter(|t| !t.is_held_at(from))
+            .filter_map(|t| match t.kind {
+                TaskKind::Fixed { start, end } if end > from && start < end => {
+                    Some((t.id, start, end))
+                }
+                _ => None,
+            })
+            .collect();
+        windows.sort_by_key(|(_, start, _)| *start);
+        windows.reverse();
+
+        let mut flexible: Vec<Task> = self
+            .tasks
+            .iter()
+            .filter(|t| t.kind == TaskKind::Flexible && t.duration > Duration::zero())
+            .filter(|t| !t.is_held_at(from))
+            .cloned()
+            .collect();
+
+        let mut slots: Vec<Slot> = Vec::new();
+        let mut now = from;
+        let mut switched_to = None;
+
+        loop {
+            // a fixed window that has already opened takes the time slot
+            if let Some(&(id, start, end)) = windows.last() {
+                if start <= now {
+                    windows.pop();
+                    slots.push(Slot {
+                        id,
+                        start: start.max(now),
+                        end,
+                    });
+                    now = now.max(end);
+                    switched_to = None;
+                    continue;
+                }
+            }
+
+            let Some(task) = self.priority.select(&flexible) else {
+                break;
+            };
+
+            // pay for the context switch before starting on a new task
+            let previous = slots.last().map(|s| s.id);
+            if switch_cost > Duration::zero()
+                && previous.is_some_and(|id| id != task.id)
+                && switched_to != Some(task.id)
+            {
+                switched_to = Some(task.id);
+                now += switch_cost;
+                continue;
+            }
+            switched_to = None;
+
+            let mut end = now + task.duration;
+            if let Some(&(_, start, _)) = windows.last() {
+                end = end.min(start);
+            }
+
+            slots.push(Slot {
+                id: task.id,
+                start: now,
+                end,
+            });
+
+            let worked = end - now;
+            if let Some(i) = flexible.iter().position(|t| t.id == task.id) {
+                flexible[i].duration -= worked;
+                if flexible[i].duration <= Duration::zero() {
+                    flexible.remove(i);
+                }
+            }
+
+            now = end;
+        }
+
+        // any remaining appointments still happen after the flexible work is
+        // done
+        while let Some((id, start, end)) = windows.pop() {
+            slots.push(Slot {
+                id,
+                start: start.max(now),
+                end,
+            });
+            now = now.max(end);
+        }
+
+        slots
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::priority::FIFO;
+    use crate::PriorityLevel;
+
+    #[test]
+    fn test_forecast_splits_around_fixed() {
+        let mut queue = TaskQueue::with_priority(FIFO);
+        let now = chrono::Local::now().naive_local();
+
+        queue.add(Task::new(
+            1,
+            "Flexible".to_string(),
+            now + Duration::hours(8),
+            Duration::hours(3),
+            PriorityLevel::Normal,
+        ));
+        queue.add(
+            Task::new(
+                2,
+                "Meeting".to_string(),
+                now + Duration::hours(2),
+                Duration::hours(1),
+                PriorityLevel::Normal,
+            )
+            .with_kind(TaskKind::Fixed {
+                start: now + Duration::hours(1),
+                end: now + Duration::hours(2),
+            }),
+        );
+
+        let slots = queue.forecast(now);
+        assert_eq!(
+            slots,
+            vec![
+                Slot {
+                    id: 1,
+                    start: now,
+                    end: now + Duration::hours(1)
+                },
+                Slot {
+                    id: 2,
+                    start: now + Duration::hours(1),
+                    end: now + Duration::hours(2)
+                },
+                Slot {
+                    id: 1,
+                    start: now + Duration::hours(2),
+                    end: now + Duration::hours(4)
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_forecast_switch_cost() {
+        let mut queue = TaskQueue::with_priority(FIFO);
+        let now = chrono::Local::now().naive_local();
+
+        for i in 1..=2 {
+            queue.add(Task::new(
+                i,
+                format!("Task {i}"),
+                now + Duration::hours(8),
+                Duration::hours(1),
+                PriorityLevel::Normal,
+            ));
+        }
+
+        let slots = queue.forecast_with_switch_cost(now, Duration::minutes(10));
+        assert_eq!(slots.len(), 2);
+        assert_eq!(slots[0].end, now + Duration::hours(1));
+        assert_eq!(slots[1].start, now + Duration::minutes(70));
+        assert_eq!(slots[1].end, now + Duration::minutes(130));
+    }
+}
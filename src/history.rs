@@ -0,0 +1,412 @@
+use crate::error::HistoryError;
+use crate::priority::Priority;
+use crate::query::TaskStatus;
+use crate::{Task, TaskQueue, UpdateTask};
+use chrono::NaiveDateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+
+/// The number of changes kept in the history. Older changes can no longer be
+/// undone.
+pub const HISTORY_LIMIT: usize = 100;
+
+/// A reversible change to a `TaskQueue`, with everything needed to undo and
+/// redo it.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Change {
+    /// A task was added
+    Add { task: Task },
+
+    /// A task was updated. `before` holds the previous values of only the
+    /// fields that changed, and `after` their new values.
+    Update {
+        before: UpdateTask,
+        after: UpdateTask,
+    },
+
+    /// A task was deleted
+    Delete { task: Task },
+
+    /// A task was completed, and is now `completed` in the completed list
+    Complete { task: Task, completed: Task },
+
+    /// The queue priority was changed
+    Priority {
+        before: Box<dyn Priority>,
+        after: Box<dyn Priority>,
+    },
+
+    /// A batch was committed, making these changes in order. They are undone
+    /// and redone together.
+    Batch { changes: Vec<Change> },
+}
+
+/// A change along with when it was made.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Entry {
+    pub at: NaiveDateTime,
+    #[serde(flatten)]
+    pub change: Change,
+}
+
+/// The changes that can be undone, and those that were undone and can be
+/// redone. Both lists are ordered oldest first.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct History {
+    pub undo: VecDeque<Entry>,
+    pub redo: Vec<Entry>,
+
+    /// The changes made so far by the batch being applied, if any
+    #[serde(skip)]
+    batch: Option<Vec<Change>>,
+}
+
+impl TaskQueue {
+    /// Returns the queue's history.
+    pub fn history(&self) -> &History {
+        &self.history
+    }
+
+    /// Records a change in the history. Making a new change discards the
+    /// changes that were undone, as they can no longer be redone. While a
+    /// batch is being applied, its changes are collected instead, to be
+    /// recorded as one.
+    pub(crate) fn record(&mut self, change: Change) {
+        if let Some(batch) = &mut self.history.batch {
+            batch.push(change);
+            return;
+        }
+
+        self.history.redo.clear();
+        self.history.undo.push_back(Entry {
+            at: chrono::Local::now().naive_local(),
+            change,
+        });
+        if self.history.undo.len() > HISTORY_LIMIT {
+            self.history.undo.pop_front();
+        }
+    }
+
+    /// Reverts the most recent change, and returns it with the tasks as they
+    /// were restored, which may have been given new IDs. If there is nothing
+    /// to undo, a `HistoryError` is returned. If the change no longer applies
+    /// because the task has since been removed, the change is discarded so
+    /// that the changes before it can still be undone, and a `HistoryError`
+    /// reports it.
+    pub fn undo(&mut self) -> Result<Entry, HistoryError> {
+        let entry = self
+            .history
+            .undo
+            .pop_back()
+            .ok_or(HistoryError("Nothing to undo".to_string()))?;
+
+        let entry = Entry {
+            at: entry.at,
+            change: self.revert(&entry.change).map_err(discarded)?,
+        };
+        self.history.redo.push(entry.clone());
+        Ok(entry)
+    }
+
+    /// Reapplies the most recently undone change, and returns it with the
+    /// tasks as they were reapplied. If there is nothing to redo, a
+    /// `HistoryError` is returned. If the change no longer applies, it is
+    /// discarded, and a `HistoryError` reports it.
+    pub fn redo(&mut self) -> Result<Entry, HistoryError> {
+        let entry = self
+            .history
+            .redo
+            .pop()
+            .ok_or(HistoryError("Nothing to redo".to_string()))?;
+
+        let entry = Entry {
+            at: entry.at,
+            change: self.reapply(&entry.change).map_err(discarded)?,
+        };
+        self.history.undo.push_back(entry.clone());
+        Ok(entry)
+    }
+
+    /// Drops every change involving the task with the given ID from the
+    /// history, e.g. because the task was moved to another queue. A batch is
+    /// dropped as a whole, since its changes are undone together.
+    pub(crate) fn forget(&mut self, id: usize) {
+        self.history.undo.retain(|e| !e.change.involves(id));
+        self.history.redo.retain(|e| !e.change.involves(id));
+    }
+
+    /// Starts collecting the changes of a batch instead of recording them.
+    pub(crate) fn begin_batch(&mut self) {
+        self.history.batch = Some(Vec::new());
+    }
+
+    /// Stops collecting the changes of a batch, and records them as a single
+    /// change.
+    pub(crate) fn end_batch(&mut self) {
+        let changes = self.history.batch.take().unwrap_or_default();
+        if !changes.is_empty() {
+            self.record(Change::Batch { changes });
+        }
+    }
+
+    /// Reverts a change, and returns it with the tasks as they are now. A
+    /// restored task may have been given a new ID, which the change has to
+    /// refer to from then on.
+    fn revert(&mut self, change: &Change) -> Result<Change, HistoryError> {
+        let change = match change {
+            Change::Add { task } => Change::Add {
+                task: self.take(task.id).map_err(|_| gone(task.id))?,
+            },
+            Change::Update { before, after } => {
+                self.patch(before).map_err(|_| gone(before.id))?;
+                Change::Update {
+                    before: before.clone(),
+                    after: after.clone(),
+                }
+            }
+            Change::Delete { task } => Change::Delete {
+                task: self
+                    .restore_trashed(TaskStatus::Open, task.id)
+                    .map_err(|_| gone(task.id))?,
+            },
+            Change::Complete { task, completed } => {
+                let completed = self
+                    .take_completed(completed.id)
+                    .map_err(|_| gone(completed.id))?;
+                let id = self.restore(task.clone());
+                Change::Complete {
+                    task: self.get(id).cloned().ok_or_else(|| gone(id))?,
+                    completed,
+                }
+            }
+            Change::Priority { before, after } => {
+                self.priority_revision = self.touch();
+                self.priority = before.clone();
+                Change::Priority {
+                    before: before.clone(),
+                    after: after.clone(),
+                }
+            }
+            Change::Batch { changes } => {
+                let snapshot = self.clone();
+                let mut reverted = Vec::with_capacity(changes.len());
+                for change in changes.iter().rev() {
+                    match self.revert(change) {
+                        Ok(change) => reverted.push(change),
+                        Err(e) => {
+                            *self = snapshot;
+                            return Err(e);
+                        }
+                    }
+                }
+                reverted.reverse();
+                Change::Batch { changes: reverted }
+            }
+        };
+        Ok(change)
+    }
+
+    /// Reapplies a change, and returns it with the tasks as they are now.
+    fn reapply(&mut self, change: &Change) -> Result<Change, HistoryError> {
+        let change = match change {
+            Change::Add { task } => {
+                let id = self.restore(task.clone());
+                Change::Add {
+                    task: self.get(id).cloned().ok_or_else(|| gone(id))?,
+                }
+            }
+            Change::Update { before, after } => {
+                self.patch(after).map_err(|_| gone(after.id))?;
+                Change::Update {
+                    before: before.clone(),
+                    after: after.clone(),
+                }
+            }
+            Change::Delete { task } => {
+                let task = self.take(task.id).map_err(|_| gone(task.id))?;
+                self.discard(TaskStatus::Open, task.clone());
+                Change::Delete { task }
+            }
+            Change::Complete { task, completed } => {
+                let task = self.take(task.id).map_err(|_| gone(task.id))?;
+                let mut completed = completed.clone();
+                if self.get_completed(completed.id).is_some() {
+                    completed.id = self.new_id_completed();
+                }
+                self.add_completed(completed);
+                Change::Complete {
+                    completed: self
+                        .completed
+                        .last()
+                        .cloned()
+                        .ok_or_else(|| gone(task.id))?,
+                    task,
+                }
+            }
+            Change::Priority { before, after } => {
+                self.priority_revision = self.touch();
+                self.priority = after.clone();
+                Change::Priority {
+                    before: before.clone(),
+                    after: after.clone(),
+                }
+            }
+            Change::Batch { changes } => {
+                let snapshot = self.clone();
+                let mut reapplied = Vec::with_capacity(changes.len());
+                for change in changes {
+                    match self.reapply(change) {
+                        Ok(change) => reapplied.push(change),
+                        Err(e) => {
+                            *self = snapshot;
+                            return Err(e);
+                        }
+                    }
+                }
+                Change::Batch { changes: reapplied }
+            }
+        };
+        Ok(change)
+    }
+}
+
+impl Change {
+    /// Returns `true` if undoing or redoing the change sets the priority.
+    pub fn sets_priority(&self) -> bool {
+        match self {
+            Change::Priority { .. } => true,
+            Change::Batch { changes } => changes.iter().any(Change::sets_priority),
+            _ => false,
+        }
+    }
+
+    /// Returns `true` if the change involves the open task with the given ID.
+    fn involves(&self, id: usize) -> bool {
+        match self {
+            Change::Add { task } | Change::Delete { task } | Change::Complete { task, .. } => {
+                task.id == id
+            }
+            Change::Update { before, .. } => before.id == id,
+            Change::Priority { .. } => false,
+            Change::Batch { changes } => changes.iter().any(|c| c.involves(id)),
+        }
+    }
+}
+
+fn gone(id: usize) -> HistoryError {
+    HistoryError(format!("Task {id} no longer exists"))
+}
+
+fn discarded(e: HistoryError) -> HistoryError {
+    HistoryError(format!("{e}, so the change was discarded"))
+}
+
+#[cfg(test)]
+mod test {
+    use super::Change;
+    use crate::test::task;
+    use crate::{TaskQueue, UpdateTask};
+    use chrono::Duration;
+
+    #[test]
+    fn test_undo_redo() {
+        let mut queue = TaskQueue::new();
+        queue.add(task(1));
+        queue.add(task(2));
+        let mut update = UpdateTask::new(1);
+        update.title = Some("Renamed".to_string());
+        queue.update(update).unwrap();
+        queue.delete(2).unwrap();
+        queue.complete(1).unwrap();
+        assert_eq!(queue.history().undo.len(), 5);
+
+        // undo the completion, the deletion and the update
+        queue.undo().unwrap();
+        assert!(queue.get_completed(1).is_none());
+        queue.undo().unwrap();
+        assert_eq!(queue.get(2).unwrap().title, "Task 2");
+        // time charged to the task since the update is kept
+        queue.get_mut(1).unwrap().duration = Duration::hours(2);
+        queue.undo().unwrap();
+        assert_eq!(queue.get(1).unwrap().title, "Task 1");
+        assert_eq!(queue.get(1).unwrap().duration, Duration::hours(2));
+
+        queue.redo().unwrap();
+        assert_eq!(queue.get(1).unwrap().title, "Renamed");
+        assert_eq!(queue.history().redo.len(), 2);
+
+        // a new change discards what can be redone
+        queue.add(task(3));
+        assert!(queue.redo().is_err());
+
+        while queue.undo().is_ok() {}
+        assert_eq!(queue.iter().count(), 0);
+        assert!(queue.history().undo.is_empty());
+    }
+
+    #[test]
+    fn test_stale_changes() {
+        let mut queue = TaskQueue::new();
+        queue.add(task(1));
+        queue.add(task(2));
+        let mut update = UpdateTask::new(2);
+        update.title = Some("Renamed".to_string());
+        queue.update(update).unwrap();
+
+        // the task is moved away without being recorded, e.g. to another
+        // workspace
+        queue.take(2).unwrap();
+
+        // the stale changes are discarded rather than blocking what came
+        // before them
+        assert!(queue.undo().is_err());
+        assert_eq!(queue.history().undo.len(), 2);
+        assert!(queue.undo().is_err());
+        queue.undo().unwrap();
+        assert_eq!(queue.iter().count(), 0);
+        assert!(queue.history().undo.is_empty());
+    }
+
+    #[test]
+    fn test_renumbered() {
+        let mut queue = TaskQueue::new();
+        queue.add(task(1));
+        queue.complete(1).unwrap();
+        // another task takes over the ID without being recorded
+        assert_eq!(queue.adopt(task(5)), 1);
+
+        // the task is restored under a new ID, which the entry reports, and
+        // redoing the completion completes it rather than the other task
+        let entry = queue.undo().unwrap();
+        assert!(matches!(entry.change, Change::Complete { task, .. } if task.id == 2));
+        assert!(queue.get(2).is_some());
+        queue.redo().unwrap();
+        assert!(queue.get(1).is_some() && queue.get(2).is_none());
+    }
+
+    #[test]
+    fn test_forget() {
+        let mut queue = TaskQueue::new();
+        queue.add(task(1));
+        queue.add(task(2));
+        let mut update = UpdateTask::new(2);
+        update.title = Some("Renamed".to_string());
+        queue.update(update).unwrap();
+        queue.begin_batch();
+        queue.delete(1).unwrap();
+        queue.complete(2).unwrap();
+        queue.end_batch();
+        queue.undo().unwrap();
+
+        // every change to task 2 goes along with it, including the batch
+        // that also deleted task 1
+        queue.take(2).unwrap();
+        queue.forget(2);
+        assert_eq!(queue.history().undo.len(), 1);
+        assert!(queue.history().redo.is_empty());
+
+        queue.undo().unwrap();
+        assert_eq!(queue.iter().count(), 0);
+    }
+}
@@ -1,15 +1,37 @@
 use chrono::{Duration, NaiveDateTime};
+use history::{Change, History};
 use priority::{Deadline, Priority};
+use query::TaskStatus;
+use scheduler::Drift;
+use search::SearchIndex;
 use serde::{Deserialize, Serialize};
 use std::fmt::Display;
 use std::str::FromStr;
 use std::sync::{Arc, Mutex};
+use trash::Trashed;
+use validation::Rules;
+use webhooks::Webhook;
 
+pub mod auth;
+pub mod batch;
+#[cfg(feature = "client")]
+pub mod client;
 pub mod error;
+pub mod events;
+pub mod forecast;
+pub mod history;
+pub mod notify;
+pub mod openapi;
 pub mod priority;
+pub mod query;
+pub mod registry;
 pub mod scheduler;
+pub mod search;
 pub mod server;
+pub mod trash;
+pub mod validation;
 pub mod vars;
+pub mod webhooks;
 
 #[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord)]
 pub enum PriorityLevel {
@@ -48,8 +70,101 @@ impl FromStr for PriorityLevel {
     }
 }
 
+/// Describes how a task is scheduled. `Flexible` tasks are selected by the
+/// queue priority whenever it sees fit, while `Fixed` tasks (meetings,
+/// appointments, etc.) occupy a set window of time and preempt the queue while
+/// that window is open.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum TaskKind {
+    #[default]
+    Flexible,
+    Fixed {
+        start: NaiveDateTime,
+        end: NaiveDateTime,
+    },
+}
+
+impl TaskKind {
+    /// Returns `true` if this is a fixed task whose window contains `now`.
+    pub fn is_active_at(&self, now: NaiveDateTime) -> bool {
+        match self {
+            TaskKind::Flexible => false,
+            TaskKind::Fixed { start, end } => *start <= now && now < *end,
+        }
+    }
+}
+
+/// A `Hold` keeps a task in the queue without allowing it to be scheduled,
+/// e.g. while waiting on someone else. A hold with no `until` timestamp lasts
+/// until it is manually released.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Hold {
+    pub reason: String,
+    pub until: Option<NaiveDateTime>,
+}
+
+impl Hold {
+    /// Creates a new `Hold` with the given reason and optional expiration.
+    pub fn new(reason: String, until: Option<NaiveDateTime>) -> Self {
+        Self { reason, until }
+    }
+
+    /// Returns `true` if the hold has expired at the given time.
+    pub fn is_expired_at(&self, now: NaiveDateTime) -> bool {
+        self.until.is_some_and(|until| until <= now)
+    }
+}
+
+/// A `Pin` forces the task with the given ID to be the active task, bypassing
+/// the queue priority. A pin with no `until` timestamp lasts until the task is
+/// unpinned.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Pin {
+    pub id: usize,
+    pub until: Option<NaiveDateTime>,
+}
+
+impl Pin {
+    /// Creates a new `Pin` from an existing `NaivePin` and a task ID.
+    pub fn from_naive(pin: NaivePin, id: usize) -> Self {
+        Self {
+            id,
+            until: pin.until,
+        }
+    }
+
+    /// Returns `true` if the pin has expired at the given time.
+    pub fn is_expired_at(&self, now: NaiveDateTime) -> bool {
+        self.until.is_some_and(|until| until <= now)
+    }
+}
+
+/// A `NaivePin` contains the same information as a `Pin`, but lacks a task ID.
+/// The ID is provided separately by the client in the request path.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NaivePin {
+    pub until: Option<NaiveDateTime>,
+}
+
+/// `Active` records which task the scheduler is currently working on, and when
+/// it became active.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct Active {
+    pub id: usize,
+    pub since: NaiveDateTime,
+}
+
+/// An `ActiveTask` is sent to the client when it requests the active task. It
+/// contains the full task along with the time it became active.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ActiveTask {
+    pub task: Task,
+    pub since: NaiveDateTime,
+    pub pinned: bool,
+}
+
 /// `Task` contains information about a single task, including its ID, title,
-/// deadline, duration, and priority.
+/// deadline, duration, priority, kind, and hold status.
 #[derive(Clone, Serialize, Deserialize)]
 pub struct Task {
     id: usize,
@@ -57,10 +172,22 @@ pub struct Task {
     pub deadline: NaiveDateTime,
     pub duration: Duration,
     pub priority: PriorityLevel,
+    #[serde(default)]
+    pub kind: TaskKind,
+    #[serde(default)]
+    pub hold: Option<Hold>,
+    #[serde(default)]
+    overdue: bool,
+    #[serde(default)]
+    reminded: Vec<usize>,
+    #[serde(default)]
+    revision: u64,
+    #[serde(default)]
+    completed_at: Option<NaiveDateTime>,
 }
 
 impl Task {
-    /// Creates a new `Task` with the provided information.
+    /// Creates a new flexible `Task` with the provided information.
     pub fn new(
         id: usize,
         title: String,
@@ -74,6 +201,12 @@ impl Task {
             deadline,
             duration,
             priority,
+            kind: TaskKind::Flexible,
+            hold: None,
+            overdue: false,
+            reminded: Vec::new(),
+            revision: 0,
+            completed_at: None,
         }
     }
 
@@ -85,13 +218,51 @@ impl Task {
             deadline: task.deadline,
             duration: task.duration,
             priority: task.priority,
+            kind: task.kind,
+            hold: None,
+            overdue: false,
+            reminded: Vec::new(),
+            revision: 0,
+            completed_at: None,
         }
     }
 
+    /// Sets the kind of the `Task` and returns it.
+    pub fn with_kind(mut self, kind: TaskKind) -> Self {
+        self.kind = kind;
+        self
+    }
+
     /// Returns this `Task`'s ID
     pub fn id(&self) -> usize {
         self.id
     }
+
+    /// Returns this `Task`'s revision, which changes whenever the task does.
+    /// Revisions are drawn from the revision counter of the queue, so a task
+    /// that replaces a deleted task with the same ID has a new revision.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Returns when this `Task` was completed, if it has been. Tasks completed
+    /// before completion times were recorded return `None`.
+    pub fn completed_at(&self) -> Option<NaiveDateTime> {
+        self.completed_at
+    }
+
+    /// Resets the deadline notifications for this task, so that reminders and
+    /// overdue notifications fire again. This should be called whenever the
+    /// deadline changes.
+    pub fn reset_reminders(&mut self) {
+        self.overdue = false;
+        self.reminded.clear();
+    }
+
+    /// Returns `true` if the task is on hold at the given time.
+    pub fn is_held_at(&self, now: NaiveDateTime) -> bool {
+        self.hold.as_ref().is_some_and(|h| !h.is_expired_at(now))
+    }
 }
 
 impl std::fmt::Display for Task {
@@ -104,7 +275,20 @@ impl std::fmt::Display for Task {
             self.deadline,
             self.duration.num_hours(),
             self.priority,
-        )
+        )?;
+
+        if let TaskKind::Fixed { start, end } = self.kind {
+            writeln!(f, "\tScheduled: {start} - {end}")?;
+        }
+
+        if let Some(hold) = &self.hold {
+            match hold.until {
+                Some(until) => writeln!(f, "\tOn Hold: {} (until {until})", hold.reason)?,
+                None => writeln!(f, "\tOn Hold: {}", hold.reason)?,
+            }
+        }
+
+        Ok(())
     }
 }
 
@@ -118,10 +302,12 @@ pub struct NaiveTask {
     pub deadline: NaiveDateTime,
     pub duration: Duration,
     pub priority: PriorityLevel,
+    #[serde(default)]
+    pub kind: TaskKind,
 }
 
 impl NaiveTask {
-    /// Creates a new `NaiveTask` with the provided information.
+    /// Creates a new flexible `NaiveTask` with the provided information.
     pub fn new(
         title: String,
         deadline: NaiveDateTime,
@@ -133,8 +319,22 @@ impl NaiveTask {
             deadline,
             duration,
             priority,
+            kind: TaskKind::Flexible,
         }
     }
+
+    /// Checks the task against the default validation `Rules`, e.g. before
+    /// sending it to the server. Otherwise, a `ValidationError` is returned
+    /// listing every problem.
+    pub fn validate(&self) -> Result<(), error::ValidationError> {
+        Rules::default().check_task(self, chrono::Local::now().naive_local())
+    }
+
+    /// Sets the kind of the `NaiveTask` and returns it.
+    pub fn with_kind(mut self, kind: TaskKind) -> Self {
+        self.kind = kind;
+        self
+    }
 }
 
 /// An `UpdateTask` requires an ID, and will be sent to the server to update
@@ -146,6 +346,7 @@ pub struct UpdateTask {
     pub deadline: Option<NaiveDateTime>,
     pub duration: Option<Duration>,
     pub priority: Option<PriorityLevel>,
+    pub kind: Option<TaskKind>,
 }
 
 impl UpdateTask {
@@ -158,9 +359,17 @@ impl UpdateTask {
             deadline: None,
             duration: None,
             priority: None,
+            kind: None,
         }
     }
 
+    /// Checks the fields being updated against the default validation
+    /// `Rules`, e.g. before sending the update to the server. Otherwise, a
+    /// `ValidationError` is returned listing every problem.
+    pub fn validate(&self) -> Result<(), error::ValidationError> {
+        Rules::default().check_update(self, chrono::Local::now().naive_local())
+    }
+
     /// Adds a title to the `UpdateTask` and returns it.
     pub fn with_title(mut self, title: Option<String>) -> Self {
         self.title = title;
@@ -184,6 +393,35 @@ impl UpdateTask {
         self.priority = priority;
         self
     }
+
+    /// Adds a kind to the `UpdateTask` and returns it.
+    pub fn with_kind(mut self, kind: Option<TaskKind>) -> Self {
+        self.kind = kind;
+        self
+    }
+
+    /// Creates a new `UpdateTask` from an existing `NaiveUpdate` and a task ID.
+    pub fn from_naive(update: NaiveUpdate, id: usize) -> Self {
+        Self {
+            id,
+            title: update.title,
+            deadline: update.deadline,
+            duration: update.duration,
+            priority: update.priority,
+            kind: update.kind,
+        }
+    }
+}
+
+/// A `NaiveUpdate` contains the same information as an `UpdateTask`, but lacks
+/// a task ID. The ID is provided separately by the client in the request path.
+#[derive(Clone, Default, Serialize, Deserialize)]
+pub struct NaiveUpdate {
+    pub title: Option<String>,
+    pub deadline: Option<NaiveDateTime>,
+    pub duration: Option<Duration>,
+    pub priority: Option<PriorityLevel>,
+    pub kind: Option<TaskKind>,
 }
 
 /// A `TaskQueue` is a priority queue whose priority can be changed on the fly.
@@ -194,6 +432,34 @@ pub struct TaskQueue {
     tasks: Vec<Task>,
     completed: Vec<Task>,
     priority: Box<dyn Priority>,
+    #[serde(default)]
+    pin: Option<Pin>,
+    #[serde(default)]
+    active: Option<Active>,
+    #[serde(default)]
+    webhooks: Vec<Webhook>,
+    /// Whether this queue is its user's active workspace (see `Registry`)
+    #[serde(default)]
+    selected: bool,
+    /// The search index, brought up to date with the task titles on each search
+    #[serde(skip)]
+    index: SearchIndex,
+    #[serde(default)]
+    revision: u64,
+    /// The revision at which the priority last changed
+    #[serde(default)]
+    priority_revision: u64,
+    /// The revision at which the scheduler was last enabled or disabled
+    #[serde(default)]
+    enabled_revision: u64,
+    #[serde(default)]
+    history: History,
+    #[serde(default)]
+    trash: Vec<Trashed>,
+    /// The long gaps between updates the scheduler noticed (see
+    /// `TimeAccounting`)
+    #[serde(default)]
+    drift: Vec<Drift>,
     pub enabled: bool,
 }
 
@@ -204,6 +470,17 @@ impl TaskQueue {
             tasks: Vec::new(),
             completed: Vec::new(),
             priority: Box::new(Deadline {}),
+            pin: None,
+            active: None,
+            webhooks: Vec::new(),
+            selected: false,
+            index: SearchIndex::default(),
+            revision: 0,
+            priority_revision: 0,
+            enabled_revision: 0,
+            history: History::default(),
+            trash: Vec::new(),
+            drift: Vec::new(),
             enabled: false,
         }
     }
@@ -214,6 +491,17 @@ impl TaskQueue {
             tasks: Vec::new(),
             completed: Vec::new(),
             priority: Box::new(priority),
+            pin: None,
+            active: None,
+            webhooks: Vec::new(),
+            selected: false,
+            index: SearchIndex::default(),
+            revision: 0,
+            priority_revision: 0,
+            enabled_revision: 0,
+            history: History::default(),
+            trash: Vec::new(),
+            drift: Vec::new(),
             enabled: false,
         }
     }
@@ -223,6 +511,47 @@ impl TaskQueue {
         self.priority.string()
     }
 
+    /// Replaces the queue priority.
+    pub fn set_priority(&mut self, priority: Box<dyn Priority>) {
+        self.priority_revision = self.touch();
+        let before = std::mem::replace(&mut self.priority, priority);
+        self.record(Change::Priority {
+            before,
+            after: self.priority.clone(),
+        });
+    }
+
+    /// Enables or disables the scheduler for this queue.
+    pub fn set_enabled(&mut self, enabled: bool) {
+        self.enabled_revision = self.touch();
+        self.enabled = enabled;
+    }
+
+    /// Returns the queue's revision, which changes whenever the queue or any
+    /// of its tasks do.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
+    /// Returns the revision at which the priority last changed. Unlike the
+    /// queue's revision, it isn't affected by changes to the tasks.
+    pub fn priority_revision(&self) -> u64 {
+        self.priority_revision
+    }
+
+    /// Returns the revision at which the scheduler was last enabled or
+    /// disabled. Unlike the queue's revision, it isn't affected by changes to
+    /// the tasks.
+    pub fn enabled_revision(&self) -> u64 {
+        self.enabled_revision
+    }
+
+    /// Records that the queue has changed, and returns its new revision.
+    fn touch(&mut self) -> u64 {
+        self.revision += 1;
+        self.revision
+    }
+
     /// Finds and returns the lowest unused ID.
     pub fn new_id(&self) -> usize {
         use std::collections::HashSet;
@@ -240,7 +569,7 @@ impl TaskQueue {
     }
 
     /// Returns an iterator over the contents of the queue.
-    pub fn iter(&self) -> TaskQueueIterator {
+    pub fn iter(&self) -> TaskQueueIterator<'_> {
         TaskQueueIterator {
             task_queue: self,
             index: 0,
@@ -248,7 +577,7 @@ impl TaskQueue {
     }
 
     /// Returns an iterator over the contents of the completed tasks.
-    pub fn iter_completed(&self) -> TaskQueueIteratorCompleted {
+    pub fn iter_completed(&self) -> TaskQueueIteratorCompleted<'_> {
         TaskQueueIteratorCompleted {
             task_queue: self,
             index: 0,
@@ -256,23 +585,250 @@ impl TaskQueue {
     }
 
     /// Add a new `Task` to the queue.
-    pub fn add(&mut self, task: Task) {
+    pub fn add(&mut self, mut task: Task) {
+        task.revision = self.touch();
+        self.record(Change::Add { task: task.clone() });
         self.tasks.push(task);
     }
 
     /// Add a `Task` to the completed list.
-    pub fn add_completed(&mut self, task: Task) {
+    pub fn add_completed(&mut self, mut task: Task) {
+        task.revision = self.touch();
         self.completed.push(task);
     }
 
     /// Returns the next task based on the current priority algorithm.
     pub fn select(&self) -> Option<Task> {
-        self.priority.select(&self.tasks)
+        self.select_at(chrono::Local::now().naive_local())
+    }
+
+    /// Returns the task that should be active at the given time. A pinned task
+    /// always wins, followed by a fixed task whose window contains `now`;
+    /// otherwise the queue priority selects from the flexible tasks. Tasks on
+    /// hold are never selected unless they are pinned.
+    pub fn select_at(&self, now: NaiveDateTime) -> Option<Task> {
+        if let Some(pin) = self.pin.as_ref().filter(|p| !p.is_expired_at(now)) {
+            if let Some(task) = self.tasks.iter().find(|t| t.id == pin.id) {
+                return Some(task.clone());
+            }
+        }
+
+        let mut available = self.tasks.iter().filter(|t| !t.is_held_at(now));
+
+        if let Some(task) = available.clone().find(|t| t.kind.is_active_at(now)) {
+            return Some(task.clone());
+        }
+
+        let flexible: Vec<Task> = available
+            .by_ref()
+            .filter(|t| t.kind == TaskKind::Flexible)
+            .cloned()
+            .collect();
+        self.priority.select(&flexible)
+    }
+
+    /// Like `select_at()`, but resists switching away from the active task.
+    /// A challenger only preempts the active task once it has run for at
+    /// least `min_run_time`, and, if the priority provides scores, only when
+    /// the challenger's score beats the active task's by at least `threshold`.
+    /// Pinned tasks and open fixed windows always preempt immediately, and an
+    /// active task that can no longer run is always replaced.
+    pub fn select_stable(
+        &self,
+        now: NaiveDateTime,
+        min_run_time: Duration,
+        threshold: i64,
+    ) -> Option<Task> {
+        let candidate = self.select_at(now)?;
+        let Some(active) = self.active() else {
+            return Some(candidate);
+        };
+
+        let forced = self.pin.as_ref().is_some_and(|p| p.id == candidate.id)
+            || candidate.kind.is_active_at(now);
+        let runnable = active.task.kind == TaskKind::Flexible && !active.task.is_held_at(now);
+        if active.task.id == candidate.id || forced || !runnable {
+            return Some(candidate);
+        }
+
+        if now - active.since < min_run_time {
+            return Some(active.task);
+        }
+
+        match (
+            self.priority.score(&active.task, now),
+            self.priority.score(&candidate, now),
+        ) {
+            (Some(incumbent), Some(challenger)) if incumbent - challenger < threshold => {
+                Some(active.task)
+            }
+            _ => Some(candidate),
+        }
+    }
+
+    /// Puts the task corresponding to the given ID on hold. If the task does
+    /// not exist, a `TaskNotFound` error is returned.
+    pub fn hold(&mut self, id: usize, hold: Hold) -> Result<(), error::TaskNotFound> {
+        let task = self.get_mut(id).ok_or(error::TaskNotFound)?;
+        task.hold = Some(hold);
+        Ok(())
+    }
+
+    /// Releases the hold on the task corresponding to the given ID. If the
+    /// task does not exist, a `TaskNotFound` error is returned.
+    pub fn release(&mut self, id: usize) -> Result<(), error::TaskNotFound> {
+        let task = self.get_mut(id).ok_or(error::TaskNotFound)?;
+        task.hold = None;
+        Ok(())
+    }
+
+    /// Returns the task the scheduler is currently working on, along with the
+    /// time it became active. This is maintained by the `Scheduler`, and may
+    /// differ from what `select()` would return right now.
+    pub fn active(&self) -> Option<ActiveTask> {
+        let active = self.active.as_ref()?;
+        let task = self.tasks.iter().find(|t| t.id == active.id)?;
+
+        Some(ActiveTask {
+            task: task.clone(),
+            since: active.since,
+            pinned: self.pin.as_ref().is_some_and(|p| p.id == active.id),
+        })
+    }
+
+    /// Records the task with the given ID as the active task. The activation
+    /// time is only updated if the active task actually changes. Returns
+    /// `true` if it did.
+    pub fn set_active(&mut self, id: Option<usize>, now: NaiveDateTime) -> bool {
+        if self.active.as_ref().map(|a| a.id) == id {
+            return false;
+        }
+
+        self.touch();
+        self.active = id.map(|id| Active { id, since: now });
+        true
+    }
+
+    /// Pins a task so that it is selected regardless of the queue priority,
+    /// replacing any existing pin. If the task does not exist, a
+    /// `TaskNotFound` error is returned.
+    pub fn pin(&mut self, pin: Pin) -> Result<(), error::TaskNotFound> {
+        if !self.tasks.iter().any(|t| t.id == pin.id) {
+            return Err(error::TaskNotFound);
+        }
+
+        self.touch();
+        self.pin = Some(pin);
+        Ok(())
+    }
+
+    /// Removes the current pin, if any, and returns it.
+    pub fn unpin(&mut self) -> Option<Pin> {
+        let pin = self.pin.take()?;
+        self.touch();
+        Some(pin)
+    }
+
+    /// Returns a reference to the current pin, if any.
+    pub fn pinned(&self) -> Option<&Pin> {
+        self.pin.as_ref()
+    }
+
+    /// Removes the current pin if it has expired at the given time, and
+    /// returns it.
+    pub fn expire_pin(&mut self, now: NaiveDateTime) -> Option<Pin> {
+        if self.pin.as_ref().is_some_and(|p| p.is_expired_at(now)) {
+            self.unpin()
+        } else {
+            None
+        }
+    }
+
+    /// Returns the tasks whose deadlines have passed at the given time and
+    /// have not been reported as overdue yet. Each task is only returned once
+    /// per deadline.
+    pub fn newly_overdue(&mut self, now: NaiveDateTime) -> Vec<Task> {
+        let revision = self.revision + 1;
+        let overdue: Vec<Task> = self
+            .tasks
+            .iter_mut()
+            .filter(|t| !t.overdue && t.deadline <= now)
+            .map(|t| {
+                t.overdue = true;
+                t.revision = revision;
+                t.clone()
+            })
+            .collect();
+
+        if !overdue.is_empty() {
+            self.touch();
+        }
+        overdue
+    }
+
+    /// Returns the tasks whose deadlines are within one of the given
+    /// thresholds (in minutes) at the given time, along with the threshold
+    /// that was crossed. Each threshold only fires once per task, and if
+    /// several are crossed at once, only the tightest one is returned.
+    pub fn due_reminders(&mut self, now: NaiveDateTime, thresholds: &[usize]) -> Vec<(Task, usize)> {
+        let mut reminders = Vec::new();
+        let revision = self.revision + 1;
+
+        for task in self.tasks.iter_mut().filter(|t| t.deadline > now) {
+            let remaining = task.deadline - now;
+            let crossed: Vec<usize> = thresholds
+                .iter()
+                .copied()
+                .filter(|m| remaining <= Duration::minutes(*m as i64))
+                .filter(|m| !task.reminded.contains(m))
+                .collect();
+
+            if let Some(tightest) = crossed.iter().min().copied() {
+                task.reminded.extend(crossed);
+                task.revision = revision;
+                reminders.push((task.clone(), tightest));
+            }
+        }
+
+        if !reminders.is_empty() {
+            self.touch();
+        }
+        reminders
+    }
+
+    /// Returns a copy of the queue that is safe to send to clients, with
+    /// sensitive information such as webhook secrets removed.
+    pub fn redacted(&self) -> Self {
+        let mut queue = self.clone();
+        queue.webhooks.clear();
+        queue
+    }
+
+    /// Releases every hold that has expired at the given time, and returns the
+    /// IDs of the tasks that were woken up.
+    pub fn wake(&mut self, now: NaiveDateTime) -> Vec<usize> {
+        let revision = self.revision + 1;
+        let woken: Vec<usize> = self
+            .tasks
+            .iter_mut()
+            .filter(|t| t.hold.as_ref().is_some_and(|h| h.is_expired_at(now)))
+            .map(|t| {
+                t.hold = None;
+                t.revision = revision;
+                t.id
+            })
+            .collect();
+
+        if !woken.is_empty() {
+            self.touch();
+        }
+        woken
     }
 
     /// Remove the `i`th task from the queue.
     pub fn remove(&mut self, i: usize) -> Option<Task> {
         if i < self.tasks.len() {
+            self.touch();
             Some(self.tasks.remove(i))
         } else {
             None
@@ -282,6 +838,7 @@ impl TaskQueue {
     /// Remove the `i`th task from the completed list.
     pub fn remove_completed(&mut self, i: usize) -> Option<Task> {
         if i < self.completed.len() {
+            self.touch();
             Some(self.completed.remove(i))
         } else {
             None
@@ -300,39 +857,207 @@ impl TaskQueue {
         self.completed.get(i)
     }
 
+    /// Returns a reference to the task corresponding to the given ID.
+    pub fn get(&self, id: usize) -> Option<&Task> {
+        self.tasks.iter().find(|t| t.id == id)
+    }
+
+    /// Returns a reference to the completed task corresponding to the given
+    /// ID.
+    pub fn get_completed(&self, id: usize) -> Option<&Task> {
+        self.completed.iter().find(|t| t.id == id)
+    }
+
     /// Returns a mutable reference to the task corresponding to the given ID.
     pub fn get_mut(&mut self, id: usize) -> Option<&mut Task> {
-        self.tasks.iter_mut().find(|t| t.id == id)
+        let i = self.tasks.iter().position(|t| t.id == id)?;
+        let revision = self.touch();
+        let task = &mut self.tasks[i];
+        task.revision = revision;
+        Some(task)
     }
 
     /// Returns a mutable reference to the completed task corresponding to the
     /// given ID.
     pub fn get_mut_completed(&mut self, id: usize) -> Option<&mut Task> {
-        self.completed.iter_mut().find(|t| t.id == id)
+        let i = self.completed.iter().position(|t| t.id == id)?;
+        let revision = self.touch();
+        let task = &mut self.completed[i];
+        task.revision = revision;
+        Some(task)
     }
 
-    /// Deletes the task corresponding to the given ID from the queue. If the
-    /// task does not exist, a `TaskNotFound` error is returned.
+    /// Deletes the task corresponding to the given ID from the queue, along
+    /// with its pin and active status. If the task does not exist, a
+    /// `TaskNotFound` error is returned.
     pub fn delete(&mut self, id: usize) -> Result<(), error::TaskNotFound> {
+        let task = self.take(id)?;
+        self.record(Change::Delete { task: task.clone() });
+        self.discard(TaskStatus::Open, task);
+        Ok(())
+    }
+
+    /// Same as `delete()`, but returns the deleted task and doesn't record
+    /// the deletion in the history, e.g. because the task is being moved
+    /// elsewhere.
+    pub fn take(&mut self, id: usize) -> Result<Task, error::TaskNotFound> {
         if let Some((i, _)) = self.tasks.iter().enumerate().find(|(_, t)| t.id == id) {
-            self.tasks.remove(i);
-            Ok(())
+            self.touch();
+            let task = self.tasks.remove(i);
+            if self.pin.as_ref().is_some_and(|p| p.id == id) {
+                self.pin = None;
+            }
+            if self.active.as_ref().is_some_and(|a| a.id == id) {
+                self.active = None;
+            }
+            Ok(task)
         } else {
             Err(error::TaskNotFound)
         }
     }
 
+    /// Adds a task taken from another queue, giving it a new ID in this queue.
+    /// Returns the new ID.
+    pub fn adopt(&mut self, mut task: Task) -> usize {
+        task.revision = self.touch();
+        task.id = self.new_id();
+        let id = task.id;
+        self.tasks.push(task);
+        id
+    }
+
+    /// Puts a task back in the queue under its old ID, and returns the ID. If
+    /// that ID has been reused in the meantime, the task is given a new one.
+    pub(crate) fn restore(&mut self, mut task: Task) -> usize {
+        if self.get(task.id).is_some() {
+            return self.adopt(task);
+        }
+
+        task.revision = self.touch();
+        let id = task.id;
+        self.tasks.push(task);
+        id
+    }
+
+    /// Applies the fields given in the `UpdateTask` to the task with its ID,
+    /// and returns the updated task. If the task does not exist, a
+    /// `TaskNotFound` error is returned.
+    pub fn update(&mut self, updates: UpdateTask) -> Result<&Task, error::TaskNotFound> {
+        if let Some((before, after)) = self.patch(&updates)? {
+            self.record(Change::Update { before, after });
+        }
+        self.get(updates.id).ok_or(error::TaskNotFound)
+    }
+
+    /// Applies the fields given in the `UpdateTask` to the task with its ID
+    /// without recording the change. Returns the previous and new values of
+    /// only the fields that actually changed, or `None` if none did.
+    pub(crate) fn patch(
+        &mut self,
+        updates: &UpdateTask,
+    ) -> Result<Option<(UpdateTask, UpdateTask)>, error::TaskNotFound> {
+        let revision = self.revision + 1;
+        let task = self
+            .tasks
+            .iter_mut()
+            .find(|t| t.id == updates.id)
+            .ok_or(error::TaskNotFound)?;
+        let mut before = UpdateTask::new(updates.id);
+        let mut after = UpdateTask::new(updates.id);
+        let mut changed = false;
+
+        if let Some(title) = updates.title.as_ref().filter(|t| **t != task.title) {
+            before.title = Some(std::mem::replace(&mut task.title, title.clone()));
+            after.title = Some(title.clone());
+            changed = true;
+        }
+        if let Some(deadline) = updates.deadline.filter(|d| *d != task.deadline) {
+            before.deadline = Some(std::mem::replace(&mut task.deadline, deadline));
+            after.deadline = Some(deadline);
+            task.reset_reminders();
+            changed = true;
+        }
+        if let Some(duration) = updates.duration.filter(|d| *d != task.duration) {
+            before.duration = Some(std::mem::replace(&mut task.duration, duration));
+            after.duration = Some(duration);
+            changed = true;
+        }
+        if let Some(priority) = updates.priority.filter(|p| *p != task.priority) {
+            before.priority = Some(std::mem::replace(&mut task.priority, priority));
+            after.priority = Some(priority);
+            changed = true;
+        }
+        if let Some(kind) = updates.kind.filter(|k| *k != task.kind) {
+            before.kind = Some(std::mem::replace(&mut task.kind, kind));
+            after.kind = Some(kind);
+            changed = true;
+        }
+
+        if !changed {
+            return Ok(None);
+        }
+        task.revision = revision;
+        self.touch();
+        Ok(Some((before, after)))
+    }
+
+    /// Moves the task with the given ID to the completed list, and returns it
+    /// with its new ID. If the task does not exist, a `TaskNotFound` error is
+    /// returned.
+    pub fn complete(&mut self, id: usize) -> Result<Task, error::TaskNotFound> {
+        let task = self.take(id)?;
+        let mut completed = Task::new(
+            self.new_id_completed(),
+            task.title.clone(),
+            task.deadline,
+            task.duration,
+            task.priority,
+        )
+        .with_kind(task.kind);
+        completed.completed_at = Some(chrono::Local::now().naive_local());
+        self.add_completed(completed);
+
+        let completed = self.completed.last().cloned().ok_or(error::TaskNotFound)?;
+        self.record(Change::Complete {
+            task,
+            completed: completed.clone(),
+        });
+        Ok(completed)
+    }
+
     /// Deletes the task corresponding to the given ID from the completed list.
     /// If the task does not exist, a `TaskNotFound` error is returned.
     pub fn delete_completed(&mut self, id: usize) -> Result<(), error::TaskNotFound> {
+        let task = self.take_completed(id)?;
+        self.discard(TaskStatus::Completed, task);
+        Ok(())
+    }
+
+    /// Same as `delete_completed()`, but returns the deleted task instead of
+    /// moving it to the trash.
+    pub fn take_completed(&mut self, id: usize) -> Result<Task, error::TaskNotFound> {
         if let Some((i, _)) = self.completed.iter().enumerate().find(|(_, t)| t.id == id) {
-            self.completed.remove(i);
-            Ok(())
+            self.touch();
+            Ok(self.completed.remove(i))
         } else {
             Err(error::TaskNotFound)
         }
     }
 
+    /// Removes the tasks that were completed at least `age` ago, and returns
+    /// them so they can be archived.
+    pub fn archive_completed(&mut self, now: NaiveDateTime, age: Duration) -> Vec<Task> {
+        let (archived, kept): (Vec<Task>, Vec<Task>) = self
+            .completed
+            .drain(..)
+            .partition(|t| t.completed_at.is_some_and(|at| at + age <= now));
+        self.completed = kept;
+        if !archived.is_empty() {
+            self.touch();
+        }
+        archived
+    }
+
     /// Returns `true` if there are no tasks in the queue, and `false` if there
     /// is at least one.
     pub fn is_empty(&self) -> bool {
@@ -395,10 +1120,35 @@ impl<'a> Iterator for TaskQueueIteratorCompleted<'a> {
     }
 }
 
+pub type SharedQueue = Arc<Mutex<TaskQueue>>;
+
 #[cfg(test)]
-mod test {
+pub(crate) mod test {
     use super::*;
 
+    /// Returns an hour-long task with the given ID, shared by the tests of
+    /// every module.
+    pub(crate) fn task(id: usize) -> Task {
+        Task::new(
+            id,
+            format!("Task {id}"),
+            NaiveDateTime::default(),
+            Duration::hours(1),
+            PriorityLevel::Normal,
+        )
+    }
+
+    /// Returns an hour-long `NaiveTask` with the given title, due in a day so
+    /// the server accepts it, shared by the tests of every module.
+    pub(crate) fn naive_task(title: &str) -> NaiveTask {
+        NaiveTask::new(
+            title.to_string(),
+            chrono::Local::now().naive_local() + Duration::days(1),
+            Duration::hours(1),
+            PriorityLevel::Normal,
+        )
+    }
+
     #[test]
     fn test_new_id() {
         let mut queue = TaskQueue::new();
@@ -431,6 +1181,247 @@ mod test {
             assert_eq!(queue1.new_id(), 4);
         }
     }
-}
 
-pub type SharedQueue = Arc<Mutex<TaskQueue>>;
+    #[test]
+    fn test_fixed_preempts() {
+        let mut queue = TaskQueue::with_priority(priority::FIFO);
+        let now = chrono::Local::now().naive_local();
+
+        let flexible = Task::new(
+            1,
+            "Flexible".to_string(),
+            now + Duration::hours(1),
+            Duration::hours(1),
+            PriorityLevel::Urgent,
+        );
+        let meeting = Task::new(
+            2,
+            "Meeting".to_string(),
+            now + Duration::hours(3),
+            Duration::hours(1),
+            PriorityLevel::Low,
+        )
+        .with_kind(TaskKind::Fixed {
+            start: now + Duration::hours(2),
+            end: now + Duration::hours(3),
+        });
+
+        queue.add(meeting);
+        queue.add(flexible);
+
+        // outside the window, the fixed task is invisible to the priority
+        assert_eq!(queue.select_at(now).unwrap().id, 1);
+        assert_eq!(queue.select_at(now + Duration::minutes(150)).unwrap().id, 2);
+        assert_eq!(queue.select_at(now + Duration::hours(3)).unwrap().id, 1);
+    }
+
+    #[test]
+    fn test_hold() {
+        let mut queue = TaskQueue::with_priority(priority::FIFO);
+        let now = chrono::Local::now().naive_local();
+
+        for i in 1..=2 {
+            queue.add(Task::new(
+                i,
+                format!("Task {i}"),
+                now + Duration::hours(1),
+                Duration::hours(1),
+                PriorityLevel::Normal,
+            ));
+        }
+
+        let until = now + Duration::minutes(30);
+        queue
+            .hold(1, Hold::new("Waiting on review".to_string(), Some(until)))
+            .unwrap();
+        assert_eq!(queue.select_at(now).unwrap().id, 2);
+        assert_eq!(queue.select_at(until).unwrap().id, 1);
+
+        assert!(queue.wake(now).is_empty());
+        assert_eq!(queue.wake(until), vec![1]);
+        assert!(queue.nth(0).unwrap().hold.is_none());
+
+        queue.hold(2, Hold::new("Blocked".to_string(), None)).unwrap();
+        queue.hold(1, Hold::new("Blocked".to_string(), None)).unwrap();
+        assert!(queue.select_at(now + Duration::days(365)).is_none());
+        queue.release(1).unwrap();
+        assert_eq!(queue.select_at(now).unwrap().id, 1);
+
+        assert!(queue.hold(3, Hold::new("Missing".to_string(), None)).is_err());
+    }
+
+    #[test]
+    fn test_pin() {
+        let mut queue = TaskQueue::with_priority(priority::FIFO);
+        let now = chrono::Local::now().naive_local();
+
+        for i in 1..=3 {
+            queue.add(Task::new(
+                i,
+                format!("Task {i}"),
+                now + Duration::hours(1),
+                Duration::hours(1),
+                PriorityLevel::Normal,
+            ));
+        }
+
+        let until = now + Duration::minutes(30);
+        queue.pin(Pin { id: 3, until: Some(until) }).unwrap();
+        assert_eq!(queue.select_at(now).unwrap().id, 3);
+        assert_eq!(queue.select_at(until).unwrap().id, 1);
+
+        assert!(queue.expire_pin(now).is_none());
+        assert_eq!(queue.expire_pin(until).unwrap().id, 3);
+        assert!(queue.pinned().is_none());
+
+        queue.pin(Pin { id: 2, until: None }).unwrap();
+        assert_eq!(queue.select_at(now).unwrap().id, 2);
+        queue.delete(2).unwrap();
+        assert!(queue.pinned().is_none());
+
+        assert!(queue.pin(Pin { id: 7, until: None }).is_err());
+    }
+
+    #[test]
+    fn test_set_active() {
+        let mut queue = TaskQueue::new();
+        let now = chrono::Local::now().naive_local();
+
+        for i in 1..=2 {
+            queue.add(Task::new(
+                i,
+                format!("Task {i}"),
+                now + Duration::hours(1),
+                Duration::hours(1),
+                PriorityLevel::Normal,
+            ));
+        }
+
+        assert!(queue.active().is_none());
+        assert!(queue.set_active(Some(1), now));
+        assert!(!queue.set_active(Some(1), now + Duration::minutes(5)));
+        assert_eq!(queue.active().unwrap().since, now);
+
+        assert!(queue.set_active(Some(2), now + Duration::minutes(10)));
+        assert_eq!(queue.active().unwrap().since, now + Duration::minutes(10));
+
+        queue.delete(2).unwrap();
+        assert!(queue.active().is_none());
+    }
+
+    #[test]
+    fn test_select_stable() {
+        let mut queue = TaskQueue::with_priority(priority::Shortest);
+        let now = chrono::Local::now().naive_local();
+
+        queue.add(Task::new(
+            1,
+            "Long".to_string(),
+            now + Duration::hours(8),
+            Duration::hours(2),
+            PriorityLevel::Normal,
+        ));
+        queue.set_active(Some(1), now);
+        queue.add(Task::new(
+            2,
+            "Short".to_string(),
+            now + Duration::hours(8),
+            Duration::hours(1),
+            PriorityLevel::Normal,
+        ));
+
+        let min_run_time = Duration::minutes(15);
+        assert_eq!(queue.select_stable(now, min_run_time, 0).unwrap().id, 1);
+        let later = now + min_run_time;
+        assert_eq!(queue.select_stable(later, min_run_time, 0).unwrap().id, 2);
+
+        // pins preempt regardless of the minimum run time
+        queue.pin(Pin { id: 2, until: None }).unwrap();
+        assert_eq!(queue.select_stable(now, min_run_time, 0).unwrap().id, 2);
+    }
+
+    #[test]
+    fn test_reminders() {
+        let mut queue = TaskQueue::new();
+        let now = chrono::Local::now().naive_local();
+        let thresholds = [24 * 60, 60];
+
+        queue.add(Task::new(
+            1,
+            "Task 1".to_string(),
+            now + Duration::hours(2),
+            Duration::hours(1),
+            PriorityLevel::Normal,
+        ));
+        queue.add(Task::new(
+            2,
+            "Task 2".to_string(),
+            now + Duration::minutes(30),
+            Duration::hours(1),
+            PriorityLevel::Normal,
+        ));
+
+        // task 2 has crossed both thresholds, but is only reminded once
+        let reminders = queue.due_reminders(now, &thresholds);
+        let reminders: Vec<(usize, usize)> = reminders.iter().map(|(t, m)| (t.id, *m)).collect();
+        assert_eq!(reminders, vec![(1, 24 * 60), (2, 60)]);
+        assert!(queue.due_reminders(now, &thresholds).is_empty());
+
+        let later = now + Duration::minutes(90);
+        let reminders = queue.due_reminders(later, &thresholds);
+        assert_eq!(reminders.len(), 1);
+        assert_eq!(reminders[0].0.id, 1);
+
+        let overdue = queue.newly_overdue(later);
+        assert_eq!(overdue.len(), 1);
+        assert_eq!(overdue[0].id, 2);
+        assert!(queue.newly_overdue(later).is_empty());
+
+        queue.get_mut(2).unwrap().reset_reminders();
+        assert_eq!(queue.newly_overdue(later).len(), 1);
+    }
+
+    #[test]
+    fn test_revision() {
+        let now = NaiveDateTime::default();
+        let mut queue = TaskQueue::new();
+        queue.add(Task::new(1, "Task 1".to_string(), now, Duration::hours(1), PriorityLevel::Normal));
+        queue.add(Task::new(2, "Task 2".to_string(), now, Duration::hours(1), PriorityLevel::Normal));
+        let (first, second) = (queue.get(1).unwrap().revision(), queue.get(2).unwrap().revision());
+        assert!(first < second);
+        assert_eq!(queue.revision(), second);
+
+        // modifying a task changes its revision, but not the others'
+        queue.get_mut(1).unwrap().title = "Renamed".to_string();
+        assert!(queue.get(1).unwrap().revision() > second);
+        assert_eq!(queue.get(2).unwrap().revision(), second);
+
+        // a task that reuses a deleted task's ID has a new revision
+        let revision = queue.get(2).unwrap().revision();
+        queue.delete(2).unwrap();
+        queue.add(Task::new(2, "Task 2".to_string(), now, Duration::hours(1), PriorityLevel::Normal));
+        assert_ne!(queue.get(2).unwrap().revision(), revision);
+
+        // reading doesn't change the revision, and neither does a no-op
+        let revision = queue.revision();
+        queue.get(1);
+        assert!(queue.wake(now).is_empty());
+        assert!(!queue.set_active(None, now));
+        assert_eq!(queue.revision(), revision);
+    }
+
+    #[test]
+    fn test_validate() {
+        let now = chrono::Local::now().naive_local();
+        let task = NaiveTask::new("Task".to_string(), now, Duration::hours(1), PriorityLevel::Normal);
+        assert!(task.validate().is_ok());
+
+        let task = NaiveTask::new(" ".to_string(), now, Duration::hours(-1), PriorityLevel::Normal);
+        let fields: Vec<String> = task.validate().unwrap_err().0.into_iter().map(|e| e.field).collect();
+        assert_eq!(fields, vec!["title", "duration"]);
+
+        let update = UpdateTask::new(1).with_duration(Some(Duration::minutes(-5)));
+        assert!(update.validate().is_err());
+        assert!(UpdateTask::new(1).validate().is_ok());
+    }
+}
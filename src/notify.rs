@@ -0,0 +1,135 @@
+use crate::error::NotifyError;
+use crate::Task;
+use piglog::info;
+use std::process::Command;
+
+/// A `Notification` is sent by the scheduler when a task's deadline is
+/// approaching or has passed.
+#[derive(Clone)]
+pub enum Notification {
+    /// The deadline is within the given number of minutes.
+    Approaching { task: Task, minutes: usize },
+    /// The deadline has passed.
+    Overdue(Task),
+}
+
+impl Notification {
+    /// Returns a short summary of the notification.
+    pub fn title(&self) -> String {
+        match self {
+            Notification::Approaching { task, .. } => format!("Deadline approaching: {}", task.title),
+            Notification::Overdue(task) => format!("Deadline missed: {}", task.title),
+        }
+    }
+
+    /// Returns a longer description of the notification.
+    pub fn body(&self) -> String {
+        match self {
+            Notification::Approaching { task, minutes } => {
+                let within = if minutes % 60 == 0 {
+                    format!("{} hour(s)", minutes / 60)
+                } else {
+                    format!("{minutes} minute(s)")
+                };
+                format!("Task {} is due within {within} ({})", task.id(), task.deadline)
+            }
+            Notification::Overdue(task) => {
+                format!("Task {} was due at {}", task.id(), task.deadline)
+            }
+        }
+    }
+}
+
+/// A struct implementing the `Notifier` trait can be given to a `Scheduler`
+/// to deliver deadline notifications to the user.
+pub trait Notifier: Send + Sync {
+    fn notify(&self, notification: &Notification) -> Result<(), NotifyError>;
+}
+
+/// Writes notifications to the log.
+#[derive(Clone)]
+pub struct LogNotifier;
+
+impl Notifier for LogNotifier {
+    fn notify(&self, notification: &Notification) -> Result<(), NotifyError> {
+        info!("{}: {}", notification.title(), notification.body());
+        Ok(())
+    }
+}
+
+/// Runs a command for every notification, e.g. `notify-send` to show a
+/// desktop notification. The placeholders `{title}` and `{body}` in the
+/// arguments are replaced with the notification's title and body.
+///
+/// ## Example
+/// ```rust
+/// use taskscheduler::notify::CommandNotifier;
+///
+/// let notifier = CommandNotifier::new(
+///     "notify-send",
+///     &["--app-name=tasks", "{title}", "{body}"],
+/// );
+/// ```
+#[derive(Clone)]
+pub struct CommandNotifier {
+    pub program: String,
+    pub args: Vec<String>,
+}
+
+impl CommandNotifier {
+    /// Creates a new `CommandNotifier` that runs `program` with `args`.
+    pub fn new(program: &str, args: &[&str]) -> Self {
+        Self {
+            program: program.to_string(),
+            args: args.iter().map(|a| a.to_string()).collect(),
+        }
+    }
+}
+
+impl Notifier for CommandNotifier {
+    fn notify(&self, notification: &Notification) -> Result<(), NotifyError> {
+        let (title, body) = (notification.title(), notification.body());
+        let args = self
+            .args
+            .iter()
+            .map(|a| a.replace("{title}", &title).replace("{body}", &body));
+
+        let status = Command::new(&self.program)
+            .args(args)
+            .status()
+            .map_err(|e| NotifyError(e.to_string()))?;
+
+        if status.success() {
+            Ok(())
+        } else {
+            Err(NotifyError(format!("{} exited with {status}", self.program)))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_command_notifier() {
+        let path =
+            std::env::temp_dir().join(format!("taskscheduler-notify-{}", std::process::id()));
+        let notification = Notification::Overdue(crate::test::task(1));
+
+        // the placeholders are replaced, and each argument is passed as is
+        let script = format!("printf '%s\\n' \"$1\" \"$2\" > {}", path.display());
+        let notifier = CommandNotifier::new("sh", &["-c", &script, "sh", "{title}", "{body}"]);
+        notifier.notify(&notification).unwrap();
+        let received = std::fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            received,
+            format!("{}\n{}\n", notification.title(), notification.body())
+        );
+        std::fs::remove_file(path).unwrap();
+
+        let failing = CommandNotifier::new("sh", &["-c", "exit 3"]);
+        let error = failing.notify(&notification).unwrap_err();
+        assert!(error.to_string().starts_with("sh exited with"));
+    }
+}
@@ -0,0 +1,931 @@
+use serde_json::{json, Map, Value};
+use warp::http::Method;
+
+/// A version of the API, served under its own prefix. The unversioned `/api`
+/// prefix is an alias for `V1`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Version {
+    V1,
+    V2,
+}
+
+impl Version {
+    /// Returns the endpoints of the version.
+    pub fn endpoints(self) -> &'static [Endpoint] {
+        match self {
+            Self::V1 => V1_ENDPOINTS,
+            Self::V2 => V2_ENDPOINTS,
+        }
+    }
+
+    /// Returns the prefix every path of the version is relative to.
+    pub fn prefix(self) -> &'static str {
+        match self {
+            Self::V1 => "/api/v1",
+            Self::V2 => "/api/v2",
+        }
+    }
+}
+
+/// An operation of the API. Each is served by one handler, shared by every
+/// version that has an endpoint for it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum OperationId {
+    AddTask,
+    ListTasks,
+    UpdateTask,
+    ApplyBatch,
+    GetHistory,
+    Undo,
+    Redo,
+    GetTrash,
+    EmptyTrash,
+    RestoreTask,
+    SearchTasks,
+    GetTask,
+    DeleteTask,
+    GetCompletedTask,
+    CompleteTask,
+    DeleteCompletedTask,
+    EnableScheduler,
+    DisableScheduler,
+    GetScheduler,
+    SetScheduler,
+    GetActiveTask,
+    PinTask,
+    UnpinTask,
+    GetStatus,
+    GetPriority,
+    SetPriority,
+    GetPolicy,
+    SetPolicy,
+    GetForecast,
+    GetDrift,
+    HoldTask,
+    ReleaseTask,
+    MoveTask,
+    StreamEvents,
+    StreamEventsWs,
+    AddWebhook,
+    GetWebhooks,
+    DeleteWebhook,
+    GetWorkspaces,
+    AddWorkspace,
+    GetActiveWorkspace,
+    SetActiveWorkspace,
+    DeleteWorkspace,
+    ListUsers,
+    GetOpenApi,
+}
+
+impl OperationId {
+    /// Returns the name of the operation, used as its `operationId`.
+    pub fn name(self) -> &'static str {
+        match self {
+            Self::AddTask => "addTask",
+            Self::ListTasks => "listTasks",
+            Self::UpdateTask => "updateTask",
+            Self::ApplyBatch => "applyBatch",
+            Self::GetHistory => "getHistory",
+            Self::Undo => "undo",
+            Self::Redo => "redo",
+            Self::GetTrash => "getTrash",
+            Self::EmptyTrash => "emptyTrash",
+            Self::RestoreTask => "restoreTask",
+            Self::SearchTasks => "searchTasks",
+            Self::GetTask => "getTask",
+            Self::DeleteTask => "deleteTask",
+            Self::GetCompletedTask => "getCompletedTask",
+            Self::CompleteTask => "completeTask",
+            Self::DeleteCompletedTask => "deleteCompletedTask",
+            Self::EnableScheduler => "enableScheduler",
+            Self::DisableScheduler => "disableScheduler",
+            Self::GetScheduler => "getScheduler",
+            Self::SetScheduler => "setScheduler",
+            Self::GetActiveTask => "getActiveTask",
+            Self::PinTask => "pinTask",
+            Self::UnpinTask => "unpinTask",
+            Self::GetStatus => "getStatus",
+            Self::GetPriority => "getPriority",
+            Self::SetPriority => "setPriority",
+            Self::GetPolicy => "getPolicy",
+            Self::SetPolicy => "setPolicy",
+            Self::GetForecast => "getForecast",
+            Self::GetDrift => "getDrift",
+            Self::HoldTask => "holdTask",
+            Self::ReleaseTask => "releaseTask",
+            Self::MoveTask => "moveTask",
+            Self::StreamEvents => "streamEvents",
+            Self::StreamEventsWs => "streamEventsWs",
+            Self::AddWebhook => "addWebhook",
+            Self::GetWebhooks => "getWebhooks",
+            Self::DeleteWebhook => "deleteWebhook",
+            Self::GetWorkspaces => "getWorkspaces",
+            Self::AddWorkspace => "addWorkspace",
+            Self::GetActiveWorkspace => "getActiveWorkspace",
+            Self::SetActiveWorkspace => "setActiveWorkspace",
+            Self::DeleteWorkspace => "deleteWorkspace",
+            Self::ListUsers => "listUsers",
+            Self::GetOpenApi => "getOpenApi",
+        }
+    }
+}
+
+/// An endpoint of the API, as served and as described in the OpenAPI
+/// document. Paths are relative to the prefix of their `Version`, with path
+/// parameters in braces.
+pub struct Endpoint {
+    pub method: Method,
+    pub path: &'static str,
+    pub operation: OperationId,
+    pub summary: &'static str,
+
+    /// The schema of the request body, if there is one
+    pub request: Option<&'static str>,
+
+    /// The status and schema of a successful reply
+    pub status: u16,
+    pub response: &'static str,
+
+    /// The query parameters and their types
+    pub query: &'static [(&'static str, &'static str)],
+
+    /// Whether the endpoint honors `If-Match` (`412`) or `If-None-Match`
+    /// (`304`)
+    pub if_match: bool,
+    pub if_none_match: bool,
+}
+
+impl Endpoint {
+    const fn new(
+        method: Method,
+        path: &'static str,
+        operation: OperationId,
+        summary: &'static str,
+        status: u16,
+        response: &'static str,
+    ) -> Self {
+        Self {
+            method,
+            path,
+            operation,
+            summary,
+            request: None,
+            status,
+            response,
+            query: &[],
+            if_match: false,
+            if_none_match: false,
+        }
+    }
+
+    const fn with_request(mut self, request: &'static str) -> Self {
+        self.request = Some(request);
+        self
+    }
+
+    const fn with_query(mut self, query: &'static [(&'static str, &'static str)]) -> Self {
+        self.query = query;
+        self
+    }
+
+    const fn with_if_match(mut self) -> Self {
+        self.if_match = true;
+        self
+    }
+
+    const fn with_if_none_match(mut self) -> Self {
+        self.if_none_match = true;
+        self
+    }
+}
+
+/// The query parameters of `GET /tasks` (see `TaskQuery`).
+const TASK_QUERY: &[(&str, &str)] = &[
+    ("status", "TaskStatus"),
+    ("priority", "PriorityLevel"),
+    ("from", "DateTime"),
+    ("to", "DateTime"),
+    ("q", "string"),
+    ("sort", "SortField"),
+    ("order", "SortOrder"),
+    ("limit", "integer"),
+    ("cursor", "string"),
+];
+
+/// Every endpoint served by `Server::run` under `/api/v1`. The server builds
+/// its routes from this table, pairing each operation with its handler, so
+/// adding a route means adding it here. Response schemas
+/// are either a primitive type (`string`, `integer`, `boolean`, `object`),
+/// the name of a schema in `schemas()`, or either of those followed by `[]`
+/// for a list.
+#[rustfmt::skip]
+pub const V1_ENDPOINTS: &[Endpoint] = &[
+    Endpoint::new(Method::POST, "/tasks", OperationId::AddTask, "Adds a task to the queue", 201, "string")
+        .with_request("NaiveTask"),
+    Endpoint::new(Method::GET, "/tasks", OperationId::ListTasks, "Lists one page of tasks", 200, "TaskPage")
+        .with_query(TASK_QUERY)
+        .with_if_none_match(),
+    Endpoint::new(Method::PUT, "/tasks", OperationId::UpdateTask, "Updates a task", 201, "string")
+        .with_request("UpdateTask")
+        .with_if_match(),
+    Endpoint::new(Method::POST, "/tasks/batch", OperationId::ApplyBatch, "Applies operations atomically", 200, "BatchResult")
+        .with_request("Operation[]"),
+    Endpoint::new(Method::GET, "/tasks/history", OperationId::GetHistory, "Fetches the change history", 200, "History"),
+    Endpoint::new(Method::POST, "/tasks/undo", OperationId::Undo, "Undoes the most recent change", 200, "Entry"),
+    Endpoint::new(Method::POST, "/tasks/redo", OperationId::Redo, "Redoes the most recently undone change", 200, "Entry"),
+    Endpoint::new(Method::GET, "/tasks/trash", OperationId::GetTrash, "Lists the deleted tasks", 200, "Trashed[]"),
+    Endpoint::new(Method::DELETE, "/tasks/trash", OperationId::EmptyTrash, "Permanently deletes the trash", 200, "integer"),
+    Endpoint::new(Method::PUT, "/tasks/trash/{status}/{id}", OperationId::RestoreTask, "Restores a deleted task", 200, "Task"),
+    Endpoint::new(Method::GET, "/tasks/search", OperationId::SearchTasks, "Searches task titles", 200, "SearchResults")
+        .with_query(&[("q", "string"), ("limit", "integer")]),
+    Endpoint::new(Method::GET, "/tasks/{id}", OperationId::GetTask, "Fetches a task", 200, "Task")
+        .with_if_none_match(),
+    Endpoint::new(Method::DELETE, "/tasks/{id}", OperationId::DeleteTask, "Deletes a task", 200, "string")
+        .with_if_match(),
+    Endpoint::new(Method::GET, "/tasks/complete/{id}", OperationId::GetCompletedTask, "Fetches a completed task", 200, "Task")
+        .with_if_none_match(),
+    Endpoint::new(Method::PUT, "/tasks/complete/{id}", OperationId::CompleteTask, "Marks a task as complete", 200, "string")
+        .with_if_match(),
+    Endpoint::new(Method::DELETE, "/tasks/complete/{id}", OperationId::DeleteCompletedTask, "Deletes a completed task", 200, "string"),
+    Endpoint::new(Method::POST, "/tasks/enable", OperationId::EnableScheduler, "Enables the scheduler", 200, "string"),
+    Endpoint::new(Method::POST, "/tasks/disable", OperationId::DisableScheduler, "Disables the scheduler", 200, "string"),
+    Endpoint::new(Method::GET, "/tasks/active", OperationId::GetActiveTask, "Fetches the active task", 200, "ActiveTask")
+        .with_if_none_match(),
+    Endpoint::new(Method::DELETE, "/tasks/active", OperationId::UnpinTask, "Unpins the active task", 200, "string"),
+    Endpoint::new(Method::PUT, "/tasks/active/{id}", OperationId::PinTask, "Pins a task as the active task", 200, "string")
+        .with_request("NaivePin"),
+    Endpoint::new(Method::GET, "/tasks/status", OperationId::GetStatus, "Fetches whether the scheduler is enabled", 200, "boolean")
+        .with_if_none_match(),
+    Endpoint::new(Method::GET, "/tasks/priority", OperationId::GetPriority, "Fetches the queue priority", 200, "Priority")
+        .with_if_none_match(),
+    Endpoint::new(Method::PUT, "/tasks/priority", OperationId::SetPriority, "Sets the queue priority", 201, "string")
+        .with_request("Priority")
+        .with_if_match(),
+    Endpoint::new(Method::GET, "/tasks/forecast", OperationId::GetForecast, "Predicts the schedule", 200, "Slot[]"),
+    Endpoint::new(Method::GET, "/tasks/drift", OperationId::GetDrift, "Lists long gaps between updates", 200, "Drift[]"),
+    Endpoint::new(Method::PUT, "/tasks/hold/{id}", OperationId::HoldTask, "Puts a task on hold", 200, "string")
+        .with_request("Hold"),
+    Endpoint::new(Method::DELETE, "/tasks/hold/{id}", OperationId::ReleaseTask, "Releases a task from hold", 200, "string"),
+    Endpoint::new(Method::PUT, "/tasks/move/{id}", OperationId::MoveTask, "Moves a task to another workspace", 200, "integer")
+        .with_request("string"),
+    Endpoint::new(Method::GET, "/events", OperationId::StreamEvents, "Streams events as Server-Sent Events", 200, "Event"),
+    Endpoint::new(Method::GET, "/events/ws", OperationId::StreamEventsWs, "Streams events over a WebSocket", 101, "Event"),
+    Endpoint::new(Method::POST, "/webhooks", OperationId::AddWebhook, "Registers a webhook", 201, "integer")
+        .with_request("NaiveWebhook"),
+    Endpoint::new(Method::GET, "/webhooks", OperationId::GetWebhooks, "Lists the webhooks", 200, "WebhookInfo[]"),
+    Endpoint::new(Method::DELETE, "/webhooks/{id}", OperationId::DeleteWebhook, "Deletes a webhook", 200, "string"),
+    Endpoint::new(Method::GET, "/workspaces", OperationId::GetWorkspaces, "Lists the user's workspaces", 200, "WorkspaceInfo[]"),
+    Endpoint::new(Method::POST, "/workspaces", OperationId::AddWorkspace, "Creates a workspace", 201, "string")
+        .with_request("NewWorkspace"),
+    Endpoint::new(Method::GET, "/workspaces/active", OperationId::GetActiveWorkspace, "Fetches the active workspace", 200, "string"),
+    Endpoint::new(Method::PUT, "/workspaces/active", OperationId::SetActiveWorkspace, "Sets the active workspace", 200, "string")
+        .with_request("string"),
+    Endpoint::new(Method::DELETE, "/workspaces/{workspace}", OperationId::DeleteWorkspace, "Deletes a workspace", 200, "string"),
+    Endpoint::new(Method::GET, "/users", OperationId::ListUsers, "Lists the users", 200, "string[]"),
+    Endpoint::new(Method::GET, "/openapi.json", OperationId::GetOpenApi, "Fetches this document", 200, "object"),
+];
+
+/// Every endpoint served by `Server::run` under `/api/v2`, in the same form as
+/// `V1_ENDPOINTS`.
+#[rustfmt::skip]
+pub const V2_ENDPOINTS: &[Endpoint] = &[
+    Endpoint::new(Method::POST, "/tasks", OperationId::AddTask, "Adds a task to the queue", 201, "string")
+        .with_request("NaiveTask"),
+    Endpoint::new(Method::GET, "/tasks", OperationId::ListTasks, "Lists one page of tasks", 200, "TaskPage")
+        .with_query(TASK_QUERY)
+        .with_if_none_match(),
+    Endpoint::new(Method::POST, "/tasks/batch", OperationId::ApplyBatch, "Applies operations atomically", 200, "BatchResult")
+        .with_request("Operation[]"),
+    Endpoint::new(Method::GET, "/tasks/search", OperationId::SearchTasks, "Searches task titles", 200, "SearchResults")
+        .with_query(&[("q", "string"), ("limit", "integer")]),
+    Endpoint::new(Method::GET, "/tasks/{id}", OperationId::GetTask, "Fetches a task", 200, "Task")
+        .with_if_none_match(),
+    Endpoint::new(Method::PATCH, "/tasks/{id}", OperationId::UpdateTask, "Updates a task", 201, "string")
+        .with_request("NaiveUpdate")
+        .with_if_match(),
+    Endpoint::new(Method::DELETE, "/tasks/{id}", OperationId::DeleteTask, "Deletes a task", 200, "string")
+        .with_if_match(),
+    Endpoint::new(Method::POST, "/tasks/{id}/complete", OperationId::CompleteTask, "Marks a task as complete", 200, "string")
+        .with_if_match(),
+    Endpoint::new(Method::PUT, "/tasks/{id}/hold", OperationId::HoldTask, "Puts a task on hold", 200, "string")
+        .with_request("Hold"),
+    Endpoint::new(Method::DELETE, "/tasks/{id}/hold", OperationId::ReleaseTask, "Releases a task from hold", 200, "string"),
+    Endpoint::new(Method::PUT, "/tasks/{id}/workspace", OperationId::MoveTask, "Moves a task to another workspace", 200, "integer")
+        .with_request("string"),
+    Endpoint::new(Method::GET, "/completed/{id}", OperationId::GetCompletedTask, "Fetches a completed task", 200, "Task")
+        .with_if_none_match(),
+    Endpoint::new(Method::DELETE, "/completed/{id}", OperationId::DeleteCompletedTask, "Deletes a completed task", 200, "string"),
+    Endpoint::new(Method::GET, "/scheduler", OperationId::GetScheduler, "Fetches the state of the scheduler", 200, "SchedulerState")
+        .with_if_none_match(),
+    Endpoint::new(Method::PUT, "/scheduler", OperationId::SetScheduler, "Enables or disables the scheduler", 200, "SchedulerState")
+        .with_request("SchedulerState"),
+    Endpoint::new(Method::GET, "/scheduler/policy", OperationId::GetPolicy, "Fetches the queue priority", 200, "Priority")
+        .with_if_none_match(),
+    Endpoint::new(Method::PUT, "/scheduler/policy", OperationId::SetPolicy, "Sets the queue priority", 201, "string")
+        .with_request("Priority")
+        .with_if_match(),
+    Endpoint::new(Method::GET, "/scheduler/active", OperationId::GetActiveTask, "Fetches the active task", 200, "ActiveTask")
+        .with_if_none_match(),
+    Endpoint::new(Method::PUT, "/scheduler/active", OperationId::PinTask, "Pins a task as the active task", 200, "string")
+        .with_request("Pin"),
+    Endpoint::new(Method::DELETE, "/scheduler/active", OperationId::UnpinTask, "Unpins the active task", 200, "string"),
+    Endpoint::new(Method::GET, "/scheduler/forecast", OperationId::GetForecast, "Predicts the schedule", 200, "Slot[]"),
+    Endpoint::new(Method::GET, "/scheduler/drift", OperationId::GetDrift, "Lists long gaps between updates", 200, "Drift[]"),
+    Endpoint::new(Method::GET, "/history", OperationId::GetHistory, "Fetches the change history", 200, "History"),
+    Endpoint::new(Method::POST, "/history/undo", OperationId::Undo, "Undoes the most recent change", 200, "Entry"),
+    Endpoint::new(Method::POST, "/history/redo", OperationId::Redo, "Redoes the most recently undone change", 200, "Entry"),
+    Endpoint::new(Method::GET, "/trash", OperationId::GetTrash, "Lists the deleted tasks", 200, "Trashed[]"),
+    Endpoint::new(Method::DELETE, "/trash", OperationId::EmptyTrash, "Permanently deletes the trash", 200, "integer"),
+    Endpoint::new(Method::POST, "/trash/{status}/{id}/restore", OperationId::RestoreTask, "Restores a deleted task", 200, "Task"),
+    Endpoint::new(Method::GET, "/events", OperationId::StreamEvents, "Streams events as Server-Sent Events", 200, "Event"),
+    Endpoint::new(Method::GET, "/events/ws", OperationId::StreamEventsWs, "Streams events over a WebSocket", 101, "Event"),
+    Endpoint::new(Method::POST, "/webhooks", OperationId::AddWebhook, "Registers a webhook", 201, "integer")
+        .with_request("NaiveWebhook"),
+    Endpoint::new(Method::GET, "/webhooks", OperationId::GetWebhooks, "Lists the webhooks", 200, "WebhookInfo[]"),
+    Endpoint::new(Method::DELETE, "/webhooks/{id}", OperationId::DeleteWebhook, "Deletes a webhook", 200, "string"),
+    Endpoint::new(Method::GET, "/workspaces", OperationId::GetWorkspaces, "Lists the user's workspaces", 200, "WorkspaceInfo[]"),
+    Endpoint::new(Method::POST, "/workspaces", OperationId::AddWorkspace, "Creates a workspace", 201, "string")
+        .with_request("NewWorkspace"),
+    Endpoint::new(Method::GET, "/workspaces/active", OperationId::GetActiveWorkspace, "Fetches the active workspace", 200, "string"),
+    Endpoint::new(Method::PUT, "/workspaces/active", OperationId::SetActiveWorkspace, "Sets the active workspace", 200, "string")
+        .with_request("string"),
+    Endpoint::new(Method::DELETE, "/workspaces/{workspace}", OperationId::DeleteWorkspace, "Deletes a workspace", 200, "string"),
+    Endpoint::new(Method::GET, "/users", OperationId::ListUsers, "Lists the users", 200, "string[]"),
+    Endpoint::new(Method::GET, "/openapi.json", OperationId::GetOpenApi, "Fetches this document", 200, "object"),
+];
+
+/// Builds the OpenAPI 3 document describing the endpoints of a version.
+pub fn document(version: Version) -> Value {
+    let mut paths = Map::new();
+    for endpoint in version.endpoints() {
+        let path = paths
+            .entry(endpoint.path)
+            .or_insert_with(|| json!({}))
+            .as_object_mut()
+            .expect("paths are objects");
+        path.insert(endpoint.method.as_str().to_lowercase(), operation(endpoint));
+    }
+
+    let servers = match version {
+        Version::V1 => json!([{ "url": version.prefix() }, { "url": "/api" }]),
+        Version::V2 => json!([{ "url": version.prefix() }]),
+    };
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "Task Scheduler",
+            "version": env!("CARGO_PKG_VERSION"),
+            "description": "Every path may be prefixed with `/users/{user}` to operate on \
+                another user's queues, and paths other than `/workspaces` and `/users` with \
+                `/workspaces/{workspace}` to operate on a workspace other than the active one.",
+        },
+        "servers": servers,
+        "security": [{ "token": [] }],
+        "paths": paths,
+        "components": {
+            "securitySchemes": {
+                "token": { "type": "http", "scheme": "bearer" },
+            },
+            "schemas": schemas(),
+        },
+    })
+}
+
+/// Describes a single operation.
+fn operation(endpoint: &Endpoint) -> Value {
+    let mut parameters: Vec<Value> = endpoint
+        .path
+        .split('/')
+        .filter_map(|segment| segment.strip_prefix('{')?.strip_suffix('}'))
+        .map(|name| {
+            let schema = match name {
+                "id" => json!({ "type": "integer", "minimum": 0 }),
+                "status" => schema("TaskStatus"),
+                _ => json!({ "type": "string" }),
+            };
+            json!({ "name": name, "in": "path", "required": true, "schema": schema })
+        })
+        .collect();
+    parameters.extend(endpoint.query.iter().map(|(name, kind)| {
+        // only the search text is required
+        let required = endpoint.operation == OperationId::SearchTasks && *name == "q";
+        json!({ "name": name, "in": "query", "required": required, "schema": schema(kind) })
+    }));
+
+    let mut responses = Map::new();
+    let content = if endpoint.path == "/events" {
+        json!({ "text/event-stream": { "schema": schema(endpoint.response) } })
+    } else {
+        json!({ "application/json": { "schema": schema(endpoint.response) } })
+    };
+    responses.insert(
+        endpoint.status.to_string(),
+        json!({ "description": endpoint.summary, "content": content }),
+    );
+    if endpoint.if_none_match {
+        parameters.push(header("If-None-Match"));
+        responses.insert("304".to_string(), json!({ "description": "Not modified" }));
+    }
+    if endpoint.if_match {
+        parameters.push(header("If-Match"));
+        responses.insert(
+            "412".to_string(),
+            json!({ "description": "The resource has been modified", "content": error() }),
+        );
+    }
+    if endpoint.operation == OperationId::ApplyBatch {
+        responses.insert(
+            "409".to_string(),
+            json!({ "description": "The batch was rolled back", "content": { "application/json": { "schema": schema("BatchResult") } } }),
+        );
+    }
+    responses.insert(
+        "default".to_string(),
+        json!({ "description": "An error", "content": error() }),
+    );
+
+    let mut operation = json!({
+        "operationId": endpoint.operation.name(),
+        "summary": endpoint.summary,
+        "parameters": parameters,
+        "responses": responses,
+    });
+    if let Some(request) = endpoint.request {
+        operation["requestBody"] = json!({
+            "required": true,
+            "content": { "application/json": { "schema": schema(request) } },
+        });
+    }
+    // the document itself is served without authentication
+    if endpoint.operation == OperationId::GetOpenApi {
+        operation["security"] = json!([]);
+    }
+
+    operation
+}
+
+/// Returns the schema of a primitive type, a reference to a named schema, or
+/// a list of either.
+fn schema(kind: &str) -> Value {
+    if let Some(item) = kind.strip_suffix("[]") {
+        return json!({ "type": "array", "items": schema(item) });
+    }
+    match kind {
+        "string" | "integer" | "boolean" | "object" => json!({ "type": kind }),
+        _ => json!({ "$ref": format!("#/components/schemas/{kind}") }),
+    }
+}
+
+fn header(name: &str) -> Value {
+    json!({ "name": name, "in": "header", "required": false, "schema": { "type": "string" } })
+}
+
+fn error() -> Value {
+    json!({ "application/json": { "schema": schema("ErrorResponse") } })
+}
+
+/// The names of the built-in priorities, as used in their `type` tag.
+pub const PRIORITIES: &[&str] = &[
+    "FIFO",
+    "Deadline",
+    "Shortest",
+    "Longest",
+    "HighestPriority",
+    "LowestPriority",
+    "ShortestWithUrgency",
+];
+
+/// Returns the schemas of every type sent or received by the API.
+fn schemas() -> Value {
+    let task_fields = json!({
+        "title": { "type": "string" },
+        "deadline": schema("DateTime"),
+        "duration": schema("Duration"),
+        "priority": schema("PriorityLevel"),
+        "kind": schema("TaskKind"),
+    });
+    let mut task = task_fields.clone();
+    for (name, value) in [
+        ("id", json!({ "type": "integer" })),
+        (
+            "hold",
+            json!({ "allOf": [schema("Hold")], "nullable": true }),
+        ),
+        ("overdue", json!({ "type": "boolean" })),
+        (
+            "reminded",
+            json!({ "type": "array", "items": { "type": "integer" } }),
+        ),
+        ("revision", json!({ "type": "integer" })),
+        (
+            "completed_at",
+            json!({ "allOf": [schema("DateTime")], "nullable": true }),
+        ),
+    ] {
+        task[name] = value;
+    }
+    let mut naive_update = json!({});
+    for (name, value) in task_fields.as_object().expect("fields are an object") {
+        naive_update[name] = json!({ "allOf": [value], "nullable": true });
+    }
+    let mut update = naive_update.clone();
+    update["id"] = json!({ "type": "integer" });
+
+    let priorities: Vec<Value> = PRIORITIES
+        .iter()
+        .map(|name| {
+            let mut properties = json!({ "type": { "type": "string", "enum": [name] } });
+            let mut required = vec!["type"];
+            if *name == "ShortestWithUrgency" {
+                properties["deadline_weight"] = json!({ "type": "integer" });
+                properties["duration_weight"] = json!({ "type": "integer" });
+                required.extend(["deadline_weight", "duration_weight"]);
+            }
+            json!({ "type": "object", "properties": properties, "required": required })
+        })
+        .collect();
+
+    json!({
+        "DateTime": {
+            "type": "string",
+            "description": "A local date and time without an offset, e.g. 2025-06-01T12:00:00",
+            "example": "2025-06-01T12:00:00",
+        },
+        "Duration": {
+            "type": "array",
+            "description": "Seconds and nanoseconds",
+            "items": { "type": "integer" },
+            "minItems": 2,
+            "maxItems": 2,
+            "example": [3600, 0],
+        },
+        "PriorityLevel": { "type": "string", "enum": ["Urgent", "High", "Normal", "Low"] },
+        "TaskStatus": { "type": "string", "enum": ["open", "completed"] },
+        "SortField": { "type": "string", "enum": ["id", "title", "deadline", "duration", "priority"] },
+        "SortOrder": { "type": "string", "enum": ["asc", "desc"] },
+        "TaskKind": {
+            "oneOf": [
+                { "type": "string", "enum": ["Flexible"] },
+                {
+                    "type": "object",
+                    "properties": {
+                        "Fixed": {
+                            "type": "object",
+                            "properties": { "start": schema("DateTime"), "end": schema("DateTime") },
+                            "required": ["start", "end"],
+                        },
+                    },
+                    "required": ["Fixed"],
+                },
+            ],
+        },
+        "Hold": {
+            "type": "object",
+            "properties": {
+                "reason": { "type": "string" },
+                "until": { "allOf": [schema("DateTime")], "nullable": true },
+            },
+            "required": ["reason"],
+        },
+        "NaivePin": {
+            "type": "object",
+            "properties": { "until": { "allOf": [schema("DateTime")], "nullable": true } },
+        },
+        "Pin": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "integer" },
+                "until": { "allOf": [schema("DateTime")], "nullable": true },
+            },
+            "required": ["id"],
+        },
+        "SchedulerState": {
+            "type": "object",
+            "properties": { "enabled": { "type": "boolean" } },
+            "required": ["enabled"],
+        },
+        "Task": {
+            "type": "object",
+            "properties": task,
+            "required": ["id", "title", "deadline", "duration", "priority"],
+        },
+        "NaiveTask": {
+            "type": "object",
+            "properties": task_fields,
+            "required": ["title", "deadline", "duration", "priority"],
+        },
+        "UpdateTask": { "type": "object", "properties": update, "required": ["id"] },
+        "NaiveUpdate": { "type": "object", "properties": naive_update },
+        "ActiveTask": {
+            "type": "object",
+            "properties": {
+                "task": schema("Task"),
+                "since": schema("DateTime"),
+                "pinned": { "type": "boolean" },
+            },
+            "required": ["task", "since", "pinned"],
+        },
+        "TaskEntry": {
+            "allOf": [
+                schema("Task"),
+                { "type": "object", "properties": { "status": schema("TaskStatus") }, "required": ["status"] },
+            ],
+        },
+        "TaskPage": {
+            "type": "object",
+            "properties": {
+                "tasks": schema("TaskEntry[]"),
+                "total": { "type": "integer" },
+                "next_cursor": { "type": "string", "nullable": true },
+            },
+            "required": ["tasks", "total"],
+        },
+        "SearchResults": {
+            "type": "object",
+            "properties": {
+                "tasks": {
+                    "type": "array",
+                    "items": {
+                        "allOf": [
+                            schema("TaskEntry"),
+                            { "type": "object", "properties": { "score": { "type": "integer" } }, "required": ["score"] },
+                        ],
+                    },
+                },
+                "total": { "type": "integer" },
+            },
+            "required": ["tasks", "total"],
+        },
+        "Operation": {
+            "type": "object",
+            "description": "One of `add` (with a NaiveTask), `update` (with an UpdateTask), `delete` or `complete` (with an id)",
+            "properties": {
+                "op": { "type": "string", "enum": ["add", "update", "delete", "complete"] },
+                "task": { "oneOf": [schema("NaiveTask"), schema("UpdateTask")] },
+                "id": { "type": "integer" },
+            },
+            "required": ["op"],
+        },
+        "BatchResult": {
+            "type": "object",
+            "properties": {
+                "committed": { "type": "boolean" },
+                "results": {
+                    "type": "array",
+                    "items": {
+                        "type": "object",
+                        "properties": {
+                            "task": { "allOf": [schema("Task")], "nullable": true },
+                            "error": { "type": "string", "nullable": true },
+                        },
+                    },
+                },
+            },
+            "required": ["committed", "results"],
+        },
+        "Entry": {
+            "type": "object",
+            "description": "A change, tagged by `op`, with the tasks or priorities needed to undo it",
+            "properties": {
+                "at": schema("DateTime"),
+                "op": {
+                    "type": "string",
+                    "enum": ["add", "update", "delete", "complete", "priority", "batch"],
+                },
+            },
+            "required": ["at", "op"],
+            "additionalProperties": true,
+        },
+        "History": {
+            "type": "object",
+            "properties": { "undo": schema("Entry[]"), "redo": schema("Entry[]") },
+            "required": ["undo", "redo"],
+        },
+        "Trashed": {
+            "allOf": [
+                schema("Task"),
+                {
+                    "type": "object",
+                    "properties": { "status": schema("TaskStatus"), "deleted_at": schema("DateTime") },
+                    "required": ["status", "deleted_at"],
+                },
+            ],
+        },
+        "Slot": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "integer" },
+                "start": schema("DateTime"),
+                "end": schema("DateTime"),
+            },
+            "required": ["id", "start", "end"],
+        },
+        "Drift": {
+            "type": "object",
+            "description": "A gap between updates longer than `max_gap`, or one only the wall clock noticed, and the time charged",
+            "properties": {
+                "at": schema("DateTime"),
+                "gap": schema("Duration"),
+                "charged": schema("Duration"),
+            },
+            "required": ["at", "gap", "charged"],
+        },
+        "Priority": {
+            "oneOf": priorities,
+            "discriminator": { "propertyName": "type" },
+        },
+        "Event": {
+            "type": "object",
+            "description": "A change to a workspace's queue or scheduler, tagged by `type`",
+            "properties": {
+                "workspace": { "type": "string" },
+                "type": { "type": "string" },
+                "data": {},
+            },
+            "required": ["workspace", "type"],
+        },
+        "NaiveWebhook": {
+            "type": "object",
+            "properties": {
+                "url": { "type": "string" },
+                "events": { "type": "array", "items": { "type": "string" } },
+                "secret": { "type": "string" },
+            },
+            "required": ["url", "secret"],
+        },
+        "WebhookInfo": {
+            "type": "object",
+            "properties": {
+                "id": { "type": "integer" },
+                "url": { "type": "string" },
+                "events": { "type": "array", "items": { "type": "string" } },
+            },
+            "required": ["id", "url", "events"],
+        },
+        "NewWorkspace": {
+            "type": "object",
+            "properties": { "name": { "type": "string" }, "priority": schema("Priority") },
+            "required": ["name"],
+        },
+        "WorkspaceInfo": {
+            "type": "object",
+            "properties": {
+                "name": { "type": "string" },
+                "active": { "type": "boolean" },
+                "enabled": { "type": "boolean" },
+                "priority": { "type": "string" },
+                "tasks": { "type": "integer" },
+            },
+            "required": ["name", "active", "enabled", "priority", "tasks"],
+        },
+        "ErrorResponse": {
+            "type": "object",
+            "properties": {
+                "code": { "type": "string" },
+                "message": { "type": "string" },
+                "details": {},
+                "request_id": { "type": "string" },
+            },
+            "required": ["code", "message", "request_id"],
+        },
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::priority::Priority;
+    use crate::server::{SchedulerState, Server, ServerConfig};
+    use crate::{NaiveTask, NaiveUpdate, Pin, PriorityLevel, Task, TaskQueue, UpdateTask};
+    use chrono::{Duration, NaiveDateTime};
+    use std::collections::BTreeSet;
+    use std::sync::{Arc, Mutex};
+    use warp::Reply;
+
+    /// Sends a request to the server's routes, and returns the error code it
+    /// replied with, if any.
+    async fn code<F>(routes: &F, method: &Method, prefix: &str, path: &str) -> Option<String>
+    where
+        F: warp::Filter + 'static,
+        F::Extract: Reply + Send,
+    {
+        let response = warp::test::request()
+            .method(method.as_str())
+            .path(&format!("{prefix}{}", concrete(path)))
+            .header("content-type", "application/json")
+            .body("{}")
+            .reply(routes)
+            .await;
+        if response.status().is_success() {
+            return None;
+        }
+
+        serde_json::from_slice::<Value>(response.body()).ok()?["code"]
+            .as_str()
+            .map(str::to_string)
+    }
+
+    /// Fills in the parameters of a path in the spec.
+    fn concrete(path: &str) -> String {
+        path.replace("{id}", "1")
+            .replace("{status}", "open")
+            .replace("{workspace}", "default")
+    }
+
+    fn properties(schemas: &Value, name: &str) -> BTreeSet<String> {
+        schemas[name]["properties"]
+            .as_object()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect()
+    }
+
+    fn keys(value: Value) -> BTreeSet<String> {
+        value.as_object().unwrap().keys().cloned().collect()
+    }
+
+    #[tokio::test]
+    async fn test_routes_match_spec() {
+        let config: ServerConfig = serde_json::from_str(r#"{"address":"127.0.0.1:0"}"#).unwrap();
+        let server = Server::with_queue(Arc::new(Mutex::new(TaskQueue::new())));
+        let routes = server.routes(&config);
+
+        // the routes are built from the spec, so every endpoint is served.
+        // Check that each is reachable rather than shadowed by another route.
+        // v1 is also served without a prefix
+        for version in [Version::V1, Version::V2] {
+            let prefixes: &[&str] = match version {
+                Version::V1 => &["/api/v1", "/api"],
+                Version::V2 => &["/api/v2"],
+            };
+            let endpoints = version.endpoints().iter().filter(|e| e.path != "/events");
+            for (endpoint, prefix) in endpoints.flat_map(|e| prefixes.iter().map(move |p| (e, p))) {
+                let reply = code(&routes, &endpoint.method, prefix, endpoint.path).await;
+                assert!(
+                    !matches!(reply.as_deref(), Some("not_found" | "method_not_allowed")),
+                    "{} {prefix}{} is not reachable",
+                    endpoint.method,
+                    endpoint.path
+                );
+
+                // and only the whole path matches
+                let path = format!("{}/extra", endpoint.path);
+                let reply = code(&routes, &endpoint.method, prefix, &path).await;
+                assert!(
+                    matches!(reply.as_deref(), Some("not_found" | "method_not_allowed")),
+                    "{} {prefix}{path} is served",
+                    endpoint.method,
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_schemas_match_types() {
+        let document = document(Version::V2);
+        let schemas = &document["components"]["schemas"];
+
+        let task = NaiveTask::new(
+            "Task".to_string(),
+            NaiveDateTime::default(),
+            Duration::hours(1),
+            PriorityLevel::Normal,
+        );
+        assert_eq!(
+            properties(schemas, "NaiveTask"),
+            keys(serde_json::to_value(&task).unwrap())
+        );
+        assert_eq!(
+            properties(schemas, "Task"),
+            keys(serde_json::to_value(Task::from_naive(task, 1)).unwrap())
+        );
+        assert_eq!(
+            properties(schemas, "UpdateTask"),
+            keys(serde_json::to_value(UpdateTask::new(1)).unwrap())
+        );
+        assert_eq!(
+            properties(schemas, "NaiveUpdate"),
+            keys(serde_json::to_value(NaiveUpdate::default()).unwrap())
+        );
+        assert_eq!(
+            properties(schemas, "Pin"),
+            keys(serde_json::to_value(Pin { id: 1, until: None }).unwrap())
+        );
+        assert_eq!(
+            properties(schemas, "SchedulerState"),
+            keys(serde_json::to_value(SchedulerState { enabled: true }).unwrap())
+        );
+
+        // every priority in the spec can be deserialized
+        for priority in schemas["Priority"]["oneOf"].as_array().unwrap() {
+            let mut value = json!({ "type": priority["properties"]["type"]["enum"][0] });
+            for field in priority["required"].as_array().unwrap().iter().skip(1) {
+                value[field.as_str().unwrap()] = json!(1);
+            }
+            assert!(serde_json::from_value::<Box<dyn Priority>>(value).is_ok());
+        }
+    }
+}
@@ -1,10 +1,12 @@
 use crate::Task;
+use chrono::NaiveDateTime;
 use serde::{Deserialize, Serialize};
 
 /// A struct implementing the `Priority` trait can be assigned to a `TaskQueue`
 /// to define the method for selecting tasks. The important method is
-/// `select()` which defines the actual method of selection. To work properly,
-/// the `select()` method should **ignore tasks that are flagged as complete**.
+/// `select()` which defines the actual method of selection. Fixed tasks are
+/// scheduled by their windows rather than by the priority, so the queue passed
+/// to `select()` only ever contains flexible tasks.
 ///
 /// ## Example: `FIFO`
 /// ```rust
@@ -18,7 +20,7 @@ use serde::{Deserialize, Serialize};
 /// #[typetag::serde]
 /// impl Priority for FIFO {
 ///     fn select(&self, queue: &[Task]) -> Option<Task> {
-///         queue.iter().find(|t| !t.completed).cloned()
+///         queue.first().cloned()
 ///     }
 ///
 ///     fn string(&self) -> String {
@@ -31,6 +33,14 @@ use serde::{Deserialize, Serialize};
 /// }
 /// ```
 ///
+/// ## `score()`
+///
+/// Priorities that rank tasks by a numeric score can also implement `score()`
+/// (lower is better), given the time the scheduler is deciding at. The
+/// scheduler uses it to decide whether a challenger is enough of an
+/// improvement over the active task to be worth switching to. Priorities
+/// without a meaningful score can leave the default, which returns `None`.
+///
 /// ## `clone_box()`
 ///
 /// The `clone_box()` method is required to satisfy the trait bounds for
@@ -41,6 +51,10 @@ pub trait Priority: Send + Sync {
     fn select(&self, queue: &[Task]) -> Option<Task>;
     fn string(&self) -> String;
     fn clone_box(&self) -> Box<dyn Priority>;
+
+    fn score(&self, _task: &Task, _now: NaiveDateTime) -> Option<i64> {
+        None
+    }
 }
 
 /// Schedules tasks in the order they were added to the queue.
@@ -199,17 +213,10 @@ pub struct ShortestWithUrgency {
 #[typetag::serde]
 impl Priority for ShortestWithUrgency {
     fn select(&self, queue: &[Task]) -> Option<Task> {
+        let now = chrono::Local::now().naive_local();
         queue
             .iter()
-            .map(|t| {
-                let current_time = chrono::Local::now().naive_local();
-                let deadline_distance = t.deadline - current_time;
-
-                let score = (deadline_distance.num_seconds() / self.deadline_weight)
-                    - (self.duration_weight * t.duration.num_seconds());
-
-                (t, score)
-            })
+            .filter_map(|t| self.score(t, now).map(|s| (t, s)))
             .min_by_key(|(t, s)| (*s, t.priority, t.id)) // score, then priority, then id
             .map(|(t, _)| t)
             .cloned()
@@ -222,6 +229,15 @@ impl Priority for ShortestWithUrgency {
     fn clone_box(&self) -> Box<dyn Priority> {
         Box::new(self.clone())
     }
+
+    fn score(&self, task: &Task, now: NaiveDateTime) -> Option<i64> {
+        let deadline_distance = task.deadline - now;
+
+        Some(
+            (deadline_distance.num_seconds() / self.deadline_weight)
+                - (self.duration_weight * task.duration.num_seconds()),
+        )
+    }
 }
 
 #[cfg(test)]
@@ -307,4 +323,71 @@ mod test {
         assert_eq!(queue.select().unwrap().id, task2.id);
         queue.delete(queue.select().unwrap().id).unwrap();
     }
+
+    #[test]
+    fn test_shortest_urgency_threshold() {
+        let mut queue = TaskQueue::with_priority(ShortestWithUrgency {
+            deadline_weight: 1,
+            duration_weight: 1,
+        });
+
+        let now = chrono::Local::now().naive_local();
+
+        let task1 = Task::new(
+            1,
+            "task 1".to_string(),
+            now + Duration::hours(4),
+            Duration::hours(1),
+            PriorityLevel::Normal,
+        );
+        let task2 = Task::new(
+            2,
+            "task 2".to_string(),
+            now + Duration::hours(4),
+            Duration::minutes(61),
+            PriorityLevel::Normal,
+        );
+
+        queue.add(task1.clone());
+        queue.add(task2.clone());
+        queue.set_active(Some(task1.id), now);
+
+        // task 2 scores one minute better, which isn't enough to switch
+        let later = now + Duration::hours(1);
+        let threshold = Duration::minutes(5).num_seconds();
+        assert_eq!(queue.select_at(later).unwrap().id, task2.id);
+        assert_eq!(
+            queue
+                .select_stable(later, Duration::zero(), threshold)
+                .unwrap()
+                .id,
+            task1.id
+        );
+        assert_eq!(
+            queue.select_stable(later, Duration::zero(), 0).unwrap().id,
+            task2.id
+        );
+    }
+
+    #[test]
+    fn test_shortest_urgency_score_at() {
+        let priority = ShortestWithUrgency {
+            deadline_weight: 1,
+            duration_weight: 1,
+        };
+
+        let now = chrono::Local::now().naive_local();
+        let task = Task::new(
+            1,
+            "task 1".to_string(),
+            now + Duration::hours(4),
+            Duration::hours(1),
+            PriorityLevel::Normal,
+        );
+
+        // the score is taken at the given time, not the wall clock
+        let later = now + Duration::hours(2);
+        assert_eq!(priority.score(&task, now), Some(4 * 3600 - 3600));
+        assert_eq!(priority.score(&task, later), Some(2 * 3600 - 3600));
+    }
 }
@@ -0,0 +1,404 @@
+use crate::error::QueryError;
+use crate::{PriorityLevel, Task, TaskQueue};
+use chrono::{Duration, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+use std::cmp::Ordering;
+use std::str::FromStr;
+
+/// The number of tasks returned per page if no limit is given.
+pub const DEFAULT_LIMIT: usize = 100;
+
+/// The largest number of tasks that may be returned per page.
+pub const MAX_LIMIT: usize = 1000;
+
+/// Whether a task is still in the queue or has been completed.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[serde(rename_all = "lowercase")]
+pub enum TaskStatus {
+    Open,
+    Completed,
+}
+
+impl FromStr for TaskStatus {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "open" => Ok(TaskStatus::Open),
+            "completed" => Ok(TaskStatus::Completed),
+            _ => Err("Unknown task status".to_string()),
+        }
+    }
+}
+
+/// The field tasks are sorted by.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortField {
+    #[default]
+    Id,
+    Title,
+    Deadline,
+    Duration,
+    Priority,
+}
+
+/// The direction tasks are sorted in.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum SortOrder {
+    #[default]
+    Asc,
+    Desc,
+}
+
+/// A `TaskQuery` selects which tasks to list, in which order, and which page
+/// of them to return. Every filter is optional; an empty query lists every
+/// open and completed task by ID.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TaskQuery {
+    /// Only list open or completed tasks
+    pub status: Option<TaskStatus>,
+
+    /// Only list tasks with this priority level
+    pub priority: Option<PriorityLevel>,
+
+    /// Only list tasks due at or after this time
+    pub from: Option<NaiveDateTime>,
+
+    /// Only list tasks due before this time
+    pub to: Option<NaiveDateTime>,
+
+    /// Only list tasks whose title contains this text, ignoring case
+    pub q: Option<String>,
+
+    #[serde(default)]
+    pub sort: SortField,
+
+    #[serde(default)]
+    pub order: SortOrder,
+
+    /// The maximum number of tasks to return (see `DEFAULT_LIMIT` and
+    /// `MAX_LIMIT`)
+    pub limit: Option<usize>,
+
+    /// The `next_cursor` of the previous page
+    pub cursor: Option<String>,
+}
+
+/// A task along with whether it is open or completed. Open and completed
+/// tasks are numbered separately, so both are needed to identify a task.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TaskEntry {
+    pub status: TaskStatus,
+    #[serde(flatten)]
+    pub task: Task,
+}
+
+/// A `TaskPage` is one page of the tasks matching a `TaskQuery`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct TaskPage {
+    pub tasks: Vec<TaskEntry>,
+
+    /// The number of tasks matching the query, across every page
+    pub total: usize,
+
+    /// The cursor of the next page, if there is one
+    pub next_cursor: Option<String>,
+}
+
+impl TaskQuery {
+    /// Returns `true` if the task matches every filter of the query.
+    pub fn matches(&self, status: TaskStatus, task: &Task) -> bool {
+        self.status.is_none_or(|s| s == status)
+            && self.priority.is_none_or(|p| p == task.priority)
+            && self.from.is_none_or(|from| task.deadline >= from)
+            && self.to.is_none_or(|to| task.deadline < to)
+            && self.q.as_ref().is_none_or(|q| {
+                task.title.to_lowercase().contains(&q.to_lowercase())
+            })
+    }
+
+    /// Returns the position of a task in the order of the query.
+    fn position(&self, entry: &TaskEntry) -> Position {
+        let key = match self.sort {
+            SortField::Id => SortKey::Id,
+            SortField::Title => SortKey::Title(entry.task.title.to_lowercase()),
+            SortField::Deadline => SortKey::Deadline(entry.task.deadline),
+            SortField::Duration => SortKey::Duration(entry.task.duration),
+            SortField::Priority => SortKey::Priority(entry.task.priority),
+        };
+
+        Position {
+            key,
+            id: entry.task.id(),
+            status: entry.status,
+        }
+    }
+
+    /// Compares two positions in the order of the query.
+    fn compare(&self, a: &Position, b: &Position) -> Ordering {
+        match self.order {
+            SortOrder::Asc => a.cmp(b),
+            SortOrder::Desc => b.cmp(a),
+        }
+    }
+}
+
+/// The value of the sort field of a task.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum SortKey {
+    Id,
+    Title(String),
+    Deadline(NaiveDateTime),
+    Duration(Duration),
+    Priority(PriorityLevel),
+}
+
+impl SortKey {
+    /// Returns the sort field this is the value of.
+    fn field(&self) -> SortField {
+        match self {
+            SortKey::Id => SortField::Id,
+            SortKey::Title(_) => SortField::Title,
+            SortKey::Deadline(_) => SortField::Deadline,
+            SortKey::Duration(_) => SortField::Duration,
+            SortKey::Priority(_) => SortField::Priority,
+        }
+    }
+}
+
+/// The position of a task in a sorted list. Ties in the sort field are broken
+/// by ID and status, so every task has a distinct position.
+///
+/// The position of the last task of a page is its cursor, so the next page
+/// resumes strictly after it, even if tasks were added or removed in between.
+/// It's encoded as hex-encoded JSON, which clients should treat as opaque.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+struct Position {
+    key: SortKey,
+    id: usize,
+    status: TaskStatus,
+}
+
+impl Position {
+    /// Encodes the position as a cursor.
+    fn encode(&self) -> String {
+        serde_json::to_vec(self)
+            .expect("positions are always serializable")
+            .iter()
+            .map(|b| format!("{b:02x}"))
+            .collect()
+    }
+
+    /// Decodes a cursor, returning `None` if it isn't a valid one.
+    fn decode(cursor: &str) -> Option<Self> {
+        if !cursor.len().is_multiple_of(2) || !cursor.is_ascii() {
+            return None;
+        }
+        let bytes = (0..cursor.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&cursor[i..i + 2], 16).ok())
+            .collect::<Option<Vec<u8>>>()?;
+
+        serde_json::from_slice(&bytes).ok()
+    }
+}
+
+impl TaskQueue {
+    /// Returns the page of tasks matching the given query. If the cursor is
+    /// invalid, or was returned for a query sorted by another field, a
+    /// `QueryError` is returned.
+    pub fn query(&self, query: &TaskQuery) -> Result<TaskPage, QueryError> {
+        let after = match &query.cursor {
+            Some(cursor) => Some(
+                Position::decode(cursor)
+                    .filter(|p| p.key.field() == query.sort)
+                    .ok_or_else(|| QueryError(format!("Invalid cursor {cursor}")))?,
+            ),
+            None => None,
+        };
+        let limit = query.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT);
+
+        let open = self.tasks.iter().map(|t| (TaskStatus::Open, t));
+        let completed = self.completed.iter().map(|t| (TaskStatus::Completed, t));
+        let mut tasks: Vec<(Position, TaskEntry)> = open
+            .chain(completed)
+            .filter(|(status, task)| query.matches(*status, task))
+            .map(|(status, task)| {
+                let entry = TaskEntry {
+                    status,
+                    task: task.clone(),
+                };
+                (query.position(&entry), entry)
+            })
+            .collect();
+        tasks.sort_by(|a, b| query.compare(&a.0, &b.0));
+
+        let total = tasks.len();
+        let mut remaining = tasks.into_iter().filter(|(position, _)| {
+            after
+                .as_ref()
+                .is_none_or(|after| query.compare(position, after) == Ordering::Greater)
+        });
+        let page: Vec<(Position, TaskEntry)> = remaining.by_ref().take(limit).collect();
+        let next_cursor = match (page.last(), remaining.next()) {
+            (Some((position, _)), Some(_)) => Some(position.encode()),
+            _ => None,
+        };
+        let tasks = page.into_iter().map(|(_, entry)| entry).collect();
+
+        Ok(TaskPage {
+            tasks,
+            total,
+            next_cursor,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::task;
+
+    fn queue() -> TaskQueue {
+        let day = |d: u32| {
+            chrono::NaiveDate::from_ymd_opt(2025, 1, d)
+                .unwrap()
+                .and_hms_opt(12, 0, 0)
+                .unwrap()
+        };
+
+        let mut queue = TaskQueue::new();
+        queue.add(Task {
+            title: "Write report".into(),
+            deadline: day(3),
+            duration: Duration::hours(2),
+            priority: PriorityLevel::High,
+            ..task(1)
+        });
+        queue.add(Task {
+            title: "Groceries".into(),
+            deadline: day(1),
+            priority: PriorityLevel::Low,
+            ..task(2)
+        });
+        queue.add(Task {
+            title: "Review report".into(),
+            deadline: day(2),
+            priority: PriorityLevel::High,
+            ..task(3)
+        });
+        queue.add_completed(Task {
+            title: "Old report".into(),
+            deadline: day(1),
+            duration: Duration::zero(),
+            ..task(1)
+        });
+        queue
+    }
+
+    fn ids(page: &TaskPage) -> Vec<(TaskStatus, usize)> {
+        page.tasks.iter().map(|e| (e.status, e.task.id())).collect()
+    }
+
+    #[test]
+    fn test_filter_and_sort() {
+        let queue = queue();
+
+        let query = TaskQuery {
+            q: Some("REPORT".into()),
+            status: Some(TaskStatus::Open),
+            sort: SortField::Deadline,
+            ..Default::default()
+        };
+        let page = queue.query(&query).unwrap();
+        assert_eq!(ids(&page), vec![(TaskStatus::Open, 3), (TaskStatus::Open, 1)]);
+
+        let query = TaskQuery {
+            priority: Some(PriorityLevel::High),
+            order: SortOrder::Desc,
+            ..Default::default()
+        };
+        assert_eq!(
+            ids(&queue.query(&query).unwrap()),
+            vec![(TaskStatus::Open, 3), (TaskStatus::Open, 1)]
+        );
+
+        // an empty result is still a page
+        let query = TaskQuery {
+            q: Some("nothing".into()),
+            ..Default::default()
+        };
+        let page = queue.query(&query).unwrap();
+        assert!(page.tasks.is_empty());
+        assert_eq!(page.total, 0);
+    }
+
+    #[test]
+    fn test_pagination() {
+        let queue = queue();
+        let mut query = TaskQuery {
+            limit: Some(3),
+            ..Default::default()
+        };
+
+        let first = queue.query(&query).unwrap();
+        assert_eq!(first.total, 4);
+        assert_eq!(
+            ids(&first),
+            vec![(TaskStatus::Open, 1), (TaskStatus::Completed, 1), (TaskStatus::Open, 2)]
+        );
+
+        query.cursor = first.next_cursor.clone();
+        let second = queue.query(&query).unwrap();
+        assert_eq!(ids(&second), vec![(TaskStatus::Open, 3)]);
+        assert!(second.next_cursor.is_none());
+
+        query.cursor = Some("garbage".into());
+        assert!(queue.query(&query).is_err());
+
+        // a cursor can't be used with another sort field
+        query.cursor = first.next_cursor.clone();
+        query.sort = SortField::Title;
+        assert!(queue.query(&query).is_err());
+    }
+
+    #[test]
+    fn test_pagination_with_changes() {
+        let mut queue = queue();
+        let mut query = TaskQuery {
+            limit: Some(2),
+            ..Default::default()
+        };
+
+        let first = queue.query(&query).unwrap();
+        assert_eq!(
+            ids(&first),
+            vec![(TaskStatus::Open, 1), (TaskStatus::Completed, 1)]
+        );
+
+        // adding a task before the cursor doesn't repeat any
+        queue.add_completed(Task {
+            title: "Older report".into(),
+            deadline: first.tasks[0].task.deadline,
+            ..task(0)
+        });
+        query.cursor = first.next_cursor;
+        let second = queue.query(&query).unwrap();
+        assert_eq!(
+            ids(&second),
+            vec![(TaskStatus::Open, 2), (TaskStatus::Open, 3)]
+        );
+        assert!(second.next_cursor.is_none());
+
+        // and removing one from the first page doesn't skip any
+        queue.remove(0);
+        let second = queue.query(&query).unwrap();
+        assert_eq!(
+            ids(&second),
+            vec![(TaskStatus::Open, 2), (TaskStatus::Open, 3)]
+        );
+    }
+}
@@ -0,0 +1,467 @@
+use crate::error::{InvalidName, WorkspaceExists, WorkspaceNotFound};
+use crate::priority::Priority;
+use crate::{SharedQueue, TaskQueue};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+/// The user that owns the queue of a single-user server, and that requests
+/// are attributed to when authentication is disabled.
+pub const DEFAULT_USER: &str = "default";
+
+/// The workspace every user starts with.
+pub const DEFAULT_WORKSPACE: &str = "default";
+
+/// A `Registry` holds the task queues of every user of the server. Each user
+/// may have several named queues, called workspaces (e.g. "work" and
+/// "personal"). Each workspace has its own priority, enabled flag, and
+/// scheduler state, but only the user's active workspace is scheduled.
+#[derive(Default)]
+pub struct Registry {
+    queues: HashMap<String, BTreeMap<String, SharedQueue>>,
+    removed: Vec<(String, String)>,
+    /// The number of servers currently serving the registry
+    servers: usize,
+}
+
+/// A `NewWorkspace` is the request body used to create a workspace. If no
+/// priority is given, the workspace uses the default priority.
+#[derive(Serialize, Deserialize)]
+pub struct NewWorkspace {
+    pub name: String,
+    #[serde(default)]
+    pub priority: Option<Box<dyn Priority>>,
+}
+
+/// A `WorkspaceInfo` summarizes one of a user's workspaces.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WorkspaceInfo {
+    pub name: String,
+    pub active: bool,
+    pub enabled: bool,
+    pub priority: String,
+    pub tasks: usize,
+}
+
+impl Registry {
+    /// Creates a new, empty `Registry`.
+    pub fn new() -> Self {
+        Self {
+            queues: HashMap::new(),
+            removed: Vec::new(),
+            servers: 0,
+        }
+    }
+
+    /// Creates a new `Registry` containing only the given queue, which is the
+    /// default workspace of the default user.
+    pub fn with_queue(queue: SharedQueue) -> Self {
+        let mut registry = Self::new();
+        registry
+            .queues
+            .entry(DEFAULT_USER.to_string())
+            .or_default()
+            .insert(DEFAULT_WORKSPACE.to_string(), queue);
+        registry
+    }
+
+    /// Loads every workspace of every user from disk. The default user's
+    /// default workspace is stored at `data_path`, and every other workspace
+    /// is stored next to it (see `path_for()`). A missing default queue is
+    /// replaced with an empty one.
+    pub fn load(data_path: &Path) -> Result<Self, std::io::Error> {
+        let mut registry = Self::new();
+
+        let dir = match data_path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let Some((user, workspace)) = Self::parse_path(data_path, &path) else {
+                continue;
+            };
+
+            let queue: TaskQueue = serde_json::from_slice(&fs::read(&path)?)?;
+            registry
+                .queues
+                .entry(user)
+                .or_default()
+                .insert(workspace, Arc::new(Mutex::new(queue)));
+        }
+
+        registry
+            .queues
+            .entry(DEFAULT_USER.to_string())
+            .or_default()
+            .entry(DEFAULT_WORKSPACE.to_string())
+            .or_insert_with(|| Arc::new(Mutex::new(TaskQueue::new())));
+
+        Ok(registry)
+    }
+
+    /// Returns the path a workspace is stored at, given the path of the
+    /// default user's default workspace. For example, if `data_path` is
+    /// `tasks.json`, user `alice`'s default workspace is stored at
+    /// `tasks-alice.json` and her `work` workspace at `tasks-alice.work.json`.
+    pub fn path_for(data_path: &Path, user: &str, workspace: &str) -> PathBuf {
+        if user == DEFAULT_USER && workspace == DEFAULT_WORKSPACE {
+            return data_path.to_path_buf();
+        }
+
+        let (mut name, extension) = Self::file_parts(data_path);
+        if user != DEFAULT_USER {
+            name.push('-');
+            name.push_str(user);
+        }
+        if workspace != DEFAULT_WORKSPACE {
+            name.push('.');
+            name.push_str(workspace);
+        }
+        name.push_str(&extension);
+
+        data_path.with_file_name(name)
+    }
+
+    /// Determines the user and workspace stored at `path`, if it is one of the
+    /// paths returned by `path_for()`.
+    fn parse_path(data_path: &Path, path: &Path) -> Option<(String, String)> {
+        let (stem, extension) = Self::file_parts(data_path);
+        let name = path.file_name()?.to_str()?;
+        let middle = name.strip_prefix(&stem)?.strip_suffix(&extension)?;
+
+        let (user, workspace) = middle.split_once('.').unwrap_or((middle, DEFAULT_WORKSPACE));
+        let user = user.strip_prefix('-').unwrap_or(DEFAULT_USER);
+
+        // anything that doesn't map back to the same path, such as
+        // `tasks-default.json`, isn't ours
+        if is_valid_name(user)
+            && is_valid_name(workspace)
+            && Self::path_for(data_path, user, workspace) == path.with_file_name(name)
+        {
+            Some((user.to_string(), workspace.to_string()))
+        } else {
+            None
+        }
+    }
+
+    /// Splits a data path into the stem and extension used for per-user file
+    /// names.
+    fn file_parts(data_path: &Path) -> (String, String) {
+        let stem = data_path
+            .file_stem()
+            .and_then(|s| s.to_str())
+            .unwrap_or("tasks");
+        let extension = data_path
+            .extension()
+            .and_then(|e| e.to_str())
+            .map(|e| format!(".{e}"))
+            .unwrap_or_default();
+
+        (stem.to_string(), extension)
+    }
+
+    /// Adds a workspace to the registry, replacing any existing workspace
+    /// with the same name. If either name is invalid, an `InvalidName` error
+    /// is returned.
+    pub fn insert(
+        &mut self,
+        user: &str,
+        workspace: &str,
+        queue: SharedQueue,
+    ) -> Result<(), InvalidName> {
+        if !is_valid_name(user) || !is_valid_name(workspace) {
+            return Err(InvalidName);
+        }
+
+        self.queues
+            .entry(user.to_string())
+            .or_default()
+            .insert(workspace.to_string(), queue);
+        Ok(())
+    }
+
+    /// Returns the queue of the given user's active workspace, if the user
+    /// exists.
+    pub fn get(&self, user: &str) -> Option<SharedQueue> {
+        let active = self.active(user)?;
+        self.workspace(user, &active)
+    }
+
+    /// Returns the queue of the given user's active workspace, creating an
+    /// empty default workspace if the user doesn't exist yet. If the user
+    /// name is invalid, an `InvalidName` error is returned.
+    pub fn get_or_create(&mut self, user: &str) -> Result<SharedQueue, InvalidName> {
+        if let Some(queue) = self.get(user) {
+            return Ok(queue);
+        }
+
+        let queue = Arc::new(Mutex::new(TaskQueue::new()));
+        self.insert(user, DEFAULT_WORKSPACE, Arc::clone(&queue))?;
+        Ok(queue)
+    }
+
+    /// Returns the queue of one of the given user's workspaces, if it exists.
+    pub fn workspace(&self, user: &str, workspace: &str) -> Option<SharedQueue> {
+        self.queues.get(user)?.get(workspace).map(Arc::clone)
+    }
+
+    /// Returns the name of the given user's active workspace, if the user
+    /// exists. If no workspace has been activated, the default workspace is
+    /// active, or the first workspace if it has been deleted.
+    pub fn active(&self, user: &str) -> Option<String> {
+        let workspaces = self.queues.get(user)?;
+
+        let selected = workspaces
+            .iter()
+            .find(|(_, q)| q.lock().is_ok_and(|q| q.selected))
+            .map(|(name, _)| name);
+        let fallback = || {
+            workspaces
+                .get_key_value(DEFAULT_WORKSPACE)
+                .or(workspaces.first_key_value())
+                .map(|(name, _)| name)
+        };
+
+        selected.or_else(fallback).cloned()
+    }
+
+    /// Makes a workspace the given user's active workspace, so it is the one
+    /// the scheduler works on. The other workspaces no longer have an active
+    /// task. If the workspace does not exist, a `WorkspaceNotFound` error is
+    /// returned.
+    pub fn activate(&mut self, user: &str, workspace: &str) -> Result<(), WorkspaceNotFound> {
+        let workspaces = self.queues.get(user).ok_or(WorkspaceNotFound)?;
+        if !workspaces.contains_key(workspace) {
+            return Err(WorkspaceNotFound);
+        }
+
+        let now = chrono::Local::now().naive_local();
+        for (name, queue) in workspaces {
+            let Ok(mut queue) = queue.lock() else {
+                continue;
+            };
+            queue.selected = name == workspace;
+            if !queue.selected {
+                queue.set_active(None, now);
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Creates a new workspace for the given user containing `queue`. If the
+    /// user already has a workspace with that name, a `WorkspaceExists` error
+    /// is returned. The name should be checked with `is_valid_name()` first.
+    pub fn create_workspace(
+        &mut self,
+        user: &str,
+        workspace: &str,
+        queue: TaskQueue,
+    ) -> Result<SharedQueue, WorkspaceExists> {
+        let workspaces = self.queues.entry(user.to_string()).or_default();
+        if workspaces.contains_key(workspace) {
+            return Err(WorkspaceExists);
+        }
+
+        let queue = Arc::new(Mutex::new(queue));
+        workspaces.insert(workspace.to_string(), Arc::clone(&queue));
+        self.removed.retain(|(u, w)| u != user || w != workspace);
+        Ok(queue)
+    }
+
+    /// Removes one of the given user's workspaces and returns its queue. If
+    /// the workspace does not exist, a `WorkspaceNotFound` error is returned.
+    /// The caller is responsible for not removing the active workspace.
+    pub fn delete_workspace(
+        &mut self,
+        user: &str,
+        workspace: &str,
+    ) -> Result<SharedQueue, WorkspaceNotFound> {
+        let queue = self
+            .queues
+            .get_mut(user)
+            .and_then(|w| w.remove(workspace))
+            .ok_or(WorkspaceNotFound)?;
+        self.removed.push((user.to_string(), workspace.to_string()));
+
+        Ok(queue)
+    }
+
+    /// Returns a summary of every workspace belonging to the given user,
+    /// sorted by name.
+    pub fn workspaces(&self, user: &str) -> Vec<WorkspaceInfo> {
+        let Some(workspaces) = self.queues.get(user) else {
+            return Vec::new();
+        };
+        let active = self.active(user);
+
+        workspaces
+            .iter()
+            .filter_map(|(name, queue)| {
+                let queue = queue.lock().ok()?;
+                Some(WorkspaceInfo {
+                    name: name.clone(),
+                    active: active.as_ref() == Some(name),
+                    enabled: queue.enabled,
+                    priority: queue.show_priority(),
+                    tasks: queue.iter().count(),
+                })
+            })
+            .collect()
+    }
+
+    /// Returns the names of every user with a queue, sorted alphabetically.
+    pub fn users(&self) -> Vec<String> {
+        let mut users: Vec<String> = self.queues.keys().cloned().collect();
+        users.sort();
+        users
+    }
+
+    /// Returns every user along with the name and queue of their active
+    /// workspace.
+    pub fn queues(&self) -> Vec<(String, String, SharedQueue)> {
+        self.queues
+            .keys()
+            .filter_map(|user| {
+                let active = self.active(user)?;
+                let queue = self.workspace(user, &active)?;
+                Some((user.clone(), active, queue))
+            })
+            .collect()
+    }
+
+    /// Returns every workspace of every user, along with the user and the
+    /// name of the workspace.
+    pub fn entries(&self) -> Vec<(String, String, SharedQueue)> {
+        self.queues
+            .iter()
+            .flat_map(|(user, workspaces)| {
+                workspaces
+                    .iter()
+                    .map(|(name, queue)| (user.clone(), name.clone(), Arc::clone(queue)))
+            })
+            .collect()
+    }
+
+    /// Returns the user and name of every workspace deleted since the last
+    /// call, so their data can be removed from disk.
+    pub fn take_removed(&mut self) -> Vec<(String, String)> {
+        std::mem::take(&mut self.removed)
+    }
+
+    /// Records that a server has started serving the registry.
+    pub(crate) fn start_serving(&mut self) {
+        self.servers += 1;
+    }
+
+    /// Records that a server has stopped serving the registry, after
+    /// finishing its in-flight requests.
+    pub(crate) fn stop_serving(&mut self) {
+        self.servers = self.servers.saturating_sub(1);
+    }
+
+    /// Returns `true` if a server may still change the registry's queues.
+    pub fn is_serving(&self) -> bool {
+        self.servers > 0
+    }
+}
+
+/// Returns `true` if the given user or workspace name is valid. Names are
+/// used in URLs and file names, so they may only contain ASCII letters,
+/// digits, `-` and `_`.
+pub fn is_valid_name(name: &str) -> bool {
+    !name.is_empty()
+        && name.len() <= 64
+        && name
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
+}
+
+pub type SharedRegistry = Arc<Mutex<Registry>>;
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_path_for() {
+        let path = Path::new("/var/lib/tasks/tasks.json");
+        assert_eq!(Registry::path_for(path, DEFAULT_USER, DEFAULT_WORKSPACE), path);
+        assert_eq!(
+            Registry::path_for(path, "alice", DEFAULT_WORKSPACE),
+            Path::new("/var/lib/tasks/tasks-alice.json")
+        );
+        assert_eq!(
+            Registry::path_for(path, "alice", "work"),
+            Path::new("/var/lib/tasks/tasks-alice.work.json")
+        );
+
+        let parse = |name: &str| Registry::parse_path(path, &path.with_file_name(name));
+        assert_eq!(parse("tasks.json"), Some(("default".into(), "default".into())));
+        assert_eq!(parse("tasks.work.json"), Some(("default".into(), "work".into())));
+        assert_eq!(parse("tasks-alice.work.json"), Some(("alice".into(), "work".into())));
+        assert_eq!(parse("tasks-default.json"), None);
+        assert_eq!(parse("tasksalice.json"), None);
+        assert_eq!(parse("tasks.json.bak"), None);
+    }
+
+    #[test]
+    fn test_valid_name() {
+        assert!(is_valid_name("alice"));
+        assert!(is_valid_name("bob_2-x"));
+        assert!(!is_valid_name(""));
+        assert!(!is_valid_name("../etc"));
+        assert!(!is_valid_name("a b"));
+        assert!(!is_valid_name("a.b"));
+    }
+
+    #[test]
+    fn test_get_or_create() {
+        let mut registry = Registry::new();
+        assert!(registry.get("alice").is_none());
+
+        let queue = registry.get_or_create("alice").unwrap();
+        assert!(Arc::ptr_eq(&queue, &registry.get("alice").unwrap()));
+        assert!(registry.get_or_create("../alice").is_err());
+        assert_eq!(registry.users(), vec!["alice".to_string()]);
+    }
+
+    #[test]
+    fn test_workspaces() {
+        let mut registry = Registry::new();
+        let default = registry.get_or_create("alice").unwrap();
+        let work = registry
+            .create_workspace("alice", "work", TaskQueue::new())
+            .unwrap();
+        assert!(registry
+            .create_workspace("alice", "work", TaskQueue::new())
+            .is_err());
+
+        // the default workspace is active until another is activated
+        assert_eq!(registry.active("alice").as_deref(), Some(DEFAULT_WORKSPACE));
+        registry.activate("alice", "work").unwrap();
+        assert!(Arc::ptr_eq(&work, &registry.get("alice").unwrap()));
+        assert!(registry.activate("alice", "chores").is_err());
+
+        let names: Vec<(String, bool)> = registry
+            .workspaces("alice")
+            .into_iter()
+            .map(|w| (w.name, w.active))
+            .collect();
+        assert_eq!(
+            names,
+            vec![("default".to_string(), false), ("work".to_string(), true)]
+        );
+
+        registry.delete_workspace("alice", DEFAULT_WORKSPACE).unwrap();
+        assert_eq!(
+            registry.take_removed(),
+            vec![("alice".to_string(), DEFAULT_WORKSPACE.to_string())]
+        );
+        assert!(registry.workspace("alice", DEFAULT_WORKSPACE).is_none());
+        assert!(!Arc::ptr_eq(&default, &registry.get("alice").unwrap()));
+    }
+}
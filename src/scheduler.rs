@@ -1,17 +1,35 @@
 use crate::error::SchedulingError;
-use crate::{SharedQueue, Task};
-use chrono::TimeDelta;
+use crate::events::{Event, EventBus};
+use crate::notify::{LogNotifier, Notification, Notifier};
+use crate::registry::{Registry, SharedRegistry};
+use crate::vars;
+use crate::{SharedQueue, Task, TaskQueue};
+use chrono::{DateTime, NaiveDateTime, TimeDelta, Utc};
 use piglog::{debug, error, info};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::fs;
-use std::path::PathBuf;
-use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
-use std::thread::sleep;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::AtomicBool;
+use std::sync::{Arc, Mutex};
 use std::time::{Duration, Instant};
+use tokio::sync::broadcast::error::TryRecvError;
+use tokio::time::MissedTickBehavior;
+
+/// How long the scheduler waits for a `Server` sharing its registry to finish
+/// its in-flight requests before the final write. Changes accepted after that
+/// are lost.
+pub const SHUTDOWN_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// The number of `Drift` records kept in each queue.
+pub const DRIFT_LIMIT: usize = 100;
+
+/// How far the wall clock may run ahead of the monotonic clock between two
+/// updates before the difference is treated as a suspend, rather than jitter.
+pub const CLOCK_TOLERANCE: TimeDelta = TimeDelta::seconds(1);
 
 /// Scheduler configuration values defined by the user or system
-#[derive(Deserialize)]
+#[derive(Clone, Deserialize)]
 pub struct SchedulerConfig {
     /// The file to contain task data
     pub data_path: PathBuf,
@@ -21,85 +39,880 @@ pub struct SchedulerConfig {
     pub write_timeout: usize,
 
     /// The scheduler timeout in milliseconds (how often the tasks will be
-    /// updated)
+    /// updated), at least 1. Changes to the queues, such as a task being
+    /// added, update them immediately
     pub scheduler_timeout: usize,
+
+    /// The minimum time in minutes a task must be active before another task
+    /// may preempt it
+    #[serde(default)]
+    pub min_run_time: usize,
+
+    /// The score difference a challenger must beat the active task by before
+    /// it may preempt it (only applies to priorities that provide scores)
+    #[serde(default)]
+    pub preemption_threshold: i64,
+
+    /// How long in minutes before a deadline to send reminders, e.g.
+    /// `[1440, 60]` to be reminded a day and an hour before
+    #[serde(default)]
+    pub reminders: Vec<usize>,
+
+    /// How long in days deleted tasks are kept in the trash before they are
+    /// purged. If unset, the trash is never emptied automatically
+    #[serde(default)]
+    pub trash_retention: Option<usize>,
+
+    /// How long in days completed tasks are kept before they are moved to the
+    /// archive. If unset, completed tasks are never archived
+    #[serde(default)]
+    pub archive_after: Option<usize>,
+
+    /// The file archived tasks are appended to, one JSON object per line.
+    /// Defaults to the data path with the extension `.archive.jsonl`
+    #[serde(default)]
+    pub archive_path: Option<PathBuf>,
+
+    /// How the time between updates is charged to the active task
+    #[serde(default)]
+    pub time_accounting: TimeAccounting,
+}
+
+/// `TimeAccounting` configures how the time between two updates is charged to
+/// the active task. Normally the whole gap is charged, as measured by a
+/// monotonic clock. A gap longer than `max_gap`, or one the wall clock
+/// noticed but the monotonic clock didn't, e.g. because the machine was
+/// suspended, is charged according to the `policy` and recorded as a `Drift`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct TimeAccounting {
+    /// The longest gap charged in full, in seconds. Gaps up to the
+    /// `scheduler_timeout` are always charged in full
+    pub max_gap: u64,
+
+    /// How a longer gap is charged
+    pub policy: GapPolicy,
+}
+
+impl Default for TimeAccounting {
+    fn default() -> Self {
+        Self {
+            max_gap: 5 * 60,
+            policy: GapPolicy::Cap,
+        }
+    }
+}
+
+/// How a gap recorded as a `Drift` is charged to the active task.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum GapPolicy {
+    /// Charge the whole gap, but no more than `max_gap`
+    Cap,
+    /// Charge only the time the monotonic clock measured, if that's no longer
+    /// than `max_gap`
+    Skip,
+    /// Charge the whole gap
+    Charge,
+}
+
+/// A `Drift` records a gap between two updates that was longer than
+/// `TimeAccounting::max_gap`, or that only the wall clock noticed, and how much
+/// of it was charged to the active task.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Drift {
+    /// When the gap ended
+    pub at: NaiveDateTime,
+    /// How long the gap was
+    pub gap: TimeDelta,
+    /// How much of it was charged
+    pub charged: TimeDelta,
+}
+
+impl TaskQueue {
+    /// Returns the most recent gaps between updates that were recorded as a
+    /// `Drift`, oldest first.
+    pub fn drift(&self) -> &[Drift] {
+        &self.drift
+    }
+
+    /// Records a long gap between updates, dropping the oldest record if
+    /// there are more than `DRIFT_LIMIT`.
+    pub(crate) fn record_drift(&mut self, drift: Drift) {
+        self.drift.push(drift);
+        if self.drift.len() > DRIFT_LIMIT {
+            self.drift.remove(0);
+        }
+    }
+
+    /// Charges the time spent on a task to its remaining duration. This is
+    /// the scheduler's bookkeeping rather than a change to the task, so
+    /// neither the task's nor the queue's revision changes; otherwise their
+    /// ETags would change on every update.
+    pub(crate) fn charge(&mut self, id: usize, elapsed: TimeDelta) {
+        let Some(task) = self.tasks.iter_mut().find(|t| t.id == id) else {
+            return;
+        };
+        match task.duration.checked_sub(&elapsed) {
+            Some(duration) => task.duration = duration,
+            None => {
+                error!("Task duration overflowed! Something is seriously wrong.");
+            }
+        }
+    }
+}
+
+/// A `Clock` measures the time between updates.
+struct Clock {
+    instant: Instant,
+    wall: DateTime<Utc>,
+}
+
+impl Clock {
+    fn new(instant: Instant, wall: DateTime<Utc>) -> Self {
+        Self { instant, wall }
+    }
+
+    /// Returns the time to charge the active task since the last update, and a
+    /// `Drift` if the gap was longer than `limit` or the wall clock ran ahead
+    /// of the monotonic clock. Otherwise the time the monotonic clock measured
+    /// is charged; the wall clock alone is never trusted, since it may also be
+    /// stepped forward, e.g. by NTP.
+    fn advance(
+        &mut self,
+        instant: Instant,
+        wall: DateTime<Utc>,
+        limit: TimeDelta,
+        policy: GapPolicy,
+    ) -> (TimeDelta, Option<Drift>) {
+        let monotonic =
+            TimeDelta::from_std(instant.duration_since(self.instant)).unwrap_or(TimeDelta::MAX);
+        // on some platforms the monotonic clock stops while the machine is
+        // suspended, which only the wall clock notices
+        let suspended = match (wall - self.wall).checked_sub(&monotonic) {
+            Some(suspended) if suspended > CLOCK_TOLERANCE => suspended,
+            _ => TimeDelta::zero(),
+        };
+        let gap = monotonic.checked_add(&suspended).unwrap_or(TimeDelta::MAX);
+        self.instant = instant;
+        self.wall = wall;
+
+        if suspended.is_zero() && monotonic <= limit {
+            return (monotonic, None);
+        }
+
+        let charged = match policy {
+            GapPolicy::Cap => gap.min(limit),
+            GapPolicy::Skip if monotonic <= limit => monotonic,
+            GapPolicy::Skip => TimeDelta::zero(),
+            GapPolicy::Charge => gap,
+        };
+        let drift = Drift {
+            at: wall.with_timezone(&chrono::Local).naive_local(),
+            gap,
+            charged,
+        };
+        (charged, Some(drift))
+    }
+}
+
+/// The completed tasks taken out of a queue by a clean-up, which are yet to be
+/// archived.
+struct Archival {
+    user: String,
+    workspace: String,
+    queue: SharedQueue,
+    tasks: Vec<Task>,
+}
+
+/// A completed task as it is written to the archive file.
+#[derive(Serialize)]
+struct Archived<'a> {
+    user: &'a str,
+    workspace: &'a str,
+    archived_at: NaiveDateTime,
+    task: &'a Task,
 }
 
 /// `Scheduler` handles all task scheduling logic. It will update the active
-/// task based on the queue priority on a fixed timeout.
+/// task of every queue in the registry based on the queue's priority on a
+/// fixed timeout, and whenever the queues change. The active task is recorded
+/// in the shared queue so the server can report it.
 pub struct Scheduler {
-    tasks: SharedQueue,
-    active_task: Option<Task>,
+    registry: SharedRegistry,
+    events: EventBus,
+    notifiers: Vec<Arc<dyn Notifier>>,
 }
 
 impl Scheduler {
-    /// Creates a new `Scheduler` with the given task queue.
+    /// Creates a new `Scheduler` with the given task queue, owned by the
+    /// default user.
     pub fn with_queue(queue: SharedQueue) -> Self {
+        Self::with_registry(Arc::new(Mutex::new(Registry::with_queue(queue))))
+    }
+
+    /// Creates a new `Scheduler` that schedules every queue in the given
+    /// registry. This should be the same registry given to the `Server`.
+    pub fn with_registry(registry: SharedRegistry) -> Self {
         Self {
-            tasks: Arc::clone(&queue),
-            active_task: None,
+            registry,
+            events: EventBus::new(),
+            notifiers: vec![Arc::new(LogNotifier)],
         }
     }
 
-    /// Updates the scheduling logic on a timed loop. The `sigterm` parameter
-    /// should be set to `true` when the program exits, at which point all data
-    /// will be serialized and written to disk.
+    /// Adds a notifier that deadline notifications will be delivered to, and
+    /// returns the `Scheduler`. Notifications are always logged.
+    pub fn with_notifier<N: Notifier + 'static>(mut self, notifier: N) -> Self {
+        self.notifiers.push(Arc::new(notifier));
+        self
+    }
+
+    /// Sets the event bus the scheduler publishes changes to, and returns it.
+    /// This should be the same bus given to the `Server`.
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Updates the scheduling logic every `scheduler_timeout`, and as soon as
+    /// a change to the queues is published on the event bus. The `sigterm`
+    /// parameter should be set to `true` when the program exits, at which
+    /// point all data will be serialized and written to disk. If a `Server`
+    /// sharing the registry is running, the write waits until it has stopped
+    /// (see `Server::run()`), so no change it accepted is lost, or for at
+    /// most `SHUTDOWN_TIMEOUT`.
+    ///
+    /// The scheduler never blocks the runtime: it waits on async timers, and
+    /// writes to disk and delivers notifications on the blocking thread pool.
+    /// The queues are behind std mutexes rather than async ones, which is
+    /// safe because neither the scheduler nor the server ever holds one
+    /// across an `.await`; they are only locked for short, synchronous
+    /// stretches. It can share a single-threaded runtime with the `Server`.
     pub async fn run(&mut self, sigterm: Arc<AtomicBool>, config: SchedulerConfig) -> Result<(), SchedulingError> {
         info!("Starting scheduler (disabled)...");
 
+        if config.scheduler_timeout == 0 {
+            return Err(SchedulingError(
+                "The scheduler timeout must be at least 1 millisecond".to_string(),
+            ));
+        }
+
+        let timeout = TimeDelta::milliseconds(config.scheduler_timeout as i64);
+        let limit = TimeDelta::seconds(config.time_accounting.max_gap as i64).max(timeout);
+        let mut clock = Clock::new(Instant::now(), Utc::now());
+        let mut ticks = tokio::time::interval(Duration::from_millis(config.scheduler_timeout as u64));
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut changes = self.events.subscribe();
+        let terminated = vars::terminated(&sigterm);
+        tokio::pin!(terminated);
+
         let mut start = Instant::now();
-        while !sigterm.load(Ordering::Relaxed) {
-            let mut queue = self.tasks.lock()?;
-
-            // if the queue is disabled, skip the iteration.
-            if queue.enabled {
-                self.active_task = queue.select();
-
-                if let Some(task) = self.active_task.as_mut() {
-                    debug!("Active task: {} (ID: {})", task.title, task.id);
-
-                    let task_mut = queue.get_mut(task.id).ok_or(SchedulingError(
-                        "Active task is not in the queue.".to_string(),
-                    ))?;
-                    match task_mut
-                        .duration
-                        .checked_sub(&TimeDelta::milliseconds(config.scheduler_timeout as i64))
-                    {
-                        Some(duration) => task_mut.duration = duration,
-                        None => {
-                            error!("Task duration overflowed! Something is seriously wrong.");
-                        }
+        loop {
+            tokio::select! {
+                biased;
+                _ = &mut terminated => break,
+                _ = ticks.tick() => {}
+                change = changes.recv() => match change {
+                    Ok(tagged) if !Self::is_change(&tagged.event) => continue,
+                    _ => {
+                        // handle a burst of changes, e.g. from a batch, at once
+                        while !matches!(
+                            changes.try_recv(),
+                            Err(TryRecvError::Empty | TryRecvError::Closed)
+                        ) {}
                     }
-                } else {
-                    debug!("No active task.");
-                }
+                },
+            }
+
+            // the active task is charged the time that actually passed,
+            // including time spent writing to disk
+            let (elapsed, drift) = clock.advance(
+                Instant::now(),
+                Utc::now(),
+                limit,
+                config.time_accounting.policy,
+            );
+            if let Some(drift) = &drift {
+                info!(
+                    "{}s passed since the last update, charging {}s",
+                    drift.gap.num_seconds(),
+                    drift.charged.num_seconds()
+                );
             }
 
-            drop(queue);
+            for (user, workspace, queue) in self.queues()? {
+                let events = self.events.scoped(&user, &workspace);
+                if let Some(drift) = &drift {
+                    queue.lock()?.record_drift(drift.clone());
+                    events.publish(Event::ClockDrift(drift.clone()));
+                }
+                let notifications = Self::tick(&queue, &events, &config, elapsed)?;
+
+                // notifications are delivered once the lock is released, and
+                // without waiting for them, since notifiers may be slow
+                self.notify(notifications);
+            }
 
             // if it's been longer than the write timeout, write the contents
-            // of the queue to disk
+            // of the queues to disk
             if start.elapsed() >= Duration::from_secs(60 * config.write_timeout as u64) {
-                self.save(&config.data_path)?;
+                let registry = Arc::clone(&self.registry);
+                let config = config.clone();
+                Self::blocking(move || Self::clean_up_and_save(&registry, &config)).await?;
                 start = Instant::now();
             }
+        }
 
-            sleep(Duration::from_millis(config.scheduler_timeout as u64));
+        // let the server finish its in-flight requests first, but don't wait
+        // forever on one that hangs
+        if self.registry.lock()?.is_serving() {
+            info!("Waiting for the server to stop...");
+        }
+        let stopped = tokio::time::timeout(SHUTDOWN_TIMEOUT, async {
+            while self.registry.lock()?.is_serving() {
+                tokio::time::sleep(Duration::from_millis(100)).await;
+            }
+            Ok::<(), SchedulingError>(())
+        });
+        match stopped.await {
+            Ok(result) => result?,
+            Err(_) => error!(
+                "The server didn't stop within {}s, writing to disk anyway",
+                SHUTDOWN_TIMEOUT.as_secs()
+            ),
         }
 
-        self.tasks.lock()?.enabled = false;
-        self.save(&config.data_path)?;
+        for (user, workspace, queue) in self.registry.lock()?.entries() {
+            queue.lock()?.set_enabled(false);
+            self.events
+                .scoped(&user, &workspace)
+                .publish(Event::SchedulerDisabled);
+        }
+        let registry = Arc::clone(&self.registry);
+        let path = config.data_path.clone();
+        Self::blocking(move || Self::save(&registry, &path)).await?;
         info!("Exiting...");
 
         Ok(())
     }
 
-    /// Serializes and writes the task data to disk.
-    fn save(&self, path: &PathBuf) -> Result<(), SchedulingError> {
+    /// Returns `true` if the event is a change to a queue that may affect
+    /// which task should be active. The scheduler's own reminders and active
+    /// task changes are ignored, but the `TaskUpdated` it publishes when a
+    /// hold expires can't be told apart from a client's, and costs one extra
+    /// update.
+    fn is_change(event: &Event) -> bool {
+        matches!(
+            event,
+            Event::TaskAdded(_)
+                | Event::TaskUpdated(_)
+                | Event::TaskDeleted(_)
+                | Event::TaskCompleted(_)
+                | Event::SchedulerEnabled
+                | Event::SchedulerDisabled
+                | Event::PriorityChanged(_)
+                | Event::WorkspaceChanged(_)
+        )
+    }
+
+    /// Runs blocking work, such as writing to disk, on the blocking thread
+    /// pool.
+    async fn blocking<F>(work: F) -> Result<(), SchedulingError>
+    where
+        F: FnOnce() -> Result<(), SchedulingError> + Send + 'static,
+    {
+        tokio::task::spawn_blocking(work)
+            .await
+            .map_err(|e| SchedulingError(e.to_string()))?
+    }
+
+    /// Runs a single iteration of the scheduling logic on a queue, and returns
+    /// the notifications that are due.
+    fn tick(
+        queue: &SharedQueue,
+        events: &EventBus,
+        config: &SchedulerConfig,
+        elapsed: TimeDelta,
+    ) -> Result<Vec<Notification>, SchedulingError> {
+        let mut queue = queue.lock()?;
+
+        let now = chrono::Local::now().naive_local();
+        for id in queue.wake(now) {
+            info!("Task {id} is no longer on hold");
+            if let Some(task) = queue.get(id) {
+                events.publish(Event::TaskUpdated(task.clone()));
+            }
+        }
+        if let Some(pin) = queue.expire_pin(now) {
+            info!("Task {} is no longer pinned", pin.id);
+        }
+
+        let mut notifications = Vec::new();
+        for (task, minutes) in queue.due_reminders(now, &config.reminders) {
+            notifications.push(Notification::Approaching {
+                task: task.clone(),
+                minutes,
+            });
+            events.publish(Event::DeadlineApproaching { task, minutes });
+        }
+        for task in queue.newly_overdue(now) {
+            notifications.push(Notification::Overdue(task.clone()));
+            events.publish(Event::DeadlineMissed(task));
+        }
+
+        // the time since the last update was spent on the task that was active
+        // during it, not on the one selected now
+        if queue.enabled && elapsed > TimeDelta::zero() {
+            if let Some(active) = queue.active() {
+                queue.charge(active.task.id(), elapsed);
+            }
+        }
+
+        // if the queue is disabled, skip the iteration.
+        if queue.enabled {
+            let active_task = queue.select_stable(
+                now,
+                TimeDelta::minutes(config.min_run_time as i64),
+                config.preemption_threshold,
+            );
+            if queue.set_active(active_task.as_ref().map(|t| t.id()), now) {
+                events.publish(Event::ActiveChanged(queue.active()));
+            }
+
+            if let Some(task) = active_task {
+                debug!("Active task: {} (ID: {})", task.title, task.id);
+            } else {
+                debug!("No active task.");
+            }
+        } else if queue.set_active(None, now) {
+            events.publish(Event::ActiveChanged(None));
+        }
+
+        Ok(notifications)
+    }
+
+    /// Returns the queue of every user's active workspace. The registry lock
+    /// is released before returning, so the server can keep adding users while
+    /// the queues are being scheduled.
+    fn queues(&self) -> Result<Vec<(String, String, SharedQueue)>, SchedulingError> {
+        Ok(self.registry.lock()?.queues())
+    }
+
+    /// Delivers notifications to every notifier on the blocking thread pool,
+    /// in the background so that a slow notifier doesn't hold up scheduling.
+    /// A failing notifier does not stop the others.
+    fn notify(&self, notifications: Vec<Notification>) {
+        if notifications.is_empty() {
+            return;
+        }
+
+        let notifiers = self.notifiers.clone();
+        let delivery = tokio::task::spawn_blocking(move || {
+            for notification in &notifications {
+                for notifier in &notifiers {
+                    if let Err(e) = notifier.notify(notification) {
+                        error!("Unable to deliver notification: {e}");
+                    }
+                }
+            }
+        });
+        tokio::spawn(async move {
+            if let Err(e) = delivery.await {
+                error!("Unable to deliver notifications: {e}");
+            }
+        });
+    }
+
+    /// Purges tasks that have been in the trash longer than the retention
+    /// period, and takes completed tasks older than `archive_after` days out
+    /// of the queue, in every workspace. The taken tasks are returned, to be
+    /// archived once the queues are saved (see `clean_up_and_save()`).
+    fn clean_up(
+        registry: &SharedRegistry,
+        config: &SchedulerConfig,
+    ) -> Result<Vec<Archival>, SchedulingError> {
+        let now = chrono::Local::now().naive_local();
+        let mut archivals = Vec::new();
+
+        let entries = registry.lock()?.entries();
+        for (user, workspace, shared) in entries {
+            let mut queue = shared.lock()?;
+
+            if let Some(days) = config.trash_retention {
+                let purged = queue.purge_trash(now, TimeDelta::days(days as i64));
+                if !purged.is_empty() {
+                    debug!("Purged {} tasks from the trash of {user}/{workspace}", purged.len());
+                }
+            }
+
+            if let Some(days) = config.archive_after {
+                let tasks = queue.archive_completed(now, TimeDelta::days(days as i64));
+                if !tasks.is_empty() {
+                    drop(queue);
+                    archivals.push(Archival {
+                        user,
+                        workspace,
+                        queue: shared,
+                        tasks,
+                    });
+                }
+            }
+        }
+
+        Ok(archivals)
+    }
+
+    /// Cleans up the queues and writes them to disk. The completed tasks
+    /// taken out of the queues are only appended to the archive once the
+    /// queues without them are saved, so a failed save or a crash in between
+    /// never archives a task twice. If either step fails, the tasks are put
+    /// back and archived next time.
+    fn clean_up_and_save(
+        registry: &SharedRegistry,
+        config: &SchedulerConfig,
+    ) -> Result<(), SchedulingError> {
+        // a failed clean-up must not keep the queues from being saved
+        let archivals = Self::clean_up(registry, config).unwrap_or_else(|e| {
+            error!("Unable to clean up the queues: {e}");
+            Vec::new()
+        });
+        let saved = Self::save(registry, &config.data_path);
+
+        let now = chrono::Local::now().naive_local();
+        let archive_path = config
+            .archive_path
+            .clone()
+            .unwrap_or_else(|| config.data_path.with_extension("archive.jsonl"));
+        for Archival {
+            user,
+            workspace,
+            queue,
+            tasks,
+        } in archivals
+        {
+            if saved.is_ok() {
+                debug!(
+                    "Archiving {} completed tasks of {user}/{workspace}",
+                    tasks.len()
+                );
+                match Self::archive(&archive_path, &user, &workspace, now, &tasks) {
+                    Ok(()) => continue,
+                    Err(e) => error!("Unable to archive the tasks of {user}/{workspace}: {e}"),
+                }
+            }
+
+            // put the tasks back so they aren't lost, and try again next time
+            let mut queue = queue.lock()?;
+            for task in tasks {
+                queue.add_completed(task);
+            }
+        }
+
+        saved
+    }
+
+    /// Appends completed tasks to the archive file.
+    fn archive(
+        path: &Path,
+        user: &str,
+        workspace: &str,
+        now: NaiveDateTime,
+        tasks: &[Task],
+    ) -> Result<(), SchedulingError> {
+        let mut data = Vec::new();
+        for task in tasks {
+            let archived = Archived {
+                user,
+                workspace,
+                archived_at: now,
+                task,
+            };
+            serde_json::to_writer(&mut data, &archived).map_err(|e| SchedulingError(e.to_string()))?;
+            data.push(b'\n');
+        }
+
+        fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .and_then(|mut file| file.write_all(&data))
+            .map_err(|e| SchedulingError(e.to_string()))
+    }
+
+    /// Serializes and writes every workspace's task data to disk, and removes
+    /// the data of deleted workspaces. The default user's default workspace
+    /// is written to `path`, and every other workspace is written next to it
+    /// (see `Registry::path_for()`).
+    fn save(registry: &SharedRegistry, path: &Path) -> Result<(), SchedulingError> {
         info!("Writing data to disk...");
-        let queue = self.tasks.lock()?;
-        let data =
-            serde_json::to_vec(&queue.clone()).map_err(|e| SchedulingError(e.to_string()))?;
-        fs::write(path, &data).map_err(|e| SchedulingError(e.to_string()))
+        let (entries, removed) = {
+            let mut registry = registry.lock()?;
+            (registry.entries(), registry.take_removed())
+        };
+
+        for (user, workspace, queue) in entries {
+            // the queue is only locked while it's serialized, not while it's
+            // written
+            let data =
+                serde_json::to_vec(&*queue.lock()?).map_err(|e| SchedulingError(e.to_string()))?;
+            fs::write(Registry::path_for(path, &user, &workspace), &data)
+                .map_err(|e| SchedulingError(e.to_string()))?;
+        }
+        for (user, workspace) in removed {
+            match fs::remove_file(Registry::path_for(path, &user, &workspace)) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    return Err(SchedulingError(e.to_string()));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::{NaiveTask, Pin, PriorityLevel, TaskQueue};
+    use std::sync::atomic::Ordering;
+
+    #[tokio::test]
+    async fn test_wakes_on_changes() {
+        let dir = std::env::temp_dir().join(format!("taskscheduler-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // the timeout is long enough that only a change can wake the scheduler
+        let config: SchedulerConfig = serde_json::from_value(serde_json::json!({
+            "data_path": dir.join("tasks.json"),
+            "write_timeout": 60,
+            "scheduler_timeout": 60_000,
+        }))
+        .unwrap();
+
+        let queue = Arc::new(Mutex::new(TaskQueue::new()));
+        let events = EventBus::new();
+        let mut scheduler = Scheduler::with_queue(Arc::clone(&queue)).with_events(events.clone());
+        let sigterm = Arc::new(AtomicBool::new(false));
+        // on this single-threaded runtime, the test only gets to run again if
+        // the scheduler doesn't block it
+        let running = tokio::spawn({
+            let sigterm = Arc::clone(&sigterm);
+            async move { scheduler.run(sigterm, config).await }
+        });
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        {
+            let mut queue = queue.lock().unwrap();
+            let task = Task::from_naive(
+                NaiveTask::new(
+                    "Task".to_string(),
+                    chrono::Local::now().naive_local() + TimeDelta::days(1),
+                    TimeDelta::hours(1),
+                    PriorityLevel::Normal,
+                ),
+                queue.new_id(),
+            );
+            queue.add(task.clone());
+            queue.set_enabled(true);
+            events.publish(Event::TaskAdded(task));
+        }
+        tokio::time::timeout(Duration::from_secs(5), async {
+            while queue.lock().unwrap().active().is_none() {
+                tokio::time::sleep(Duration::from_millis(10)).await;
+            }
+        })
+        .await
+        .expect("the scheduler didn't wake");
+
+        sigterm.store(true, Ordering::Relaxed);
+        tokio::time::timeout(Duration::from_secs(5), running)
+            .await
+            .expect("the scheduler didn't stop")
+            .unwrap()
+            .unwrap();
+        assert!(dir.join("tasks.json").exists());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_zero_timeout() {
+        let config: SchedulerConfig = serde_json::from_value(serde_json::json!({
+            "data_path": "tasks.json",
+            "write_timeout": 60,
+            "scheduler_timeout": 0,
+        }))
+        .unwrap();
+        let mut scheduler = Scheduler::with_queue(Arc::new(Mutex::new(TaskQueue::new())));
+        let sigterm = Arc::new(AtomicBool::new(false));
+        assert!(scheduler.run(sigterm, config).await.is_err());
+    }
+
+    #[test]
+    fn test_clean_up() {
+        let dir = std::env::temp_dir().join(format!("taskscheduler-clean-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // the archive can't be written, as it's a directory
+        let config: SchedulerConfig = serde_json::from_value(serde_json::json!({
+            "data_path": dir.join("tasks.json"),
+            "write_timeout": 60,
+            "scheduler_timeout": 60_000,
+            "archive_path": dir,
+            "archive_after": 0,
+            "trash_retention": 0,
+        }))
+        .unwrap();
+
+        let queue = Arc::new(Mutex::new(TaskQueue::new()));
+        {
+            let mut queue = queue.lock().unwrap();
+            queue.add(crate::test::task(1));
+            queue.add(crate::test::task(2));
+            queue.complete(1).unwrap();
+            queue.delete(2).unwrap();
+        }
+        let registry = Arc::new(Mutex::new(Registry::with_queue(Arc::clone(&queue))));
+
+        // the trash is still purged, and the tasks that couldn't be archived
+        // are kept
+        assert!(Scheduler::clean_up_and_save(&registry, &config).is_ok());
+        assert!(queue.lock().unwrap().trash().is_empty());
+        assert!(queue.lock().unwrap().get_completed(1).is_some());
+
+        // nothing is archived if the queue can't be saved, as it's a
+        // directory
+        let archive = dir.join("tasks.archive.jsonl");
+        let config = SchedulerConfig {
+            data_path: dir.clone(),
+            archive_path: Some(archive.clone()),
+            ..config
+        };
+        assert!(Scheduler::clean_up_and_save(&registry, &config).is_err());
+        assert!(!archive.exists());
+        assert!(queue.lock().unwrap().get_completed(1).is_some());
+
+        // once it's saved, the tasks are archived exactly once
+        let config = SchedulerConfig {
+            data_path: dir.join("tasks.json"),
+            ..config
+        };
+        assert!(Scheduler::clean_up_and_save(&registry, &config).is_ok());
+        assert!(Scheduler::clean_up_and_save(&registry, &config).is_ok());
+        assert_eq!(fs::read_to_string(&archive).unwrap().lines().count(), 1);
+        assert!(queue.lock().unwrap().get_completed(1).is_none());
+        let saved: TaskQueue =
+            serde_json::from_slice(&fs::read(dir.join("tasks.json")).unwrap()).unwrap();
+        assert!(saved.get_completed(1).is_none());
+        fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_tick_charges_previous_task() {
+        let config: SchedulerConfig = serde_json::from_value(serde_json::json!({
+            "data_path": "tasks.json",
+            "write_timeout": 60,
+            "scheduler_timeout": 60_000,
+        }))
+        .unwrap();
+        let now = chrono::Local::now().naive_local();
+        let queue = Arc::new(Mutex::new(TaskQueue::new()));
+        {
+            let mut queue = queue.lock().unwrap();
+            queue.add(crate::test::task(1));
+            queue.add(crate::test::task(2));
+            queue.set_active(Some(1), now);
+            queue.pin(Pin { id: 2, until: None }).unwrap();
+        }
+        let events = EventBus::new();
+        let elapsed = TimeDelta::minutes(10);
+
+        // nothing is charged while the queue is disabled
+        Scheduler::tick(&queue, &events, &config, elapsed).unwrap();
+        assert_eq!(
+            queue.lock().unwrap().get(1).unwrap().duration,
+            TimeDelta::hours(1)
+        );
+
+        // task 1 was active until now, so it's charged even though the pinned
+        // task takes over
+        {
+            let mut queue = queue.lock().unwrap();
+            queue.set_enabled(true);
+            queue.set_active(Some(1), now);
+        }
+        Scheduler::tick(&queue, &events, &config, elapsed).unwrap();
+        {
+            let queue = queue.lock().unwrap();
+            assert_eq!(queue.get(1).unwrap().duration, TimeDelta::minutes(50));
+            assert_eq!(queue.get(2).unwrap().duration, TimeDelta::hours(1));
+            assert_eq!(queue.active().unwrap().task.id(), 2);
+        }
+
+        // charging the task that stays active doesn't change any revision, so
+        // clients polling with ETags aren't told about every update
+        let revisions = |queue: &SharedQueue| {
+            let queue = queue.lock().unwrap();
+            (queue.revision(), queue.get(2).unwrap().revision())
+        };
+        let before = revisions(&queue);
+        Scheduler::tick(&queue, &events, &config, elapsed).unwrap();
+        assert_eq!(revisions(&queue), before);
+        assert_eq!(
+            queue.lock().unwrap().get(2).unwrap().duration,
+            TimeDelta::minutes(50)
+        );
+    }
+
+    #[test]
+    fn test_clock() {
+        let (instant, wall) = (Instant::now(), Utc::now());
+        let limit = TimeDelta::minutes(5);
+        let mut clock = Clock::new(instant, wall);
+
+        // short gaps are charged as the monotonic clock measured them, and a
+        // wall clock running slightly ahead is only jitter
+        let (charged, drift) = clock.advance(
+            instant + Duration::from_millis(1500),
+            wall + TimeDelta::milliseconds(2000),
+            limit,
+            GapPolicy::Cap,
+        );
+        assert_eq!(charged, TimeDelta::milliseconds(1500));
+        assert!(drift.is_none());
+
+        // a wall clock stepped forward isn't trusted, even within the limit
+        let (charged, drift) = clock.advance(
+            instant + Duration::from_secs(3),
+            wall + TimeDelta::seconds(62),
+            limit,
+            GapPolicy::Skip,
+        );
+        assert_eq!(charged, TimeDelta::milliseconds(1500));
+        let drift = drift.unwrap();
+        assert_eq!(drift.gap, TimeDelta::seconds(60));
+        assert_eq!(drift.charged, TimeDelta::milliseconds(1500));
+
+        // a suspend the monotonic clock didn't notice is capped and recorded
+        let (charged, drift) = clock.advance(
+            instant + Duration::from_secs(4),
+            wall + TimeDelta::hours(2),
+            limit,
+            GapPolicy::Cap,
+        );
+        assert_eq!(charged, limit);
+        let drift = drift.unwrap();
+        assert_eq!(drift.gap, TimeDelta::hours(2) - TimeDelta::seconds(62));
+        assert_eq!(drift.charged, limit);
+
+        // a long gap the monotonic clock noticed isn't charged at all when
+        // skipped
+        let (charged, drift) = clock.advance(
+            instant + Duration::from_secs(3604),
+            wall + TimeDelta::hours(3),
+            limit,
+            GapPolicy::Skip,
+        );
+        assert_eq!(charged, TimeDelta::zero());
+        assert_eq!(drift.unwrap().gap, TimeDelta::hours(1));
     }
 }
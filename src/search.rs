@@ -0,0 +1,288 @@
+use crate::query::TaskStatus;
+use crate::{Task, TaskQueue};
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashMap, HashSet};
+
+/// The number of results returned if no limit is given.
+pub const DEFAULT_LIMIT: usize = 20;
+
+/// The score of a search term that matches a word exactly.
+const EXACT: u32 = 3;
+
+/// The score of a search term that is a prefix of a word.
+const PREFIX: u32 = 2;
+
+/// The score of a search term that is a slight misspelling of a word.
+const FUZZY: u32 = 1;
+
+/// A `SearchIndex` maps every word in the titles of a queue's tasks to the
+/// tasks containing it.
+#[derive(Clone, Default)]
+pub struct SearchIndex {
+    words: BTreeMap<String, Vec<(TaskStatus, usize)>>,
+
+    /// The title each task was indexed under
+    titles: HashMap<(TaskStatus, usize), String>,
+}
+
+/// The query parameters of a search.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+    pub limit: Option<usize>,
+}
+
+/// A task matching a search, along with its score. Higher scores are better
+/// matches.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SearchHit {
+    pub status: TaskStatus,
+    pub score: u32,
+    #[serde(flatten)]
+    pub task: Task,
+}
+
+/// The results of a search, best match first.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SearchResults {
+    pub tasks: Vec<SearchHit>,
+
+    /// The number of matching tasks, including those beyond the limit
+    pub total: usize,
+}
+
+impl SearchIndex {
+    /// Builds an index over the titles of every open and completed task in
+    /// the queue.
+    pub fn build(queue: &TaskQueue) -> Self {
+        let mut index = Self::default();
+        index.update(queue);
+        index
+    }
+
+    /// Brings the index up to date with the queue. Only the tasks that were
+    /// added, removed or renamed since the last update are reindexed.
+    pub fn update(&mut self, queue: &TaskQueue) {
+        let open = queue.tasks.iter().map(|t| (TaskStatus::Open, t));
+        let completed = queue.completed.iter().map(|t| (TaskStatus::Completed, t));
+
+        let mut current = HashSet::new();
+        for (status, task) in open.chain(completed) {
+            let key = (status, task.id);
+            current.insert(key);
+            if self.titles.get(&key) != Some(&task.title) {
+                self.remove(key);
+                self.insert(key, &task.title);
+            }
+        }
+
+        let stale: Vec<_> = self
+            .titles
+            .keys()
+            .filter(|key| !current.contains(key))
+            .copied()
+            .collect();
+        for key in stale {
+            self.remove(key);
+        }
+    }
+
+    /// Adds a task's title to the index.
+    fn insert(&mut self, key: (TaskStatus, usize), title: &str) {
+        for word in tokenize(title) {
+            let entries = self.words.entry(word).or_default();
+            if !entries.contains(&key) {
+                entries.push(key);
+            }
+        }
+        self.titles.insert(key, title.to_string());
+    }
+
+    /// Removes a task from the index, along with any words no other task
+    /// contains.
+    fn remove(&mut self, key: (TaskStatus, usize)) {
+        let title = match self.titles.remove(&key) {
+            Some(title) => title,
+            None => return,
+        };
+        for word in tokenize(&title) {
+            if let Some(entries) = self.words.get_mut(&word) {
+                entries.retain(|entry| *entry != key);
+                if entries.is_empty() {
+                    self.words.remove(&word);
+                }
+            }
+        }
+    }
+
+    /// Scores every task matching at least one of the search terms. Each term
+    /// contributes the score of its best match in the task's title: an exact
+    /// word, the prefix of a word, or a word within a small edit distance.
+    pub fn search(&self, terms: &[String]) -> HashMap<(TaskStatus, usize), u32> {
+        let mut scores = HashMap::new();
+
+        for term in terms {
+            let mut best: HashMap<(TaskStatus, usize), u32> = HashMap::new();
+            let mut record = |entries: &Vec<(TaskStatus, usize)>, score: u32| {
+                for entry in entries {
+                    let best = best.entry(*entry).or_insert(0);
+                    *best = (*best).max(score);
+                }
+            };
+
+            for (word, entries) in self.words.range(term.clone()..) {
+                if !word.starts_with(term.as_str()) {
+                    break;
+                }
+                record(entries, if word == term { EXACT } else { PREFIX });
+            }
+
+            let max_distance = match term.chars().count() {
+                0..=3 => 0,
+                4..=7 => 1,
+                _ => 2,
+            };
+            if max_distance > 0 {
+                for (word, entries) in &self.words {
+                    if !word.starts_with(term.as_str()) && distance(term, word) <= max_distance {
+                        record(entries, FUZZY);
+                    }
+                }
+            }
+
+            for (entry, score) in best {
+                *scores.entry(entry).or_insert(0) += score;
+            }
+        }
+
+        scores
+    }
+}
+
+impl TaskQueue {
+    /// Searches the titles of every open and completed task, and returns the
+    /// best matches first. The index is updated first with any tasks added,
+    /// removed or renamed since the last search.
+    pub fn search(&mut self, query: &SearchQuery) -> SearchResults {
+        let mut index = std::mem::take(&mut self.index);
+        index.update(self);
+        let scores = index.search(&tokenize(&query.q));
+        self.index = index;
+
+        let mut tasks: Vec<SearchHit> = scores
+            .into_iter()
+            .filter_map(|((status, id), score)| {
+                let task = match status {
+                    TaskStatus::Open => self.get(id),
+                    TaskStatus::Completed => self.get_completed(id),
+                }?;
+                Some(SearchHit {
+                    status,
+                    score,
+                    task: task.clone(),
+                })
+            })
+            .collect();
+        tasks.sort_by(|a, b| {
+            b.score
+                .cmp(&a.score)
+                .then(a.status.cmp(&b.status))
+                .then(a.task.id().cmp(&b.task.id()))
+        });
+
+        let total = tasks.len();
+        tasks.truncate(query.limit.unwrap_or(DEFAULT_LIMIT));
+
+        SearchResults { tasks, total }
+    }
+}
+
+/// Splits text into lowercase words.
+fn tokenize(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// Returns the Levenshtein distance between two words.
+fn distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut current = vec![i + 1; b.len() + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = previous[j] + usize::from(ca != *cb);
+            current[j + 1] = substitution.min(previous[j + 1] + 1).min(current[j] + 1);
+        }
+        previous = current;
+    }
+
+    previous[b.len()]
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::task;
+
+    #[test]
+    fn test_search() {
+        let mut queue = TaskQueue::new();
+        queue.add(Task {
+            title: "Pay invoice".to_string(),
+            ..task(1)
+        });
+        queue.add(Task {
+            title: "File invoices".to_string(),
+            ..task(2)
+        });
+        queue.add_completed(Task {
+            title: "Send invoice to ACME".to_string(),
+            ..task(1)
+        });
+        queue.add_completed(Task {
+            title: "Groceries".to_string(),
+            ..task(2)
+        });
+
+        let search = |queue: &mut TaskQueue, q: &str| -> Vec<(TaskStatus, usize)> {
+            let query = SearchQuery {
+                q: q.to_string(),
+                limit: None,
+            };
+            queue.search(&query).tasks.iter().map(|h| (h.status, h.task.id())).collect()
+        };
+
+        // exact matches rank above prefix matches
+        assert_eq!(
+            search(&mut queue, "invoice"),
+            vec![(TaskStatus::Open, 1), (TaskStatus::Completed, 1), (TaskStatus::Open, 2)]
+        );
+        // fuzzy
+        assert_eq!(
+            search(&mut queue, "grocerys"),
+            vec![(TaskStatus::Completed, 2)]
+        );
+        // more matching terms rank higher
+        assert_eq!(search(&mut queue, "acme invoice")[0], (TaskStatus::Completed, 1));
+        assert!(search(&mut queue, "xyz").is_empty());
+
+        // the index follows changes to the queue
+        queue.get_mut(1).unwrap().title = "Pay rent".to_string();
+        assert_eq!(search(&mut queue, "rent"), vec![(TaskStatus::Open, 1)]);
+        assert_eq!(search(&mut queue, "pay"), vec![(TaskStatus::Open, 1)]);
+        queue.remove_completed(1);
+        assert!(search(&mut queue, "groceries").is_empty());
+        assert!(!queue.index.words.contains_key("groceries"));
+        assert_eq!(queue.index.titles.len(), 3);
+    }
+
+    #[test]
+    fn test_distance() {
+        assert_eq!(distance("invoice", "invoice"), 0);
+        assert_eq!(distance("invoce", "invoice"), 1);
+        assert_eq!(distance("kitten", "sitting"), 3);
+    }
+}
@@ -1,13 +1,42 @@
-use crate::error::{IOError, SerializationError, ServerError, TaskNotFound};
+use crate::auth::{Auth, Identity, Scope, Token};
+use crate::batch::Operation;
+use crate::error::{
+    ErrorResponse, Forbidden, HistoryError, IOError, InvalidName, MethodNotAllowed,
+    PreconditionFailed, QueryError, SchedulerDisabled, SerializationError, ServerError,
+    TaskNotFound, Unauthorized, UserNotFound, ValidationError, WebhookNotFound, WorkspaceActive,
+    WorkspaceExists, WorkspaceNotFound,
+};
+use crate::events::{Event, EventBus};
+use crate::history::Change;
+use crate::openapi::{self, Endpoint, OperationId, Version};
 use crate::priority::Priority;
+use crate::query::{TaskQuery, TaskStatus};
+use crate::search::SearchQuery;
+use crate::validation::Rules;
+use crate::registry::{
+    is_valid_name, NewWorkspace, Registry, SharedRegistry, DEFAULT_USER, DEFAULT_WORKSPACE,
+};
 use crate::vars;
-use crate::{NaiveTask, SharedQueue, Task, UpdateTask};
+use crate::webhooks::{Dispatcher, NaiveWebhook, WebhookInfo};
+use crate::{
+    Hold, NaivePin, NaiveTask, NaiveUpdate, Pin, SharedQueue, Task, TaskQueue, UpdateTask,
+};
+use futures_util::{SinkExt, StreamExt};
 use piglog::{error, info};
-use serde::Deserialize;
+use serde::{Deserialize, Serialize};
 use std::convert::Infallible;
+use std::error::Error;
+use std::future::Future;
 use std::net::SocketAddr;
-use std::sync::Arc;
-use warp::Filter;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use warp::ws::{Message, WebSocket, Ws};
+use warp::filters::BoxedFilter;
+use warp::http::Method;
+use warp::path::Peek;
+use warp::{Filter, Reply};
 
 #[cfg(feature = "https")]
 use std::path::PathBuf;
@@ -25,185 +54,790 @@ pub struct ServerConfig {
     /// The path to the tls private key (https only)
     #[cfg(feature = "https")]
     pub key_path: PathBuf,
+
+    /// The modeled cost in minutes of switching between tasks, used when
+    /// forecasting the schedule
+    #[serde(default)]
+    pub context_switch_cost: usize,
+
+    /// The API tokens allowed to access the server. If empty, authentication
+    /// is disabled, which is only allowed on a loopback address unless
+    /// `allow_anonymous` is set
+    #[serde(default)]
+    pub tokens: Vec<Token>,
+
+    /// Allows serving a non-loopback address without any tokens, giving
+    /// anyone who can reach the server full access
+    #[serde(default)]
+    pub allow_anonymous: bool,
+
+    /// The rules new and updated tasks must follow
+    #[serde(default)]
+    pub validation: Rules,
+}
+
+/// `SchedulerState` is the state of a queue's scheduler, as read and written
+/// by `/api/v2/scheduler`.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SchedulerState {
+    pub enabled: bool,
 }
 
 /// `Server` handles all communication with clients. This includes waiting for
 /// requests, updating shared resources, and sending responses.
 pub struct Server {
-    tasks: SharedQueue,
+    registry: SharedRegistry,
+    events: EventBus,
+    /// Set when the server starts shutting down, to end the event streams
+    shutdown: Arc<watch::Sender<bool>>,
+}
+
+/// `Serving` marks the registry as being served for as long as it's alive, so
+/// the `Scheduler` knows to wait before its final write.
+struct Serving(SharedRegistry);
+
+impl Serving {
+    fn start(registry: SharedRegistry) -> Result<Self, ServerError> {
+        registry
+            .lock()
+            .map_err(|_| ServerError("Unable to lock the registry".to_string()))?
+            .start_serving();
+        Ok(Self(registry))
+    }
+}
+
+impl Drop for Serving {
+    fn drop(&mut self) {
+        if let Ok(mut registry) = self.0.lock() {
+            registry.stop_serving();
+        }
+    }
+}
+
+/// A `Session` is the user and queue a request operates on, along with the
+/// scope of the token it was authenticated with.
+#[derive(Clone)]
+struct Session {
+    /// Identifies the request in the server logs and in its reply
+    request_id: String,
+    scope: Scope,
+    user: String,
+    /// The name of the workspace `queue` belongs to
+    workspace: String,
+    queue: SharedQueue,
+    registry: SharedRegistry,
+    events: EventBus,
+}
+
+impl Session {
+    /// Returns the session's queue and event bus if the request has at least
+    /// the given scope, otherwise a `Forbidden` rejection.
+    fn authorize(&self, scope: Scope) -> Result<(SharedQueue, EventBus), warp::Rejection> {
+        if self.scope >= scope {
+            Ok((Arc::clone(&self.queue), self.events.clone()))
+        } else {
+            Err(warp::reject::custom(Forbidden))
+        }
+    }
+
+    /// Same as `authorize()`, but returns the session's user and the registry
+    /// instead of the queue, for requests that operate on the user's
+    /// workspaces.
+    fn authorize_user(
+        &self,
+        scope: Scope,
+    ) -> Result<(String, SharedRegistry, EventBus), warp::Rejection> {
+        if self.scope >= scope {
+            Ok((
+                self.user.clone(),
+                Arc::clone(&self.registry),
+                self.events.clone(),
+            ))
+        } else {
+            Err(warp::reject::custom(Forbidden))
+        }
+    }
+}
+
+/// A request's `Session` before it is resolved: the user and workspace given
+/// in its path, if any, and what resolving it needs.
+struct Unresolved {
+    request_id: String,
+    user: Option<String>,
+    workspace: Option<String>,
+    registry: SharedRegistry,
+    events: EventBus,
+}
+
+/// Adapts a handler taking the `Session` first to a route that extracts the
+/// request's identity first and ends in the `Unresolved` session, e.g.
+/// `handler!(Self::get_task, id, if_none_match)`. The session is resolved once
+/// everything else has matched, and the handler's result is replied with (see
+/// `Server::respond()`).
+macro_rules! handler {
+    ($handler:expr $(, $arg:ident)*) => {
+        |identity: Identity, $($arg,)* unresolved: Unresolved| {
+            Server::respond(unresolved.request_id.clone(), async move {
+                let session = Server::resolve(identity, unresolved)?;
+                $handler(session $(, $arg)*).await
+            })
+        }
+    };
 }
 
 impl Server {
-    /// Creates a new `Server` with the given task queue.
+    /// Creates a new `Server` with the given task queue, owned by the default
+    /// user.
     pub fn with_queue(queue: SharedQueue) -> Self {
+        Self::with_registry(Arc::new(Mutex::new(Registry::with_queue(queue))))
+    }
+
+    /// Creates a new `Server` serving every queue in the given registry. This
+    /// should be the same registry given to the `Scheduler`.
+    pub fn with_registry(registry: SharedRegistry) -> Self {
         Self {
-            tasks: Arc::clone(&queue),
+            registry,
+            events: EventBus::new(),
+            shutdown: Arc::new(watch::channel(false).0),
         }
     }
 
-    /// Spawns a new thread and begin listening for requests. This thread does
-    /// *not* exit gracefully as it has no cleanup, so you should exit the
-    /// thread forcibly through whatever async runtime you're using.
-    pub async fn run(&mut self, config: ServerConfig) -> Result<(), ServerError> {
+    /// Sets the event bus the server publishes changes to, and returns it.
+    /// This should be the same bus given to the `Scheduler`.
+    pub fn with_events(mut self, events: EventBus) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Listens for requests until the `sigterm` parameter is set to `true`,
+    /// which should be the same flag given to `Scheduler::run`. The server
+    /// then stops accepting connections, ends the event streams, and returns
+    /// once every in-flight request has completed. The scheduler waits for
+    /// this before its final write to disk, so the write includes every
+    /// change the server accepted.
+    pub async fn run(
+        &mut self,
+        sigterm: Arc<AtomicBool>,
+        config: ServerConfig,
+    ) -> Result<(), ServerError> {
         info!("Starting server...");
 
-        let tasks: SharedQueue = Arc::clone(&self.tasks);
-
-        let filter = warp::any().map(move || tasks.clone());
-
-        let post = warp::post()
-            .and(warp::path("api"))
-            .and(warp::path("tasks"))
-            .and(warp::path::end())
-            .and(Self::post_json())
-            .and(filter.clone())
-            .and_then(Self::add_task);
-
-        let get = warp::get()
-            .and(warp::path("api"))
-            .and(warp::path("tasks"))
-            .and(warp::path::end())
-            .and(filter.clone())
-            .and_then(Self::get_tasks);
-
-        let put = warp::put()
-            .and(warp::path("api"))
-            .and(warp::path("tasks"))
-            .and(warp::path::end())
-            .and(Self::put_json())
-            .and(filter.clone())
-            .and_then(Self::update_task);
-
-        let delete = warp::delete()
-            .and(warp::path("api"))
-            .and(warp::path("tasks"))
-            .and(warp::path::param())
-            .and(filter.clone())
-            .and_then(Self::delete_task);
-
-        let enable = warp::post()
-            .and(warp::path("api"))
-            .and(warp::path("tasks"))
-            .and(warp::path("enable"))
-            .and(warp::path::end())
-            .and(filter.clone())
-            .and_then(Self::enable);
-
-        let disable = warp::post()
-            .and(warp::path("api"))
-            .and(warp::path("tasks"))
-            .and(warp::path("disable"))
-            .and(warp::path::end())
-            .and(filter.clone())
-            .and_then(Self::disable);
-
-        let active = warp::get()
-            .and(warp::path("api"))
-            .and(warp::path("tasks"))
-            .and(warp::path("active"))
-            .and(warp::path::end())
-            .and(filter.clone())
-            .and_then(Self::active);
-
-        let status = warp::get()
-            .and(warp::path("api"))
-            .and(warp::path("tasks"))
-            .and(warp::path("status"))
-            .and(warp::path::end())
-            .and(filter.clone())
-            .and_then(Self::status);
-
-        let set_priority = warp::put()
-            .and(warp::path("api"))
-            .and(warp::path("tasks"))
-            .and(warp::path("priority"))
-            .and(warp::path::end())
-            .and(Self::priority_json())
-            .and(filter.clone())
-            .and_then(Self::set_priority);
-
-        let get_priority = warp::get()
-            .and(warp::path("api"))
-            .and(warp::path("tasks"))
-            .and(warp::path("priority"))
-            .and(warp::path::end())
-            .and(filter.clone())
-            .and_then(Self::get_priority);
-
-        let complete = warp::put()
-            .and(warp::path("api"))
-            .and(warp::path("tasks"))
-            .and(warp::path("complete"))
-            .and(warp::path::param())
-            .and(filter.clone())
-            .and_then(Self::complete);
-
-        let del_complete = warp::delete()
-            .and(warp::path("api"))
-            .and(warp::path("tasks"))
-            .and(warp::path("complete"))
-            .and(warp::path::param())
-            .and(filter.clone())
-            .and_then(Self::del_complete);
-
-        let routes = post
-            .or(get)
-            .or(put)
-            .or(delete)
-            .or(enable)
-            .or(disable)
-            .or(active)
-            .or(status)
-            .or(set_priority)
-            .or(get_priority)
-            .or(complete)
-            .or(del_complete)
-            .recover(Self::handle_rejection);
+        if config.tokens.is_empty() && !config.address.ip().is_loopback() && !config.allow_anonymous
+        {
+            return Err(ServerError(
+                "API tokens are required to serve a non-loopback address (or set allow_anonymous)"
+                    .to_string(),
+            ));
+        }
+
+        let routes = self.routes(&config);
 
         if !vars::is_available(config.address) {
             return Err(ServerError("Address is already in use".to_string()));
         }
 
-        info!("Server listening on {}", config.address);
+        self.shutdown.send_replace(false);
+        let shutdown = Arc::clone(&self.shutdown);
+        let signal = async move {
+            vars::terminated(&sigterm).await;
+            info!("Shutting down server...");
+            // event streams never end on their own, so they would keep the
+            // server waiting forever
+            shutdown.send_replace(true);
+        };
+
+        let _serving = Serving::start(Arc::clone(&self.registry))?;
+        let dispatcher =
+            tokio::spawn(Dispatcher::new(Arc::clone(&self.registry), self.events.clone()).run());
+
         #[cfg(feature = "https")]
-        warp::serve(routes)
+        let (address, server) = warp::serve(routes)
             .tls()
             .cert_path(config.cert_path)
             .key_path(config.key_path)
-            .run(config.address).await;
+            .bind_with_graceful_shutdown(config.address, signal);
 
         #[cfg(not(feature = "https"))]
-        warp::serve(routes).run(config.address).await;
+        let (address, server) = warp::serve(routes).bind_with_graceful_shutdown(config.address, signal);
+
+        info!("Server listening on {address}");
+        server.await;
+
+        // the dispatcher would otherwise outlive the server, and running it
+        // again would deliver every event twice
+        dispatcher.abort();
+        let _ = dispatcher.await;
+        info!("Server stopped");
 
         Ok(())
     }
 
+    /// Returns the filter serving every route of the API, with rejections
+    /// turned into error replies.
+    pub(crate) fn routes(
+        &self,
+        config: &ServerConfig,
+    ) -> impl Filter<Extract = (impl Reply,), Error = Infallible> + Clone + Send + Sync + 'static {
+        let auth = Auth::new(config.tokens.clone());
+        if !auth.is_enabled() {
+            info!("No API tokens configured, authentication is disabled");
+        }
+        let session = Self::session(Arc::clone(&self.registry), self.events.clone());
+        let v1 = self.api(Version::V1, &auth, session.clone(), config);
+        let v2 = self.api(Version::V2, &auth, session, config);
+
+        // the unversioned routes are an alias for v1, so existing clients keep
+        // working
+        warp::path("api")
+            .and(
+                warp::path("v1")
+                    .and(v1.clone())
+                    .or(warp::path("v2").and(v2))
+                    .unify()
+                    .or(v1)
+                    .unify(),
+            )
+            .recover(Self::handle_rejection)
+    }
+
+    /// Returns the routes of a version of the API, relative to its prefix.
+    /// Every route is built from one of the version's endpoints (see
+    /// `Version::endpoints()`), so the server serves exactly the routes its
+    /// OpenAPI document describes. Versions share the handler of each
+    /// operation.
+    fn api(
+        &self,
+        version: Version,
+        auth: &Auth,
+        session: BoxedFilter<(Unresolved,)>,
+        config: &ServerConfig,
+    ) -> BoxedFilter<(Box<dyn Reply>,)> {
+        version
+            .endpoints()
+            .iter()
+            .map(|endpoint| self.endpoint(version, endpoint, auth, session.clone(), config))
+            .reduce(|routes, route| routes.or(route).unify().boxed())
+            .expect("every version has endpoints")
+    }
+
+    /// Returns the route serving a single endpoint of a version.
+    fn endpoint(
+        &self,
+        version: Version,
+        endpoint: &'static Endpoint,
+        auth: &Auth,
+        session: BoxedFilter<(Unresolved,)>,
+        config: &ServerConfig,
+    ) -> BoxedFilter<(Box<dyn Reply>,)> {
+        let rules = config.validation.clone();
+        let rules = warp::any().map(move || rules.clone());
+        let switch_cost = chrono::Duration::minutes(config.context_switch_cost as i64);
+        let id = || Self::param::<usize>(endpoint, "id");
+        // requests are authenticated as soon as they match, before their body
+        // is parsed and validated
+        let route = Self::matches(endpoint).and(auth.identity());
+
+        match (version, endpoint.operation) {
+            (_, OperationId::AddTask) => Self::boxed(
+                route
+                    .and(Self::post_json())
+                    .and(rules)
+                    .and(session)
+                    .then(handler!(Self::add_task, task, rules)),
+            ),
+            (_, OperationId::ListTasks) => Self::boxed(
+                route
+                    .and(warp::query::<TaskQuery>())
+                    .and(Self::if_none_match())
+                    .and(session)
+                    .then(handler!(Self::get_tasks, query, if_none_match)),
+            ),
+            (Version::V1, OperationId::UpdateTask) => Self::boxed(
+                route
+                    .and(Self::if_match())
+                    .and(Self::put_json())
+                    .and(rules)
+                    .and(session)
+                    .then(handler!(Self::update_task, if_match, update, rules)),
+            ),
+            (Version::V2, OperationId::UpdateTask) => Self::boxed(
+                route
+                    .and(id())
+                    .and(Self::if_match())
+                    .and(Self::patch_json())
+                    .and(rules)
+                    .and(session)
+                    .then(handler!(Self::patch_task, id, if_match, update, rules)),
+            ),
+            (_, OperationId::ApplyBatch) => Self::boxed(
+                route
+                    .and(Self::batch_json())
+                    .and(rules)
+                    .and(session)
+                    .then(handler!(Self::batch, operations, rules)),
+            ),
+            (_, OperationId::GetHistory) => {
+                Self::boxed(route.and(session).then(handler!(Self::history)))
+            }
+            (_, OperationId::Undo) => Self::boxed(route.and(session).then(handler!(Self::undo))),
+            (_, OperationId::Redo) => Self::boxed(route.and(session).then(handler!(Self::redo))),
+            (_, OperationId::GetTrash) => {
+                Self::boxed(route.and(session).then(handler!(Self::trash)))
+            }
+            (_, OperationId::EmptyTrash) => {
+                Self::boxed(route.and(session).then(handler!(Self::empty_trash)))
+            }
+            (_, OperationId::RestoreTask) => Self::boxed(
+                route
+                    .and(Self::param::<TaskStatus>(endpoint, "status"))
+                    .and(id())
+                    .and(session)
+                    .then(handler!(Self::restore, status, id)),
+            ),
+            (_, OperationId::SearchTasks) => Self::boxed(
+                route
+                    .and(warp::query::<SearchQuery>())
+                    .and(session)
+                    .then(handler!(Self::search, query)),
+            ),
+            (_, OperationId::GetTask) => Self::boxed(
+                route
+                    .and(id())
+                    .and(Self::if_none_match())
+                    .and(session)
+                    .then(handler!(Self::get_task, id, if_none_match)),
+            ),
+            (_, OperationId::DeleteTask) => Self::boxed(
+                route
+                    .and(id())
+                    .and(Self::if_match())
+                    .and(session)
+                    .then(handler!(Self::delete_task, id, if_match)),
+            ),
+            (_, OperationId::GetCompletedTask) => Self::boxed(
+                route
+                    .and(id())
+                    .and(Self::if_none_match())
+                    .and(session)
+                    .then(handler!(Self::get_completed, id, if_none_match)),
+            ),
+            (_, OperationId::CompleteTask) => Self::boxed(
+                route
+                    .and(id())
+                    .and(Self::if_match())
+                    .and(session)
+                    .then(handler!(Self::complete, id, if_match)),
+            ),
+            (_, OperationId::DeleteCompletedTask) => Self::boxed(
+                route
+                    .and(id())
+                    .and(session)
+                    .then(handler!(Self::del_complete, id)),
+            ),
+            (_, OperationId::EnableScheduler) => {
+                Self::boxed(route.and(session).then(handler!(Self::enable)))
+            }
+            (_, OperationId::DisableScheduler) => {
+                Self::boxed(route.and(session).then(handler!(Self::disable)))
+            }
+            (_, OperationId::GetScheduler) => Self::boxed(
+                route
+                    .and(Self::if_none_match())
+                    .and(session)
+                    .then(handler!(Self::scheduler, if_none_match)),
+            ),
+            (_, OperationId::SetScheduler) => Self::boxed(
+                route
+                    .and(Self::scheduler_json())
+                    .and(session)
+                    .then(handler!(Self::set_scheduler, state)),
+            ),
+            (_, OperationId::GetActiveTask) => Self::boxed(
+                route
+                    .and(Self::if_none_match())
+                    .and(session)
+                    .then(handler!(Self::active, if_none_match)),
+            ),
+            (Version::V1, OperationId::PinTask) => Self::boxed(
+                route
+                    .and(id())
+                    .and(Self::pin_json())
+                    .and(session)
+                    .then(handler!(Self::pin, id, pin)),
+            ),
+            (Version::V2, OperationId::PinTask) => Self::boxed(
+                route
+                    .and(Self::pin_body())
+                    .and(session)
+                    .then(handler!(Self::pin_active, pin)),
+            ),
+            (_, OperationId::UnpinTask) => {
+                Self::boxed(route.and(session).then(handler!(Self::unpin)))
+            }
+            (_, OperationId::GetStatus) => Self::boxed(
+                route
+                    .and(Self::if_none_match())
+                    .and(session)
+                    .then(handler!(Self::status, if_none_match)),
+            ),
+            (_, OperationId::GetPriority | OperationId::GetPolicy) => Self::boxed(
+                route
+                    .and(Self::if_none_match())
+                    .and(session)
+                    .then(handler!(Self::get_priority, if_none_match)),
+            ),
+            (_, OperationId::SetPriority | OperationId::SetPolicy) => Self::boxed(
+                route
+                    .and(Self::if_match())
+                    .and(Self::priority_json())
+                    .and(session)
+                    .then(handler!(Self::set_priority, if_match, priority)),
+            ),
+            (_, OperationId::GetForecast) => Self::boxed(
+                route
+                    .and(warp::any().map(move || switch_cost))
+                    .and(session)
+                    .then(handler!(Self::forecast, switch_cost)),
+            ),
+            (_, OperationId::GetDrift) => {
+                Self::boxed(route.and(session).then(handler!(Self::drift)))
+            }
+            (_, OperationId::HoldTask) => Self::boxed(
+                route
+                    .and(id())
+                    .and(Self::hold_json())
+                    .and(session)
+                    .then(handler!(Self::hold, id, hold)),
+            ),
+            (_, OperationId::ReleaseTask) => Self::boxed(
+                route
+                    .and(id())
+                    .and(session)
+                    .then(handler!(Self::release, id)),
+            ),
+            (_, OperationId::MoveTask) => Self::boxed(
+                route
+                    .and(id())
+                    .and(Self::name_json())
+                    .and(session)
+                    .then(handler!(Self::move_task, id, workspace)),
+            ),
+            (_, OperationId::StreamEvents) => Self::boxed(
+                route
+                    .and(self.stopping())
+                    .and(session)
+                    .then(handler!(Self::sse, stopping)),
+            ),
+            (_, OperationId::StreamEventsWs) => Self::boxed(
+                route
+                    .and(warp::ws())
+                    .and(self.stopping())
+                    .and(session)
+                    .then(handler!(Self::ws, ws, stopping)),
+            ),
+            (_, OperationId::AddWebhook) => Self::boxed(
+                route
+                    .and(Self::webhook_json())
+                    .and(session)
+                    .then(handler!(Self::add_webhook, webhook)),
+            ),
+            (_, OperationId::GetWebhooks) => {
+                Self::boxed(route.and(session).then(handler!(Self::get_webhooks)))
+            }
+            (_, OperationId::DeleteWebhook) => Self::boxed(
+                route
+                    .and(id())
+                    .and(session)
+                    .then(handler!(Self::delete_webhook, id)),
+            ),
+            (_, OperationId::GetWorkspaces) => {
+                Self::boxed(route.and(session).then(handler!(Self::get_workspaces)))
+            }
+            (_, OperationId::AddWorkspace) => Self::boxed(
+                route
+                    .and(Self::workspace_json())
+                    .and(session)
+                    .then(handler!(Self::add_workspace, workspace)),
+            ),
+            (_, OperationId::GetActiveWorkspace) => Self::boxed(
+                route
+                    .and(session)
+                    .then(handler!(Self::get_active_workspace)),
+            ),
+            (_, OperationId::SetActiveWorkspace) => Self::boxed(
+                route
+                    .and(Self::name_json())
+                    .and(session)
+                    .then(handler!(Self::set_active_workspace, workspace)),
+            ),
+            (_, OperationId::DeleteWorkspace) => Self::boxed(
+                route
+                    .and(Self::param::<String>(endpoint, "workspace"))
+                    .and(session)
+                    .then(handler!(Self::delete_workspace, workspace)),
+            ),
+            // users and the document itself aren't part of a user's session
+            (_, OperationId::ListUsers) => {
+                let registry = Arc::clone(&self.registry);
+                Self::boxed(
+                    route
+                        .and(warp::any().map(move || Arc::clone(&registry)))
+                        .and(warp::any().map(Self::request_id))
+                        .then(|identity, registry, request_id: String| {
+                            Self::respond(
+                                request_id.clone(),
+                                Self::users(identity, registry, request_id),
+                            )
+                        }),
+                )
+            }
+            (_, OperationId::GetOpenApi) => Self::boxed(
+                Self::matches(endpoint).map(move || warp::reply::json(&openapi::document(version))),
+            ),
+        }
+    }
+
+    /// Boxes the route of an endpoint, so the routes of every endpoint have
+    /// the same type.
+    fn boxed<R: Reply + 'static>(
+        route: impl Filter<Extract = (R,), Error = warp::Rejection> + Clone + Send + Sync + 'static,
+    ) -> BoxedFilter<(Box<dyn Reply>,)> {
+        route.map(|reply| Box::new(reply) as Box<dyn Reply>).boxed()
+    }
+
+    /// Returns a filter that matches requests with the method of an endpoint
+    /// and exactly its path (after any user or workspace prefix), where a
+    /// parameter in braces matches any segment. The path isn't consumed, so
+    /// its parameters can be read with `param()`.
+    fn matches(endpoint: &'static Endpoint) -> BoxedFilter<()> {
+        let path = warp::path::peek()
+            .and_then(move |path: Peek| async move {
+                let pattern: Vec<&str> = endpoint.path.split('/').skip(1).collect();
+                let (_, _, segments) = Self::target(&path);
+                let matches = pattern.len() == segments.len()
+                    && pattern
+                        .iter()
+                        .zip(&segments)
+                        .all(|(pattern, segment)| pattern.starts_with('{') || pattern == segment);
+
+                if matches {
+                    Ok(())
+                } else {
+                    Err(warp::reject::not_found())
+                }
+            })
+            .untuple_one();
+
+        let method = warp::method()
+            .and_then(move |method: Method| async move {
+                if method == endpoint.method {
+                    Ok(())
+                } else {
+                    Err(warp::reject::custom(MethodNotAllowed))
+                }
+            })
+            .untuple_one();
+
+        path.and(method).boxed()
+    }
+
+    /// Returns a filter that extracts the parameter `name` from the path of a
+    /// request matched by `matches()`. A parameter that can't be parsed
+    /// doesn't match, like any other path, and neither does any request if
+    /// the endpoint's path has no such parameter, which leaves the endpoint
+    /// unreachable.
+    fn param<T: FromStr + Send + 'static>(
+        endpoint: &'static Endpoint,
+        name: &'static str,
+    ) -> BoxedFilter<(T,)> {
+        let index = endpoint.path.split('/').skip(1).position(|segment| {
+            segment.strip_prefix('{').and_then(|s| s.strip_suffix('}')) == Some(name)
+        });
+
+        warp::path::peek()
+            .and_then(move |path: Peek| async move {
+                index
+                    .and_then(|index| Self::target(&path).2.get(index).copied())
+                    .and_then(|segment| segment.parse().ok())
+                    .ok_or_else(warp::reject::not_found)
+            })
+            .boxed()
+    }
+
+    /// Returns a filter that extracts the `Session` a request operates on,
+    /// from the optional `/users/{user}` and `/workspaces/{workspace}`
+    /// prefixes of its path (see `target()`). Without a user in the path, a
+    /// request operates on the user its token belongs to, and without a
+    /// workspace, on the user's active workspace. Resolving the session locks
+    /// the registry and may create the user's queue, so it is left to
+    /// `handler!`, once the request has been authenticated and the rest of it
+    /// has matched.
+    fn session(registry: SharedRegistry, events: EventBus) -> BoxedFilter<(Unresolved,)> {
+        warp::path::peek()
+            .map(move |path: Peek| {
+                let (user, workspace, _) = Self::target(&path);
+                Unresolved {
+                    request_id: Self::request_id(),
+                    user: user.map(str::to_string),
+                    workspace: workspace.map(str::to_string),
+                    registry: Arc::clone(&registry),
+                    events: events.clone(),
+                }
+            })
+            .boxed()
+    }
+
+    /// Splits a path below the API version into its user, its workspace, and
+    /// the segments of the endpoint's path. `/workspaces/{workspace}` only
+    /// selects a workspace if more of the path follows, so it doesn't swallow
+    /// `DELETE /workspaces/{workspace}`.
+    fn target(path: &Peek) -> (Option<&str>, Option<&str>, Vec<&str>) {
+        let mut segments: Vec<&str> = path.segments().collect();
+
+        let mut user = None;
+        if segments.len() >= 2 && segments[0] == "users" {
+            user = Some(segments[1]);
+            segments.drain(..2);
+        }
+
+        let mut workspace = None;
+        if segments.len() > 2 && segments[0] == "workspaces" {
+            workspace = Some(segments[1]);
+            segments.drain(..2);
+        }
+
+        (user, workspace, segments)
+    }
+
+    /// Resolves the queue belonging to `user`, or to the identity's own user if
+    /// none is given. A user's own queue is created on first use. Only admin
+    /// tokens of the default user may access the queues of other users. If a
+    /// workspace is given, it must already exist.
+    fn resolve(identity: Identity, unresolved: Unresolved) -> Result<Session, warp::Rejection> {
+        let Unresolved {
+            request_id,
+            user,
+            workspace,
+            registry,
+            events,
+        } = unresolved;
+        let user = user.unwrap_or_else(|| identity.user.clone());
+        let (workspace, queue) = {
+            let mut users = registry.lock().map_err(|_| warp::reject::custom(IOError))?;
+
+            let queue = if user == identity.user {
+                users.get_or_create(&user)?
+            } else if identity.user == DEFAULT_USER && identity.scope == Scope::Admin {
+                users.get(&user).ok_or(warp::reject::custom(UserNotFound))?
+            } else {
+                return Err(warp::reject::custom(Forbidden));
+            };
+
+            match workspace {
+                Some(workspace) => {
+                    let queue = users
+                        .workspace(&user, &workspace)
+                        .ok_or(warp::reject::custom(WorkspaceNotFound))?;
+                    (workspace, queue)
+                }
+                None => {
+                    let active = users.active(&user);
+                    (
+                        active.unwrap_or_else(|| DEFAULT_WORKSPACE.to_string()),
+                        queue,
+                    )
+                }
+            }
+        };
+
+        Ok(Session {
+            request_id,
+            scope: identity.scope,
+            events: events.scoped(&user, &workspace),
+            user,
+            workspace,
+            queue,
+            registry,
+        })
+    }
+
     /// Extracts a `NaiveTask` from a `POST` request.
     fn post_json() -> impl Filter<Extract = (NaiveTask,), Error = warp::Rejection> + Clone {
         warp::body::content_length_limit(1024 * 16).and(warp::body::json())
     }
 
+    /// Extracts a list of `Operation`s from a `POST` request. Batches may be
+    /// larger than other requests.
+    fn batch_json() -> impl Filter<Extract = (Vec<Operation>,), Error = warp::Rejection> + Clone {
+        warp::body::content_length_limit(1024 * 1024).and(warp::body::json())
+    }
+
     /// Extracts an `UpdateTask` from a `PUT` request.
     fn put_json() -> impl Filter<Extract = (UpdateTask,), Error = warp::Rejection> + Clone {
         warp::body::content_length_limit(1024 * 16).and(warp::body::json())
     }
 
+    /// Extracts a `NaiveUpdate` from a `PATCH` request.
+    fn patch_json() -> impl Filter<Extract = (NaiveUpdate,), Error = warp::Rejection> + Clone {
+        warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+    }
+
+    /// Extracts a `SchedulerState` from a `PUT` request.
+    fn scheduler_json(
+    ) -> impl Filter<Extract = (SchedulerState,), Error = warp::Rejection> + Clone {
+        warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+    }
+
     /// Extracts a `Box<dyn Priority>` from a `PUT` request.
     fn priority_json(
     ) -> impl Filter<Extract = (Box<dyn Priority>,), Error = warp::Rejection> + Clone {
         warp::body::content_length_limit(1024 * 16).and(warp::body::json())
     }
 
+    /// Extracts a `Hold` from a `PUT` request.
+    fn hold_json() -> impl Filter<Extract = (Hold,), Error = warp::Rejection> + Clone {
+        warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+    }
+
+    /// Extracts a `NaivePin` from a `PUT` request.
+    fn pin_json() -> impl Filter<Extract = (NaivePin,), Error = warp::Rejection> + Clone {
+        warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+    }
+
+    /// Extracts a `Pin` from a `PUT` request.
+    fn pin_body() -> impl Filter<Extract = (Pin,), Error = warp::Rejection> + Clone {
+        warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+    }
+
+    /// Extracts a `NaiveWebhook` from a `POST` request.
+    fn webhook_json() -> impl Filter<Extract = (NaiveWebhook,), Error = warp::Rejection> + Clone {
+        warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+    }
+
+    /// Extracts a `NewWorkspace` from a `POST` request.
+    fn workspace_json() -> impl Filter<Extract = (NewWorkspace,), Error = warp::Rejection> + Clone {
+        warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+    }
+
+    /// Extracts a workspace name from a `PUT` request.
+    fn name_json() -> impl Filter<Extract = (String,), Error = warp::Rejection> + Clone {
+        warp::body::content_length_limit(1024 * 16).and(warp::body::json())
+    }
+
     /// Adds a task to the queue.
     async fn add_task(
+        session: Session,
         task: NaiveTask,
-        queue: SharedQueue,
+        rules: Rules,
     ) -> Result<impl warp::Reply, warp::Rejection> {
-        info!("Adding task {}", task.title);
+        info!("[{}] Adding task {}", session.request_id, task.title);
 
+        let (queue, events) = session.authorize(Scope::Write)?;
+        rules.check_task(&task, chrono::Local::now().naive_local())?;
         let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
         let task = Task::from_naive(task, queue.new_id());
-        queue.add(task);
+        queue.add(task.clone());
+        events.publish(Event::TaskAdded(task));
 
         Ok(warp::reply::with_status(
             warp::reply::json(&"Item successfully added"),
@@ -213,67 +847,298 @@ impl Server {
 
     /// Replies with a serialized representation of the entire contents of the
     /// queue.
-    async fn get_tasks(queue: SharedQueue) -> Result<impl warp::Reply, warp::Rejection> {
-        info!("Fetching tasks");
+    async fn get_tasks(
+        session: Session,
+        query: TaskQuery,
+        if_none_match: Option<String>,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Fetching tasks", session.request_id);
 
+        let (queue, _) = session.authorize(Scope::Read)?;
         let queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        let page = queue.query(&query)?;
 
-        if !queue.is_empty() || !queue.is_completed_empty() {
-            Ok(warp::reply::with_status(
-                warp::reply::json(&queue.clone()),
-                warp::http::StatusCode::OK
-            ))
-        } else {
-            Err(warp::reject::custom(TaskNotFound))
-        }
+        Ok(Self::tagged(&page, queue.revision(), if_none_match))
     }
 
-    /// Updates a task in the queue.
-    async fn update_task(
-        updates: UpdateTask,
-        queue: SharedQueue,
+    /// Applies a batch of operations atomically, and replies with the outcome
+    /// of each. If any operation fails, none are applied and the reply is
+    /// `409 Conflict`.
+    async fn batch(
+        session: Session,
+        operations: Vec<Operation>,
+        rules: Rules,
     ) -> Result<impl warp::Reply, warp::Rejection> {
-        info!("Updating task {}", updates.id);
+        info!(
+            "[{}] Applying batch of {} operations",
+            session.request_id,
+            operations.len()
+        );
 
+        let (queue, events) = session.authorize(Scope::Write)?;
+        rules.check_operations(&operations, chrono::Local::now().naive_local())?;
         let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
-        let task = match queue.get_mut(updates.id) {
-            Some(task) => task,
-            None => {
-                error!("Task {} does not exist", updates.id);
-                return Err(warp::reject::reject());
-            }
-        };
-
-        // update existing fields
+        let result = queue.apply(&operations);
+        if !result.committed {
+            return Ok(warp::reply::with_status(
+                warp::reply::json(&result),
+                warp::http::StatusCode::CONFLICT,
+            ));
+        }
 
-        if let Some(title) = updates.title {
-            task.title = title;
+        for (operation, outcome) in operations.iter().zip(&result.results) {
+            let Some(task) = outcome.task.clone() else {
+                continue;
+            };
+            events.publish(match operation {
+                Operation::Add { .. } => Event::TaskAdded(task),
+                Operation::Update { .. } => Event::TaskUpdated(task),
+                Operation::Delete { id } => Event::TaskDeleted(*id),
+                Operation::Complete { .. } => Event::TaskCompleted(task),
+            });
         }
-        if let Some(deadline) = updates.deadline {
-            task.deadline = deadline;
+
+        Ok(warp::reply::with_status(warp::reply::json(&result), warp::http::StatusCode::OK))
+    }
+
+    /// Replies with the changes that can be undone and redone.
+    async fn history(session: Session) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Fetching history", session.request_id);
+
+        let (queue, _) = session.authorize(Scope::Read)?;
+        let queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+
+        Ok(warp::reply::with_status(
+            warp::reply::json(queue.history()),
+            warp::http::StatusCode::OK,
+        ))
+    }
+
+    /// Reverts the most recent change, and replies with it. Like setting the
+    /// priority, undoing a change to it requires the `Admin` scope.
+    async fn undo(session: Session) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Undoing last change", session.request_id);
+
+        let (queue, events) = session.authorize(Scope::Write)?;
+        let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        if queue
+            .history()
+            .undo
+            .back()
+            .is_some_and(|e| e.change.sets_priority())
+        {
+            session.authorize(Scope::Admin)?;
         }
-        if let Some(duration) = updates.duration {
-            task.duration = duration;
+        let entry = queue.undo()?;
+        Self::publish_change(&events, &queue, &entry.change, true);
+
+        Ok(warp::reply::with_status(warp::reply::json(&entry), warp::http::StatusCode::OK))
+    }
+
+    /// Reapplies the most recently undone change, and replies with it. Like
+    /// undoing, redoing a change to the priority requires the `Admin` scope.
+    async fn redo(session: Session) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Redoing last undone change", session.request_id);
+
+        let (queue, events) = session.authorize(Scope::Write)?;
+        let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        if queue
+            .history()
+            .redo
+            .last()
+            .is_some_and(|e| e.change.sets_priority())
+        {
+            session.authorize(Scope::Admin)?;
         }
-        if let Some(priority) = updates.priority {
-            task.priority = priority;
+        let entry = queue.redo()?;
+        Self::publish_change(&events, &queue, &entry.change, false);
+
+        Ok(warp::reply::with_status(warp::reply::json(&entry), warp::http::StatusCode::OK))
+    }
+
+    /// Publishes the events of a change that was undone or redone, as returned
+    /// by `undo()` or `redo()`, so that they carry the tasks' current IDs.
+    fn publish_change(events: &EventBus, queue: &TaskQueue, change: &Change, undone: bool) {
+        match (change, undone) {
+            (Change::Add { task }, false) | (Change::Delete { task }, true) => {
+                events.publish(Event::TaskAdded(task.clone()))
+            }
+            (Change::Add { task }, true) | (Change::Delete { task }, false) => {
+                events.publish(Event::TaskDeleted(task.id()))
+            }
+            (Change::Update { after, .. }, _) => {
+                if let Some(task) = queue.get(after.id) {
+                    events.publish(Event::TaskUpdated(task.clone()))
+                }
+            }
+            (Change::Complete { task, completed }, true) => {
+                events.publish(Event::CompletedTaskDeleted(completed.id()));
+                events.publish(Event::TaskAdded(task.clone()));
+            }
+            (Change::Complete { completed, .. }, false) => {
+                events.publish(Event::TaskCompleted(completed.clone()))
+            }
+            (Change::Priority { before, .. }, true) => {
+                events.publish(Event::PriorityChanged(before.string()))
+            }
+            (Change::Priority { after, .. }, false) => {
+                events.publish(Event::PriorityChanged(after.string()))
+            }
+            (Change::Batch { changes }, true) => {
+                for change in changes.iter().rev() {
+                    Self::publish_change(events, queue, change, true);
+                }
+            }
+            (Change::Batch { changes }, false) => {
+                for change in changes {
+                    Self::publish_change(events, queue, change, false);
+                }
+            }
         }
+    }
 
-        Ok(warp::reply::with_status(
-            warp::reply::json(&"Item successfully updated"),
-            warp::http::StatusCode::CREATED,
+    /// Replies with the deleted tasks in the trash.
+    async fn trash(session: Session) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Fetching trash", session.request_id);
+
+        let (queue, _) = session.authorize(Scope::Read)?;
+        let queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+
+        Ok(warp::reply::with_status(warp::reply::json(&queue.trash()), warp::http::StatusCode::OK))
+    }
+
+    /// Restores a deleted task from the trash, and replies with it.
+    async fn restore(
+        session: Session,
+        status: TaskStatus,
+        id: usize,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        info!(
+            "[{}] Restoring task {id} from the trash",
+            session.request_id
+        );
+
+        let (queue, events) = session.authorize(Scope::Write)?;
+        let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        let task = queue.restore_trashed(status, id)?;
+        events.publish(match status {
+            TaskStatus::Open => Event::TaskAdded(task.clone()),
+            TaskStatus::Completed => Event::TaskCompleted(task.clone()),
+        });
+
+        Ok(warp::reply::with_status(warp::reply::json(&task), warp::http::StatusCode::OK))
+    }
+
+    /// Permanently deletes every task in the trash.
+    async fn empty_trash(session: Session) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Emptying trash", session.request_id);
+
+        let (queue, _) = session.authorize(Scope::Write)?;
+        let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        let count = queue.empty_trash();
+
+        Ok(warp::reply::with_status(warp::reply::json(&count), warp::http::StatusCode::OK))
+    }
+
+    /// Replies with the tasks best matching a search, best match first.
+    async fn search(
+        session: Session,
+        query: SearchQuery,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Searching tasks for {}", session.request_id, query.q);
+
+        let (queue, _) = session.authorize(Scope::Read)?;
+        let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        let results = queue.search(&query);
+
+        Ok(warp::reply::with_status(warp::reply::json(&results), warp::http::StatusCode::OK))
+    }
+
+    /// Replies with the task corresponding to the given ID.
+    async fn get_task(
+        session: Session,
+        id: usize,
+        if_none_match: Option<String>,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Fetching task {id}", session.request_id);
+
+        let (queue, _) = session.authorize(Scope::Read)?;
+        let queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        let task = queue.get(id).ok_or(warp::reject::custom(TaskNotFound))?;
+
+        Ok(Self::tagged(task, task.revision(), if_none_match))
+    }
+
+    /// Replies with the completed task corresponding to the given ID.
+    async fn get_completed(
+        session: Session,
+        id: usize,
+        if_none_match: Option<String>,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Fetching completed task {id}", session.request_id);
+
+        let (queue, _) = session.authorize(Scope::Read)?;
+        let queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        let task = queue
+            .get_completed(id)
+            .ok_or(warp::reject::custom(TaskNotFound))?;
+
+        Ok(Self::tagged(task, task.revision(), if_none_match))
+    }
+
+    /// Updates a task in the queue, and replies with its new ETag.
+    async fn update_task(
+        session: Session,
+        if_match: Option<String>,
+        updates: UpdateTask,
+        rules: Rules,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Updating task {}", session.request_id, updates.id);
+
+        let (queue, events) = session.authorize(Scope::Write)?;
+        rules.check_update(&updates, chrono::Local::now().naive_local())?;
+        let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        if let Some(task) = queue.get(updates.id) {
+            Self::precondition(if_match, task.revision())?;
+        }
+        let task = queue.update(updates)?;
+        events.publish(Event::TaskUpdated(task.clone()));
+
+        Ok(warp::reply::with_header(
+            warp::reply::with_status(
+                warp::reply::json(&"Item successfully updated"),
+                warp::http::StatusCode::CREATED,
+            ),
+            "etag",
+            Self::etag(task.revision()),
         ))
     }
 
+    /// Updates the task with the given ID, and replies with its new ETag.
+    async fn patch_task(
+        session: Session,
+        id: usize,
+        if_match: Option<String>,
+        update: NaiveUpdate,
+        rules: Rules,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        Self::update_task(session, if_match, UpdateTask::from_naive(update, id), rules).await
+    }
+
     /// Deletes a task from the queue.
     async fn delete_task(
+        session: Session,
         id: usize,
-        queue: SharedQueue,
+        if_match: Option<String>,
     ) -> Result<impl warp::Reply, warp::Rejection> {
-        info!("Deleting task {id}");
+        info!("[{}] Deleting task {id}", session.request_id);
 
+        let (queue, events) = session.authorize(Scope::Write)?;
         let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        let task = queue.get(id).ok_or(warp::reject::custom(TaskNotFound))?;
+        Self::precondition(if_match, task.revision())?;
         queue.delete(id)?;
+        events.publish(Event::TaskDeleted(id));
 
         Ok(warp::reply::with_status(
             warp::reply::json(&"Item successfully deleted"),
@@ -282,11 +1147,13 @@ impl Server {
     }
 
     /// Enables the scheduler, which will start executing scheduling logic.
-    async fn enable(queue: SharedQueue) -> Result<impl warp::Reply, warp::Rejection> {
-        info!("Enabling scheduler");
+    async fn enable(session: Session) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Enabling scheduler", session.request_id);
 
+        let (queue, events) = session.authorize(Scope::Admin)?;
         let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
-        queue.enabled = true;
+        queue.set_enabled(true);
+        events.publish(Event::SchedulerEnabled);
 
         Ok(warp::reply::with_status(
             warp::reply::json(&"Scheduler successfully enabled"),
@@ -295,11 +1162,13 @@ impl Server {
     }
 
     /// Disables the scheduler, which will stop executing scheduling logic.
-    async fn disable(queue: SharedQueue) -> Result<impl warp::Reply, warp::Rejection> {
-        info!("Disabling scheduler");
+    async fn disable(session: Session) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Disabling scheduler", session.request_id);
 
+        let (queue, events) = session.authorize(Scope::Admin)?;
         let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
-        queue.enabled = false;
+        queue.set_enabled(false);
+        events.publish(Event::SchedulerDisabled);
 
         Ok(warp::reply::with_status(
             warp::reply::json(&"Scheduler successfully disabled"),
@@ -307,71 +1176,247 @@ impl Server {
         ))
     }
 
-    /// Fetches the active task.
-    async fn active(queue: SharedQueue) -> Result<impl warp::Reply, warp::Rejection> {
-        info!("Fetching active task");
+    /// Fetches the state of the scheduler.
+    async fn scheduler(
+        session: Session,
+        if_none_match: Option<String>,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Fetching scheduler state", session.request_id);
+
+        let (queue, _) = session.authorize(Scope::Read)?;
+        let queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        let state = SchedulerState {
+            enabled: queue.enabled,
+        };
+
+        Ok(Self::tagged(
+            &state,
+            queue.enabled_revision(),
+            if_none_match,
+        ))
+    }
+
+    /// Enables or disables the scheduler, and replies with its new state.
+    async fn set_scheduler(
+        session: Session,
+        state: SchedulerState,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        info!(
+            "[{}] Setting scheduler enabled to {}",
+            session.request_id,
+            state.enabled
+        );
+
+        let (queue, events) = session.authorize(Scope::Admin)?;
+        let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        queue.set_enabled(state.enabled);
+        events.publish(if state.enabled {
+            Event::SchedulerEnabled
+        } else {
+            Event::SchedulerDisabled
+        });
+
+        Ok(warp::reply::with_status(warp::reply::json(&state), warp::http::StatusCode::OK))
+    }
+
+    /// Fetches the task the scheduler is currently working on.
+    async fn active(
+        session: Session,
+        if_none_match: Option<String>,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Fetching active task", session.request_id);
 
+        let (queue, _) = session.authorize(Scope::Read)?;
         let queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
-        if let Some(task) = queue.select() {
-            Ok(warp::reply::with_status(warp::reply::json(&task), warp::http::StatusCode::OK))
+        if !queue.enabled {
+            return Err(warp::reject::custom(SchedulerDisabled));
+        }
+
+        if let Some(active) = queue.active() {
+            Ok(Self::tagged(&active, queue.revision(), if_none_match))
         } else {
             Err(warp::reject::custom(TaskNotFound))
         }
     }
 
+    /// Pins the task with the given ID as the active task.
+    async fn pin(
+        session: Session,
+        id: usize,
+        pin: NaivePin,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Pinning task {id}", session.request_id);
+
+        let (queue, events) = session.authorize(Scope::Write)?;
+        let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        queue.pin(Pin::from_naive(pin, id))?;
+        if let Some(task) = queue.get(id) {
+            events.publish(Event::TaskUpdated(task.clone()));
+        }
+
+        Ok(warp::reply::with_status(
+            warp::reply::json(&"Task successfully pinned"),
+            warp::http::StatusCode::OK,
+        ))
+    }
+
+    /// Pins the task named in the `Pin` as the active task.
+    async fn pin_active(session: Session, pin: Pin) -> Result<impl warp::Reply, warp::Rejection> {
+        Self::pin(session, pin.id, NaivePin { until: pin.until }).await
+    }
+
+    /// Removes the pin on the active task.
+    async fn unpin(session: Session) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Unpinning active task", session.request_id);
+
+        let (queue, events) = session.authorize(Scope::Write)?;
+        let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        let pin = queue.unpin().ok_or(warp::reject::custom(TaskNotFound))?;
+        if let Some(task) = queue.get(pin.id) {
+            events.publish(Event::TaskUpdated(task.clone()));
+        }
+
+        Ok(warp::reply::with_status(
+            warp::reply::json(&"Task successfully unpinned"),
+            warp::http::StatusCode::OK,
+        ))
+    }
+
     /// Fetches the scheduler status (enabled/disabled).
-    async fn status(queue: SharedQueue) -> Result<impl warp::Reply, warp::Rejection> {
-        info!("Fetching scheduler status");
+    async fn status(
+        session: Session,
+        if_none_match: Option<String>,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Fetching scheduler status", session.request_id);
 
+        let (queue, _) = session.authorize(Scope::Read)?;
         let queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
-        Ok(warp::reply::with_status(warp::reply::json(&queue.enabled), warp::http::StatusCode::OK))
+        Ok(Self::tagged(
+            &queue.enabled,
+            queue.enabled_revision(),
+            if_none_match,
+        ))
     }
 
-    /// Applies the provided priority to the task queue.
+    /// Applies the provided priority to the task queue, and replies with the
+    /// priority's new ETag.
     async fn set_priority(
+        session: Session,
+        if_match: Option<String>,
         priority: Box<dyn Priority>,
-        queue: SharedQueue,
     ) -> Result<impl warp::Reply, warp::Rejection> {
-        info!("Updating task queue priority");
+        info!("[{}] Updating task queue priority", session.request_id);
 
+        let (queue, events) = session.authorize(Scope::Admin)?;
         let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
-        queue.priority = priority;
-
-        Ok(warp::reply::with_status(
-            warp::reply::json(&"Task queue priority successfully updated"),
-            warp::http::StatusCode::CREATED,
+        Self::precondition(if_match, queue.priority_revision())?;
+        queue.set_priority(priority);
+        events.publish(Event::PriorityChanged(queue.show_priority()));
+
+        Ok(warp::reply::with_header(
+            warp::reply::with_status(
+                warp::reply::json(&"Task queue priority successfully updated"),
+                warp::http::StatusCode::CREATED,
+            ),
+            "etag",
+            Self::etag(queue.priority_revision()),
         ))
     }
 
     /// Fetches the current scheduler priority
-    async fn get_priority(queue: SharedQueue) -> Result<impl warp::Reply, warp::Rejection> {
-        info!("Fetching scheduler priority");
+    async fn get_priority(
+        session: Session,
+        if_none_match: Option<String>,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Fetching scheduler priority", session.request_id);
+
+        let (queue, _) = session.authorize(Scope::Read)?;
+        let queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+
+        Ok(Self::tagged(
+            &queue.priority,
+            queue.priority_revision(),
+            if_none_match,
+        ))
+    }
+
+    /// Fetches the predicted schedule of the tasks in the queue.
+    async fn forecast(
+        session: Session,
+        switch_cost: chrono::Duration,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Fetching forecast", session.request_id);
+
+        let (queue, _) = session.authorize(Scope::Read)?;
+        let queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        let forecast =
+            queue.forecast_with_switch_cost(chrono::Local::now().naive_local(), switch_cost);
+
+        Ok(warp::reply::with_status(warp::reply::json(&forecast), warp::http::StatusCode::OK))
+    }
+
+    /// Replies with the long gaps between updates the scheduler recorded.
+    async fn drift(session: Session) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Fetching clock drift", session.request_id);
 
+        let (queue, _) = session.authorize(Scope::Read)?;
         let queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
 
-        Ok(warp::reply::with_status(warp::reply::json(&queue.priority), warp::http::StatusCode::OK))
+        Ok(warp::reply::with_status(warp::reply::json(&queue.drift()), warp::http::StatusCode::OK))
+    }
+
+    /// Puts the task with the given ID on hold.
+    async fn hold(
+        session: Session,
+        id: usize,
+        hold: Hold,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Putting task {id} on hold", session.request_id);
+
+        let (queue, events) = session.authorize(Scope::Write)?;
+        let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        queue.hold(id, hold)?;
+        if let Some(task) = queue.get(id) {
+            events.publish(Event::TaskUpdated(task.clone()));
+        }
+
+        Ok(warp::reply::with_status(
+            warp::reply::json(&"Task put on hold"),
+            warp::http::StatusCode::OK,
+        ))
+    }
+
+    /// Releases the hold on the task with the given ID.
+    async fn release(session: Session, id: usize) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Releasing hold on task {id}", session.request_id);
+
+        let (queue, events) = session.authorize(Scope::Write)?;
+        let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        queue.release(id)?;
+        if let Some(task) = queue.get(id) {
+            events.publish(Event::TaskUpdated(task.clone()));
+        }
+
+        Ok(warp::reply::with_status(
+            warp::reply::json(&"Task released from hold"),
+            warp::http::StatusCode::OK,
+        ))
     }
 
     /// Marks the task with the given ID as complete.
-    async fn complete(id: usize, queue: SharedQueue) -> Result<impl warp::Reply, warp::Rejection> {
-        info!("Marking task {id} as complete");
+    async fn complete(
+        session: Session,
+        id: usize,
+        if_match: Option<String>,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Marking task {id} as complete", session.request_id);
 
+        let (queue, events) = session.authorize(Scope::Write)?;
         let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
-        let task = queue
-            .get_mut(id)
-            .ok_or(warp::reject::custom(TaskNotFound))?
-            .to_owned();
-        queue
-            .delete(task.id)
-            .map_err(|_| warp::reject::custom(TaskNotFound))?;
-        let c_task = Task::new(
-            queue.new_id_completed(),
-            task.title,
-            task.deadline,
-            task.duration,
-            task.priority,
-        );
-        queue.add_completed(c_task);
+        let task = queue.get(id).ok_or(warp::reject::custom(TaskNotFound))?;
+        Self::precondition(if_match, task.revision())?;
+        let task = queue.complete(id)?;
+        events.publish(Event::TaskCompleted(task));
 
         Ok(warp::reply::with_status(
             warp::reply::json(&"Task marked as completed"),
@@ -381,13 +1426,15 @@ impl Server {
 
     /// Deletes a task from the completed list.
     async fn del_complete(
+        session: Session,
         id: usize,
-        queue: SharedQueue,
     ) -> Result<impl warp::Reply, warp::Rejection> {
-        info!("Deleting task {id}");
+        info!("[{}] Deleting task {id}", session.request_id);
 
+        let (queue, events) = session.authorize(Scope::Write)?;
         let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
         queue.delete_completed(id)?;
+        events.publish(Event::CompletedTaskDeleted(id));
 
         Ok(warp::reply::with_status(
             warp::reply::json(&"Item successfully deleted"),
@@ -395,26 +1442,865 @@ impl Server {
         ))
     }
 
-    /// Transforms rejections into proper server replies.
+    /// Registers a new webhook.
+    async fn add_webhook(
+        session: Session,
+        webhook: NaiveWebhook,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        info!(
+            "[{}] Registering webhook for {}",
+            session.request_id,
+            webhook.url
+        );
+
+        let (queue, _) = session.authorize(Scope::Admin)?;
+        let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        let id = queue.add_webhook(webhook);
+
+        Ok(warp::reply::with_status(
+            warp::reply::json(&id),
+            warp::http::StatusCode::CREATED,
+        ))
+    }
+
+    /// Replies with the registered webhooks, without their secrets.
+    async fn get_webhooks(session: Session) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Fetching webhooks", session.request_id);
+
+        let (queue, _) = session.authorize(Scope::Admin)?;
+        let queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        let webhooks: Vec<WebhookInfo> = queue.webhooks().map(WebhookInfo::from).collect();
+
+        Ok(warp::reply::with_status(warp::reply::json(&webhooks), warp::http::StatusCode::OK))
+    }
+
+    /// Deletes a webhook.
+    async fn delete_webhook(
+        session: Session,
+        id: usize,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{}] Deleting webhook {id}", session.request_id);
+
+        let (queue, _) = session.authorize(Scope::Admin)?;
+        let mut queue = queue.lock().map_err(|_| warp::reject::custom(IOError))?;
+        queue.delete_webhook(id)?;
+
+        Ok(warp::reply::with_status(
+            warp::reply::json(&"Webhook successfully deleted"),
+            warp::http::StatusCode::OK,
+        ))
+    }
+
+    /// Moves a task to another of the user's workspaces, and replies with its
+    /// ID in that workspace.
+    async fn move_task(
+        session: Session,
+        id: usize,
+        workspace: String,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        info!(
+            "[{}] Moving task {id} to workspace {workspace}",
+            session.request_id
+        );
+
+        let (user, registry) = (session.user.clone(), Arc::clone(&session.registry));
+        let (queue, events) = session.authorize(Scope::Write)?;
+        let target = registry
+            .lock()
+            .map_err(|_| warp::reject::custom(IOError))?
+            .workspace(&user, &workspace)
+            .ok_or(warp::reject::custom(WorkspaceNotFound))?;
+        if Arc::ptr_eq(&queue, &target) {
+            return Ok(warp::reply::with_status(warp::reply::json(&id), warp::http::StatusCode::OK));
+        }
+
+        // both queues are held while the task is moved, so it can't get lost
+        // in between. They are locked in the order of their names, so that
+        // concurrent moves in opposite directions can't deadlock
+        let (first, second) = if session.workspace < workspace {
+            (&queue, &target)
+        } else {
+            (&target, &queue)
+        };
+        let first = first.lock().map_err(|_| warp::reject::custom(IOError))?;
+        let second = second.lock().map_err(|_| warp::reject::custom(IOError))?;
+        let (mut source, mut target) = if session.workspace < workspace {
+            (first, second)
+        } else {
+            (second, first)
+        };
+
+        // the source's history of the task no longer applies, and would
+        // otherwise be undone on whichever task is given its ID next
+        let task = source.take(id)?;
+        source.forget(id);
+        events.publish(Event::TaskDeleted(id));
+
+        let new_id = target.adopt(task);
+        if let Some(task) = target.get(new_id) {
+            events
+                .scoped(&user, &workspace)
+                .publish(Event::TaskAdded(task.clone()));
+        }
+
+        Ok(warp::reply::with_status(warp::reply::json(&new_id), warp::http::StatusCode::OK))
+    }
+
+    /// Replies with a summary of the user's workspaces.
+    async fn get_workspaces(session: Session) -> Result<impl warp::Reply, warp::Rejection> {
+        let (user, registry, _) = session.authorize_user(Scope::Read)?;
+        info!("[{}] Fetching workspaces", session.request_id);
+
+        let registry = registry.lock().map_err(|_| warp::reject::custom(IOError))?;
+        Ok(warp::reply::with_status(
+            warp::reply::json(&registry.workspaces(&user)),
+            warp::http::StatusCode::OK,
+        ))
+    }
+
+    /// Creates a new workspace for the user.
+    async fn add_workspace(
+        session: Session,
+        workspace: NewWorkspace,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let (user, registry, _) = session.authorize_user(Scope::Admin)?;
+        info!(
+            "[{}] Creating workspace {}",
+            session.request_id,
+            workspace.name
+        );
+
+        if !is_valid_name(&workspace.name) {
+            return Err(warp::reject::custom(InvalidName));
+        }
+        let mut queue = TaskQueue::new();
+        if let Some(priority) = workspace.priority {
+            queue.priority = priority;
+        }
+
+        let mut registry = registry.lock().map_err(|_| warp::reject::custom(IOError))?;
+        registry.create_workspace(&user, &workspace.name, queue)?;
+
+        Ok(warp::reply::with_status(
+            warp::reply::json(&"Workspace successfully created"),
+            warp::http::StatusCode::CREATED,
+        ))
+    }
+
+    /// Fetches the name of the user's active workspace.
+    async fn get_active_workspace(session: Session) -> Result<impl warp::Reply, warp::Rejection> {
+        let (user, registry, _) = session.authorize_user(Scope::Read)?;
+        info!("[{}] Fetching active workspace", session.request_id);
+
+        let registry = registry.lock().map_err(|_| warp::reject::custom(IOError))?;
+        let active = registry
+            .active(&user)
+            .ok_or(warp::reject::custom(WorkspaceNotFound))?;
+
+        Ok(warp::reply::with_status(warp::reply::json(&active), warp::http::StatusCode::OK))
+    }
+
+    /// Makes a workspace the user's active workspace, which the scheduler
+    /// works on.
+    async fn set_active_workspace(
+        session: Session,
+        workspace: String,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let (user, registry, events) = session.authorize_user(Scope::Admin)?;
+        info!("[{}] Activating workspace {workspace}", session.request_id);
+
+        let mut registry = registry.lock().map_err(|_| warp::reject::custom(IOError))?;
+        registry.activate(&user, &workspace)?;
+        events.publish(Event::WorkspaceChanged(workspace));
+
+        Ok(warp::reply::with_status(
+            warp::reply::json(&"Workspace successfully activated"),
+            warp::http::StatusCode::OK,
+        ))
+    }
+
+    /// Deletes one of the user's workspaces, along with its tasks. The active
+    /// workspace can't be deleted.
+    async fn delete_workspace(
+        session: Session,
+        workspace: String,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let (user, registry, _) = session.authorize_user(Scope::Admin)?;
+        info!("[{}] Deleting workspace {workspace}", session.request_id);
+
+        let mut registry = registry.lock().map_err(|_| warp::reject::custom(IOError))?;
+        if registry.active(&user).as_ref() == Some(&workspace) {
+            return Err(warp::reject::custom(WorkspaceActive));
+        }
+        registry.delete_workspace(&user, &workspace)?;
+
+        Ok(warp::reply::with_status(
+            warp::reply::json(&"Workspace successfully deleted"),
+            warp::http::StatusCode::OK,
+        ))
+    }
+
+    /// Replies with the names of every user with a queue. Only admin tokens of
+    /// the default user may list users.
+    async fn users(
+        identity: Identity,
+        registry: SharedRegistry,
+        request_id: String,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        info!("[{request_id}] Fetching users");
+
+        if identity.user != DEFAULT_USER || identity.scope != Scope::Admin {
+            return Err(warp::reject::custom(Forbidden));
+        }
+
+        let registry = registry.lock().map_err(|_| warp::reject::custom(IOError))?;
+        Ok(warp::reply::with_status(
+            warp::reply::json(&registry.users()),
+            warp::http::StatusCode::OK,
+        ))
+    }
+
+    /// Streams events to the client as Server-Sent Events.
+    async fn sse(
+        session: Session,
+        stopping: watch::Receiver<bool>,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let (_, events) = session.authorize(Scope::Read)?;
+        info!("[{}] Client subscribed to events", session.request_id);
+
+        let stream = events
+            .stream_tagged()
+            .take_until(Self::stopped(stopping))
+            .map(|tagged| {
+                warp::sse::Event::default()
+                    .event(tagged.event.name())
+                    .json_data(&tagged)
+            });
+
+        Ok(warp::sse::reply(warp::sse::keep_alive().stream(stream)))
+    }
+
+    /// Upgrades the connection to a WebSocket and streams events to the
+    /// client as JSON text messages.
+    async fn ws(
+        session: Session,
+        ws: Ws,
+        stopping: watch::Receiver<bool>,
+    ) -> Result<impl warp::Reply, warp::Rejection> {
+        let (_, events) = session.authorize(Scope::Read)?;
+        info!(
+            "[{}] Client subscribed to events over WebSocket",
+            session.request_id
+        );
+
+        Ok(ws.on_upgrade(move |socket| Self::forward_events(socket, events, stopping)))
+    }
+
+    /// Forwards events to a WebSocket until either side closes it, or the
+    /// server shuts down.
+    async fn forward_events(socket: WebSocket, events: EventBus, stopping: watch::Receiver<bool>) {
+        let (mut sender, mut receiver) = socket.split();
+        let mut stream = Box::pin(events.stream_tagged());
+        let stopped = Self::stopped(stopping);
+        tokio::pin!(stopped);
+
+        loop {
+            let event = tokio::select! {
+                event = stream.next() => event,
+                _ = &mut stopped => {
+                    let _ = sender.close().await;
+                    break;
+                }
+                // incoming messages are ignored; the stream only ends when
+                // the client disconnects
+                message = receiver.next() => match message {
+                    Some(Ok(_)) => continue,
+                    _ => break,
+                },
+            };
+
+            let Some(tagged) = event else {
+                break;
+            };
+
+            let Ok(data) = serde_json::to_string(&tagged) else {
+                error!("Unable to serialize event {}", tagged.event.name());
+                continue;
+            };
+
+            if sender.send(Message::text(data)).await.is_err() {
+                break;
+            }
+        }
+
+        info!("Client unsubscribed from events");
+    }
+
+    /// Provides a receiver that is notified when the server shuts down.
+    fn stopping(&self) -> impl Filter<Extract = (watch::Receiver<bool>,), Error = Infallible> + Clone {
+        let shutdown = Arc::clone(&self.shutdown);
+        warp::any().map(move || shutdown.subscribe())
+    }
+
+    /// Resolves once the server starts shutting down.
+    async fn stopped(mut stopping: watch::Receiver<bool>) {
+        // an error means the server was dropped, which is just as final
+        let _ = stopping.wait_for(|stopping| *stopping).await;
+    }
+
+    /// Extracts the `If-Match` header of a request that modifies a resource.
+    fn if_match() -> impl Filter<Extract = (Option<String>,), Error = warp::Rejection> + Clone {
+        warp::header::optional::<String>("if-match")
+    }
+
+    /// Extracts the `If-None-Match` header of a request that fetches a
+    /// resource.
+    fn if_none_match() -> impl Filter<Extract = (Option<String>,), Error = warp::Rejection> + Clone {
+        warp::header::optional::<String>("if-none-match")
+    }
+
+    /// Returns the ETag of a revision.
+    fn etag(revision: u64) -> String {
+        format!("\"{revision}\"")
+    }
+
+    /// Returns `true` if an `If-Match` or `If-None-Match` header lists the
+    /// ETag of the revision, or is `*`. Weak tags are only accepted if `weak`
+    /// is set, as `If-Match` requires a strong comparison.
+    fn etag_matches(header: &str, revision: u64, weak: bool) -> bool {
+        let etag = Self::etag(revision);
+        header.split(',').map(str::trim).any(|tag| {
+            let tag = match tag.strip_prefix("W/") {
+                Some(tag) if weak => tag,
+                Some(_) => return false,
+                None => tag,
+            };
+            tag == "*" || tag == etag
+        })
+    }
+
+    /// Rejects a modification with `PreconditionFailed` if the request's
+    /// `If-Match` header doesn't match the current revision.
+    fn precondition(if_match: Option<String>, revision: u64) -> Result<(), warp::Rejection> {
+        match if_match {
+            Some(header) if !Self::etag_matches(&header, revision, false) => {
+                Err(warp::reject::custom(PreconditionFailed))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Replies with the body and its ETag, or with `304 Not Modified` if the
+    /// request's `If-None-Match` header shows the client already has it.
+    fn tagged<T: Serialize>(
+        body: &T,
+        revision: u64,
+        if_none_match: Option<String>,
+    ) -> warp::reply::Response {
+        let etag = Self::etag(revision);
+        if if_none_match.is_some_and(|header| Self::etag_matches(&header, revision, true)) {
+            return warp::reply::with_header(warp::http::StatusCode::NOT_MODIFIED, "etag", etag)
+                .into_response();
+        }
+
+        warp::reply::with_header(
+            warp::reply::with_status(warp::reply::json(body), warp::http::StatusCode::OK),
+            "etag",
+            etag,
+        )
+        .into_response()
+    }
+
+    /// Replies with the result of a handler, tagged with the ID of the request
+    /// it handled. Errors are replied to here rather than passed on as
+    /// rejections, so the reply carries the same ID as the handler's log
+    /// lines.
+    async fn respond<R: Reply>(
+        request_id: String,
+        reply: impl Future<Output = Result<R, warp::Rejection>>,
+    ) -> warp::reply::Response {
+        match reply.await {
+            Ok(reply) => {
+                warp::reply::with_header(reply, "x-request-id", request_id).into_response()
+            }
+            Err(err) => Self::error_reply(&err, request_id),
+        }
+    }
+
+    /// Transforms rejections of requests that never reached a handler into
+    /// structured error replies.
     async fn handle_rejection(err: warp::Rejection) -> Result<impl warp::Reply, Infallible> {
-        let message;
+        Ok(Self::error_reply(&err, Self::request_id()))
+    }
+
+    /// Builds the structured error reply (see `ErrorResponse`) to a request
+    /// that was rejected, and logs it.
+    fn error_reply(err: &warp::Rejection, request_id: String) -> warp::reply::Response {
+        let status;
         let code;
+        let message;
+        let mut details = None;
 
         if err.find::<IOError>().is_some() {
-            message = "An IO error occurred on the server";
-            code = warp::http::StatusCode::INTERNAL_SERVER_ERROR;
+            code = "io_error";
+            message = "An IO error occurred on the server".to_string();
+            status = warp::http::StatusCode::INTERNAL_SERVER_ERROR;
         } else if err.find::<SerializationError>().is_some() {
-            message = "A serialization error occurred on the server";
-            code = warp::http::StatusCode::INTERNAL_SERVER_ERROR;
+            code = "serialization_error";
+            message = "A serialization error occurred on the server".to_string();
+            status = warp::http::StatusCode::INTERNAL_SERVER_ERROR;
         } else if err.find::<TaskNotFound>().is_some() {
-            message = "The specified task doesn't exist";
-            code = warp::http::StatusCode::NOT_FOUND;
+            code = "task_not_found";
+            message = "The specified task doesn't exist".to_string();
+            status = warp::http::StatusCode::NOT_FOUND;
+        } else if err.find::<UserNotFound>().is_some() {
+            code = "user_not_found";
+            message = "The specified user doesn't exist".to_string();
+            status = warp::http::StatusCode::NOT_FOUND;
+        } else if err.find::<WorkspaceNotFound>().is_some() {
+            code = "workspace_not_found";
+            message = "The specified workspace doesn't exist".to_string();
+            status = warp::http::StatusCode::NOT_FOUND;
+        } else if err.find::<WebhookNotFound>().is_some() {
+            code = "webhook_not_found";
+            message = "The specified webhook doesn't exist".to_string();
+            status = warp::http::StatusCode::NOT_FOUND;
+        } else if err.find::<WorkspaceExists>().is_some() {
+            code = "workspace_exists";
+            message = "A workspace with that name already exists".to_string();
+            status = warp::http::StatusCode::CONFLICT;
+        } else if err.find::<WorkspaceActive>().is_some() {
+            code = "workspace_active";
+            message = "The active workspace can't be deleted".to_string();
+            status = warp::http::StatusCode::CONFLICT;
+        } else if let Some(e) = err.find::<HistoryError>() {
+            code = "history_error";
+            message = e.0.clone();
+            status = warp::http::StatusCode::CONFLICT;
+        } else if err.find::<PreconditionFailed>().is_some() {
+            code = "precondition_failed";
+            message = "The resource has been modified since it was fetched".to_string();
+            status = warp::http::StatusCode::PRECONDITION_FAILED;
+        } else if let Some(e) = err.find::<ValidationError>() {
+            code = "validation_error";
+            message = "The request contains invalid values".to_string();
+            details = Some(serde_json::json!(e.0));
+            status = warp::http::StatusCode::BAD_REQUEST;
+        } else if let Some(e) = err.find::<QueryError>() {
+            code = "invalid_query";
+            message = "The query parameters are invalid".to_string();
+            details = Some(serde_json::json!(e.0));
+            status = warp::http::StatusCode::BAD_REQUEST;
+        } else if let Some(e) = err.find::<warp::reject::InvalidQuery>() {
+            code = "invalid_query";
+            message = "The query parameters are invalid".to_string();
+            details = Some(serde_json::json!(e.to_string()));
+            status = warp::http::StatusCode::BAD_REQUEST;
+        } else if err.find::<InvalidName>().is_some() {
+            code = "invalid_name";
+            message = "Names may only contain letters, digits, '-' and '_'".to_string();
+            status = warp::http::StatusCode::BAD_REQUEST;
+        } else if let Some(e) = err.find::<warp::body::BodyDeserializeError>() {
+            code = "invalid_body";
+            message = "The request body is invalid".to_string();
+            details = e.source().map(|e| serde_json::json!(e.to_string()));
+            status = warp::http::StatusCode::BAD_REQUEST;
+        } else if let Some(e) = err.find::<warp::reject::InvalidHeader>() {
+            code = "invalid_header";
+            message = "A request header is invalid".to_string();
+            details = Some(serde_json::json!(e.name()));
+            status = warp::http::StatusCode::BAD_REQUEST;
+        } else if err.find::<Unauthorized>().is_some() {
+            code = "unauthorized";
+            message = "A valid API token is required".to_string();
+            status = warp::http::StatusCode::UNAUTHORIZED;
+        } else if err.find::<Forbidden>().is_some() {
+            code = "forbidden";
+            message = "The API token does not have permission for this request".to_string();
+            status = warp::http::StatusCode::FORBIDDEN;
+        } else if err.find::<SchedulerDisabled>().is_some() {
+            code = "scheduler_disabled";
+            message = "The scheduler is disabled".to_string();
+            status = warp::http::StatusCode::CONFLICT;
+        } else if err.find::<warp::reject::LengthRequired>().is_some() {
+            code = "length_required";
+            message = "The request must include a Content-Length header".to_string();
+            status = warp::http::StatusCode::LENGTH_REQUIRED;
+        } else if err.find::<warp::reject::PayloadTooLarge>().is_some() {
+            code = "payload_too_large";
+            message = "The request body is too large".to_string();
+            status = warp::http::StatusCode::PAYLOAD_TOO_LARGE;
+        } else if err.find::<warp::reject::UnsupportedMediaType>().is_some() {
+            code = "unsupported_media_type";
+            message = "The request body must be JSON (Content-Type: application/json)".to_string();
+            status = warp::http::StatusCode::UNSUPPORTED_MEDIA_TYPE;
+        } else if err.find::<MethodNotAllowed>().is_some()
+            || err.find::<warp::reject::MethodNotAllowed>().is_some()
+        {
+            code = "method_not_allowed";
+            message = "The method is not allowed for this resource".to_string();
+            status = warp::http::StatusCode::METHOD_NOT_ALLOWED;
+        } else if err.is_not_found() {
+            code = "not_found";
+            message = "The requested resource doesn't exist".to_string();
+            status = warp::http::StatusCode::NOT_FOUND;
         } else {
-            piglog::error!("Critical: {:?}", err);
-            message = "An unknown error occurred. Sorry!";
-            code = warp::http::StatusCode::INTERNAL_SERVER_ERROR;
+            code = "internal_error";
+            message = "An unknown error occurred. Sorry!".to_string();
+            status = warp::http::StatusCode::INTERNAL_SERVER_ERROR;
+        }
+
+        if status.is_server_error() {
+            error!("[{request_id}] Request failed: {:?}", err);
+        } else {
+            info!("[{request_id}] Request rejected: {code}");
+        }
+
+        let body = ErrorResponse {
+            code: code.to_string(),
+            message,
+            details,
+            request_id: request_id.clone(),
+        };
+
+        warp::reply::with_header(
+            warp::reply::with_status(warp::reply::json(&body), status),
+            "x-request-id",
+            request_id,
+        )
+        .into_response()
+    }
+
+    /// Returns a new ID for a request, so its reply can be matched up with the
+    /// server logs.
+    fn request_id() -> String {
+        static NEXT: AtomicU64 = AtomicU64::new(0);
+
+        let millis = chrono::Utc::now().timestamp_millis();
+        format!("{millis:x}-{:x}", NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::scheduler::{Scheduler, SchedulerConfig};
+    use hyper::body::HttpBody;
+    use std::fs;
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn test_graceful_shutdown() {
+        let address = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let config: ServerConfig =
+            serde_json::from_value(serde_json::json!({ "address": address })).unwrap();
+        let dir = std::env::temp_dir().join(format!("taskscheduler-stop-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let scheduler_config: SchedulerConfig = serde_json::from_value(serde_json::json!({
+            "data_path": dir.join("tasks.json"),
+            "write_timeout": 60,
+            "scheduler_timeout": 60_000,
+        }))
+        .unwrap();
+        let queue = Arc::new(Mutex::new(TaskQueue::new()));
+        let registry = Arc::new(Mutex::new(Registry::with_queue(Arc::clone(&queue))));
+        let mut server = Server::with_registry(Arc::clone(&registry));
+        let mut scheduler = Scheduler::with_registry(Arc::clone(&registry));
+        let sigterm = Arc::new(AtomicBool::new(false));
+        let running = tokio::spawn({
+            let sigterm = Arc::clone(&sigterm);
+            async move { server.run(sigterm, config).await }
+        });
+        let scheduling = tokio::spawn({
+            let sigterm = Arc::clone(&sigterm);
+            async move { scheduler.run(sigterm, scheduler_config).await }
+        });
+
+        // subscribe to events, which would otherwise keep the server open
+        let client = hyper::Client::new();
+        let url: hyper::Uri = format!("http://{address}/api/events").parse().unwrap();
+        let response = loop {
+            if let Ok(response) = client.get(url.clone()).await {
+                break response;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        };
+        assert!(response.status().is_success());
+        assert!(registry.lock().unwrap().is_serving());
+
+        // another server is still finishing a request when the signal comes
+        let in_flight = Serving::start(Arc::clone(&registry)).unwrap();
+        sigterm.store(true, Ordering::Relaxed);
+        tokio::time::timeout(Duration::from_secs(5), running)
+            .await
+            .expect("the server didn't stop")
+            .unwrap()
+            .unwrap();
+
+        // so the change it accepts still reaches the disk
+        queue.lock().unwrap().add(crate::test::task(1));
+        drop(in_flight);
+        assert!(!registry.lock().unwrap().is_serving());
+        tokio::time::timeout(Duration::from_secs(5), scheduling)
+            .await
+            .expect("the scheduler didn't stop")
+            .unwrap()
+            .unwrap();
+        let path = Registry::path_for(&dir.join("tasks.json"), DEFAULT_USER, DEFAULT_WORKSPACE);
+        let saved: TaskQueue = serde_json::from_slice(&fs::read(path).unwrap()).unwrap();
+        assert!(saved.get(1).is_some());
+        fs::remove_dir_all(dir).unwrap();
+
+        // and the event stream was ended rather than cut off
+        let mut body = response.into_body();
+        while let Some(chunk) = body.data().await {
+            chunk.unwrap();
         }
+    }
+
+    #[tokio::test]
+    async fn test_session_resolved_last() {
+        let config: ServerConfig = serde_json::from_value(serde_json::json!({
+            "address": "127.0.0.1:0",
+            "tokens": [{
+                "name": "alice",
+                "hash": crate::auth::hash_token("alice-token"),
+                "scope": "write",
+                "user": "alice",
+            }],
+        }))
+        .unwrap();
+        let registry = Arc::new(Mutex::new(Registry::new()));
+        let routes = Server::with_registry(Arc::clone(&registry)).routes(&config);
+
+        // a request that matches no route never gets as far as the session,
+        // which would create alice's queue
+        let response = warp::test::request()
+            .path("/api/v2/nothing")
+            .header("authorization", "Bearer alice-token")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 404);
+        assert!(registry.lock().unwrap().get("alice").is_none());
+
+        let response = warp::test::request()
+            .path("/api/v2/tasks")
+            .header("authorization", "Bearer alice-token")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+        assert!(registry.lock().unwrap().get("alice").is_some());
+    }
+
+    #[tokio::test]
+    async fn test_rejections() {
+        let config: ServerConfig = serde_json::from_value(serde_json::json!({
+            "address": "127.0.0.1:0",
+            "tokens": [{
+                "name": "admin",
+                "hash": crate::auth::hash_token("token"),
+                "scope": "admin",
+            }],
+        }))
+        .unwrap();
+        let routes = Server::with_queue(Arc::new(Mutex::new(TaskQueue::new()))).routes(&config);
+        let request = |path: &str, token: bool| {
+            let request = warp::test::request().path(path);
+            if token {
+                request.header("authorization", "Bearer token")
+            } else {
+                request
+            }
+        };
+
+        // unknown routes are unknown whether or not the request is
+        // authenticated
+        let response = request("/api/v2/nothing", false).reply(&routes).await;
+        assert_eq!(response.status(), 404);
+        let response = request("/api/v2/tasks", false).reply(&routes).await;
+        assert_eq!(response.status(), 401);
+
+        // requests are authenticated before their body is looked at
+        let response = request("/api/v2/tasks", false)
+            .method("POST")
+            .header("content-type", "application/json")
+            .body("{ not json")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 401);
+        let response = request("/api/v2/tasks", true)
+            .method("POST")
+            .header("content-type", "application/json")
+            .body("{ not json")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 400);
+
+        // errors from a handler carry the ID of the request it handled
+        let response = request("/api/v2/tasks/9", true).reply(&routes).await;
+        assert_eq!(response.status(), 404);
+        let error: ErrorResponse = serde_json::from_slice(response.body()).unwrap();
+        assert_eq!(error.code, "task_not_found");
+        assert_eq!(
+            response.headers()["x-request-id"],
+            error.request_id.as_str()
+        );
+
+        let response = request("/api/v2/tasks", true).reply(&routes).await;
+        assert_eq!(response.status(), 200);
+        assert!(response.headers().contains_key("x-request-id"));
+    }
+
+    #[tokio::test]
+    async fn test_conditional_requests() {
+        let config: ServerConfig =
+            serde_json::from_value(serde_json::json!({ "address": "127.0.0.1:0" })).unwrap();
+        let queue = Arc::new(Mutex::new(TaskQueue::new()));
+        queue.lock().unwrap().add(crate::test::task(1));
+        let routes = Server::with_queue(Arc::clone(&queue)).routes(&config);
+        let request = |method: &str, path: &str, header: Option<(&str, &str)>| {
+            let request = warp::test::request().method(method).path(path);
+            match header {
+                Some((name, value)) => request.header(name, value),
+                None => request,
+            }
+        };
+
+        let response = request("GET", "/api/tasks/1", None).reply(&routes).await;
+        let task_etag = response.headers()["etag"].to_str().unwrap().to_string();
+        let response = request("GET", "/api/tasks/priority", None)
+            .reply(&routes)
+            .await;
+        let priority_etag = response.headers()["etag"].to_str().unwrap().to_string();
+
+        let response = request("GET", "/api/tasks/1", Some(("if-none-match", &task_etag)))
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 304);
+
+        // the scheduler charging time to a task changes neither the task nor
+        // the queue
+        let response = request("GET", "/api/tasks", None).reply(&routes).await;
+        let queue_etag = response.headers()["etag"].to_str().unwrap().to_string();
+        queue
+            .lock()
+            .unwrap()
+            .charge(1, chrono::Duration::minutes(1));
+        let response = request("GET", "/api/tasks", Some(("if-none-match", &queue_etag)))
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 304);
+        let response = request("GET", "/api/tasks/1", Some(("if-none-match", &task_etag)))
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 304);
+
+        // changing a task changes it, but not the priority
+        queue.lock().unwrap().get_mut(1).unwrap().duration = chrono::Duration::minutes(1);
+        let response = request("GET", "/api/tasks/1", Some(("if-none-match", &task_etag)))
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+        let response = request(
+            "GET",
+            "/api/tasks/priority",
+            Some(("if-none-match", &priority_etag)),
+        )
+        .reply(&routes)
+        .await;
+        assert_eq!(response.status(), 304);
+
+        let response = request("DELETE", "/api/tasks/1", Some(("if-match", &task_etag)))
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 412);
+        assert!(queue.lock().unwrap().get(1).is_some());
+
+        let set_priority = |etag: &str| {
+            request("PUT", "/api/tasks/priority", Some(("if-match", etag)))
+                .json(&serde_json::json!({ "type": "Deadline" }))
+        };
+        let response = set_priority(&priority_etag).reply(&routes).await;
+        assert_eq!(response.status(), 201);
+        let response = set_priority(&priority_etag).reply(&routes).await;
+        assert_eq!(response.status(), 412);
+    }
+
+    #[tokio::test]
+    async fn test_undo_priority_requires_admin() {
+        let token = |name: &str, scope: &str| {
+            serde_json::json!({
+                "name": name,
+                "hash": crate::auth::hash_token(name),
+                "scope": scope,
+            })
+        };
+        let config: ServerConfig = serde_json::from_value(serde_json::json!({
+            "address": "127.0.0.1:0",
+            "tokens": [token("writer", "write"), token("admin", "admin")],
+        }))
+        .unwrap();
+        let queue = Arc::new(Mutex::new(TaskQueue::new()));
+        queue
+            .lock()
+            .unwrap()
+            .set_priority(Box::new(crate::priority::Shortest));
+        let routes = Server::with_queue(Arc::clone(&queue)).routes(&config);
+        let request = |path: &str, token: &str| {
+            warp::test::request()
+                .method("POST")
+                .path(path)
+                .header("authorization", format!("Bearer {token}"))
+        };
+
+        let response = request("/api/v2/history/undo", "writer")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 403);
+        assert_eq!(queue.lock().unwrap().history().undo.len(), 1);
+
+        let response = request("/api/v2/history/undo", "admin")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 200);
+        let response = request("/api/v2/history/redo", "writer")
+            .reply(&routes)
+            .await;
+        assert_eq!(response.status(), 403);
+    }
+
+    #[test]
+    fn test_etag_matches() {
+        assert!(Server::etag_matches("\"3\"", 3, false));
+        assert!(Server::etag_matches("\"1\", \"3\"", 3, false));
+        assert!(Server::etag_matches("*", 3, false));
+        assert!(!Server::etag_matches("\"4\"", 3, false));
+        assert!(!Server::etag_matches("3", 3, false));
+        // weak tags only match in a weak comparison
+        assert!(!Server::etag_matches("W/\"3\"", 3, false));
+        assert!(Server::etag_matches("W/\"3\"", 3, true));
+    }
+
+    #[tokio::test]
+    async fn test_anonymous_requires_loopback() {
+        let mut server = Server::with_queue(Arc::new(Mutex::new(TaskQueue::new())));
+        let sigterm = Arc::new(AtomicBool::new(true));
+
+        let config = |allow_anonymous| -> ServerConfig {
+            serde_json::from_value(serde_json::json!({
+                "address": "0.0.0.0:0",
+                "allow_anonymous": allow_anonymous,
+            }))
+            .unwrap()
+        };
+        assert!(server.run(Arc::clone(&sigterm), config(false)).await.is_err());
+        assert!(server.run(Arc::clone(&sigterm), config(true)).await.is_ok());
 
-        Ok(warp::reply::with_status(warp::reply::json(&message), code))
+        // each run stops its webhook dispatcher, so they don't pile up
+        assert!(server.run(sigterm, config(true)).await.is_ok());
+        assert_eq!(server.events.subscribers(), 0);
     }
 }
@@ -0,0 +1,126 @@
+use crate::error::TaskNotFound;
+use crate::query::TaskStatus;
+use crate::{Task, TaskQueue};
+use chrono::{Duration, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+/// The number of deleted tasks kept in the trash. Older tasks are purged
+/// when more are deleted, even if the scheduler doesn't purge the trash.
+pub const TRASH_LIMIT: usize = 100;
+
+/// A deleted task, kept in the trash until it is restored or purged.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Trashed {
+    /// Whether the task was open or completed when it was deleted
+    pub status: TaskStatus,
+    pub deleted_at: NaiveDateTime,
+    #[serde(flatten)]
+    pub task: Task,
+}
+
+impl TaskQueue {
+    /// Returns the deleted tasks, oldest first.
+    pub fn trash(&self) -> &[Trashed] {
+        &self.trash
+    }
+
+    /// Moves a deleted task to the trash, purging the oldest task if the
+    /// trash is full.
+    pub(crate) fn discard(&mut self, status: TaskStatus, task: Task) {
+        self.trash.push(Trashed {
+            status,
+            deleted_at: chrono::Local::now().naive_local(),
+            task,
+        });
+        if self.trash.len() > TRASH_LIMIT {
+            self.trash.remove(0);
+        }
+    }
+
+    /// Moves the most recently deleted task with the given status and ID out
+    /// of the trash and back into the queue or completed list, and returns
+    /// it. If its ID has been reused in the meantime, the task is given a new
+    /// one. If there is no such task in the trash, a `TaskNotFound` error is
+    /// returned.
+    pub fn restore_trashed(&mut self, status: TaskStatus, id: usize) -> Result<Task, TaskNotFound> {
+        let i = self
+            .trash
+            .iter()
+            .rposition(|t| t.status == status && t.task.id == id)
+            .ok_or(TaskNotFound)?;
+        let mut task = self.trash.remove(i).task;
+
+        match status {
+            TaskStatus::Open => {
+                let id = self.restore(task);
+                self.get(id).cloned().ok_or(TaskNotFound)
+            }
+            TaskStatus::Completed => {
+                if self.get_completed(task.id).is_some() {
+                    task.id = self.new_id_completed();
+                }
+                self.add_completed(task);
+                self.completed.last().cloned().ok_or(TaskNotFound)
+            }
+        }
+    }
+
+    /// Permanently deletes the tasks that have been in the trash for at least
+    /// `retention`, and returns them.
+    pub fn purge_trash(&mut self, now: NaiveDateTime, retention: Duration) -> Vec<Trashed> {
+        let (purged, kept) = self
+            .trash
+            .drain(..)
+            .partition(|t| t.deleted_at + retention <= now);
+        self.trash = kept;
+        purged
+    }
+
+    /// Permanently deletes every task in the trash, and returns how many
+    /// there were.
+    pub fn empty_trash(&mut self) -> usize {
+        let count = self.trash.len();
+        self.trash.clear();
+        count
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::test::task;
+
+    #[test]
+    fn test_trash() {
+        let mut queue = TaskQueue::new();
+        queue.add(task(1));
+        queue.add(task(2));
+        queue.complete(2).unwrap();
+        queue.delete(1).unwrap();
+        queue.delete_completed(1).unwrap();
+        assert_eq!(queue.trash().len(), 2);
+
+        // a restored task whose ID was reused gets a new one
+        queue.add(task(1));
+        let restored = queue.restore_trashed(TaskStatus::Open, 1).unwrap();
+        assert_eq!(restored.id(), 2);
+        assert_eq!(restored.title, "Task 1");
+        assert!(queue.restore_trashed(TaskStatus::Open, 1).is_err());
+
+        let now = chrono::Local::now().naive_local();
+        assert!(queue.purge_trash(now, Duration::days(1)).is_empty());
+        let purged = queue.purge_trash(now + Duration::days(1), Duration::days(1));
+        assert_eq!(purged.len(), 1);
+        assert_eq!(purged[0].status, TaskStatus::Completed);
+        assert!(queue.trash().is_empty());
+
+        // only the most recently deleted tasks are kept
+        let mut queue = TaskQueue::new();
+        for id in 1..=TRASH_LIMIT + 1 {
+            queue.add(task(id));
+            queue.delete(id).unwrap();
+        }
+        assert_eq!(queue.trash().len(), TRASH_LIMIT);
+        assert_eq!(queue.trash()[0].task.id(), 2);
+    }
+}
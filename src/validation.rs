@@ -0,0 +1,219 @@
+use crate::batch::Operation;
+use crate::error::{FieldError, ValidationError};
+use crate::{NaiveTask, TaskKind, UpdateTask};
+use chrono::{Duration, NaiveDateTime};
+use serde::{Deserialize, Serialize};
+
+/// The rules tasks must follow to be accepted by the server. Every limit can
+/// be configured, and the optional ones disabled by setting them to `null`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Rules {
+    /// The longest title allowed, in characters
+    pub max_title_length: usize,
+
+    /// The shortest duration allowed, in minutes
+    pub min_duration: i64,
+
+    /// The longest duration allowed, in minutes
+    pub max_duration: Option<i64>,
+
+    /// How far in the past a deadline may be, in days
+    pub max_deadline_age: Option<i64>,
+}
+
+impl Default for Rules {
+    fn default() -> Self {
+        Self {
+            max_title_length: 256,
+            min_duration: 0,
+            max_duration: Some(365 * 24 * 60),
+            max_deadline_age: Some(365),
+        }
+    }
+}
+
+impl Rules {
+    /// Checks a new task against the rules. If it breaks any of them, a
+    /// `ValidationError` is returned listing every problem.
+    pub fn check_task(&self, task: &NaiveTask, now: NaiveDateTime) -> Result<(), ValidationError> {
+        let mut errors = Vec::new();
+        self.check_title(&task.title, &mut errors);
+        self.check_duration(task.duration, &mut errors);
+        self.check_deadline(task.deadline, now, &mut errors);
+        self.check_kind(&task.kind, &mut errors);
+
+        Self::result(errors)
+    }
+
+    /// Checks the fields an update sets against the rules. If any of them
+    /// break the rules, a `ValidationError` is returned listing every
+    /// problem.
+    pub fn check_update(
+        &self,
+        update: &UpdateTask,
+        now: NaiveDateTime,
+    ) -> Result<(), ValidationError> {
+        let mut errors = Vec::new();
+        if let Some(title) = &update.title {
+            self.check_title(title, &mut errors);
+        }
+        if let Some(duration) = update.duration {
+            self.check_duration(duration, &mut errors);
+        }
+        if let Some(deadline) = update.deadline {
+            self.check_deadline(deadline, now, &mut errors);
+        }
+        if let Some(kind) = &update.kind {
+            self.check_kind(kind, &mut errors);
+        }
+
+        Self::result(errors)
+    }
+
+    /// Checks the tasks added and updated by a batch against the rules. The
+    /// fields of the errors are prefixed with the index of their operation,
+    /// e.g. `operations[2].title`.
+    pub fn check_operations(
+        &self,
+        operations: &[Operation],
+        now: NaiveDateTime,
+    ) -> Result<(), ValidationError> {
+        let mut errors = Vec::new();
+        for (i, operation) in operations.iter().enumerate() {
+            let result = match operation {
+                Operation::Add { task } => self.check_task(task, now),
+                Operation::Update { task } => self.check_update(task, now),
+                Operation::Delete { .. } | Operation::Complete { .. } => Ok(()),
+            };
+            if let Err(ValidationError(fields)) = result {
+                errors.extend(fields.into_iter().map(|e| FieldError {
+                    field: format!("operations[{i}].{}", e.field),
+                    message: e.message,
+                }));
+            }
+        }
+
+        Self::result(errors)
+    }
+
+    fn check_title(&self, title: &str, errors: &mut Vec<FieldError>) {
+        if title.trim().is_empty() {
+            errors.push(FieldError::new("title", "must not be empty"));
+        } else if title.chars().count() > self.max_title_length {
+            errors.push(FieldError::new(
+                "title",
+                &format!("must be at most {} characters", self.max_title_length),
+            ));
+        }
+    }
+
+    fn check_duration(&self, duration: Duration, errors: &mut Vec<FieldError>) {
+        if duration < Duration::minutes(self.min_duration) {
+            errors.push(FieldError::new(
+                "duration",
+                &format!("must be at least {} minutes", self.min_duration),
+            ));
+        } else if let Some(max) = self
+            .max_duration
+            .filter(|max| duration > Duration::minutes(*max))
+        {
+            errors.push(FieldError::new(
+                "duration",
+                &format!("must be at most {max} minutes"),
+            ));
+        }
+    }
+
+    fn check_deadline(
+        &self,
+        deadline: NaiveDateTime,
+        now: NaiveDateTime,
+        errors: &mut Vec<FieldError>,
+    ) {
+        if let Some(days) = self.max_deadline_age {
+            if deadline < now - Duration::days(days) {
+                errors.push(FieldError::new(
+                    "deadline",
+                    &format!("must be no more than {days} days in the past"),
+                ));
+            }
+        }
+    }
+
+    fn check_kind(&self, kind: &TaskKind, errors: &mut Vec<FieldError>) {
+        if let TaskKind::Fixed { start, end } = kind {
+            if end <= start {
+                errors.push(FieldError::new("kind", "must end after it starts"));
+            }
+        }
+    }
+
+    fn result(errors: Vec<FieldError>) -> Result<(), ValidationError> {
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            Err(ValidationError(errors))
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::PriorityLevel;
+
+    fn fields(result: Result<(), ValidationError>) -> Vec<String> {
+        match result {
+            Ok(()) => Vec::new(),
+            Err(ValidationError(errors)) => errors.into_iter().map(|e| e.field).collect(),
+        }
+    }
+
+    #[test]
+    fn test_rules() {
+        let now = chrono::NaiveDate::from_ymd_opt(2025, 6, 1)
+            .unwrap()
+            .and_hms_opt(12, 0, 0)
+            .unwrap();
+        let rules = Rules::default();
+
+        let task = NaiveTask::new(
+            "Task".into(),
+            now,
+            Duration::hours(1),
+            PriorityLevel::Normal,
+        );
+        assert!(rules.check_task(&task, now).is_ok());
+
+        let task = NaiveTask::new(
+            "x".repeat(300),
+            NaiveDateTime::default(),
+            Duration::minutes(-5),
+            PriorityLevel::Normal,
+        );
+        assert_eq!(
+            fields(rules.check_task(&task, now)),
+            vec!["title", "duration", "deadline"]
+        );
+
+        // only the fields being updated are checked
+        let update = UpdateTask::new(1).with_duration(Some(Duration::days(400)));
+        assert_eq!(fields(rules.check_update(&update, now)), vec!["duration"]);
+
+        let relaxed = Rules {
+            max_duration: None,
+            ..Rules::default()
+        };
+        assert!(relaxed.check_update(&update, now).is_ok());
+
+        let operations = [
+            Operation::Delete { id: 1 },
+            Operation::Update { task: update },
+        ];
+        assert_eq!(
+            fields(rules.check_operations(&operations, now)),
+            vec!["operations[1].duration"]
+        );
+    }
+}
@@ -1,7 +1,17 @@
 use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::Duration;
 
 /// Validates the server address by attempting to bind to it.
 pub fn is_available(address: SocketAddr) -> bool {
     std::net::TcpListener::bind(address).is_ok()
 }
 
+/// Resolves once the `sigterm` flag given to `Server::run` and
+/// `Scheduler::run` is set to `true`.
+pub async fn terminated(sigterm: &AtomicBool) {
+    while !sigterm.load(Ordering::Relaxed) {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+}
+
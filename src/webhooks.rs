@@ -0,0 +1,365 @@
+use crate::error::WebhookError;
+use crate::events::{Event, EventBus, TaggedEvent};
+use crate::registry::SharedRegistry;
+use crate::TaskQueue;
+use chrono::NaiveDateTime;
+use futures_util::StreamExt;
+use hmac::{Hmac, Mac};
+use hyper::client::HttpConnector;
+use hyper::{Body, Client, Method, Request};
+use piglog::{error, info};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::time::Duration;
+
+/// The header containing the name of the event being delivered.
+pub const EVENT_HEADER: &str = "X-Taskscheduler-Event";
+
+/// The header containing the hex-encoded HMAC-SHA256 signature of the request
+/// body, computed with the webhook's secret.
+pub const SIGNATURE_HEADER: &str = "X-Taskscheduler-Signature";
+
+#[cfg(feature = "https")]
+type Connector = hyper_rustls::HttpsConnector<HttpConnector>;
+
+#[cfg(not(feature = "https"))]
+type Connector = HttpConnector;
+
+/// A `Webhook` is a subscription to scheduler events. Matching events are
+/// `POST`ed to `url` as JSON, signed with `secret`.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Webhook {
+    id: usize,
+    pub url: String,
+    /// The names of the events to deliver (see `Event::name()`). An empty
+    /// list subscribes to every event.
+    pub events: Vec<String>,
+    pub secret: String,
+}
+
+impl Webhook {
+    /// Creates a new `Webhook` from an existing `NaiveWebhook` and an ID.
+    pub fn from_naive(webhook: NaiveWebhook, id: usize) -> Self {
+        Self {
+            id,
+            url: webhook.url,
+            events: webhook.events,
+            secret: webhook.secret,
+        }
+    }
+
+    /// Returns this `Webhook`'s ID
+    pub fn id(&self) -> usize {
+        self.id
+    }
+
+    /// Returns `true` if the webhook is subscribed to the given event.
+    pub fn accepts(&self, event: &Event) -> bool {
+        self.events.is_empty() || self.events.iter().any(|e| e == event.name())
+    }
+}
+
+/// A `NaiveWebhook` contains the same information as a `Webhook`, but lacks an
+/// ID. The server assigns one when the webhook is registered.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct NaiveWebhook {
+    pub url: String,
+    #[serde(default)]
+    pub events: Vec<String>,
+    pub secret: String,
+}
+
+/// A `WebhookInfo` describes a registered webhook without revealing its
+/// secret. This is what the server sends when webhooks are listed.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct WebhookInfo {
+    pub id: usize,
+    pub url: String,
+    pub events: Vec<String>,
+}
+
+impl From<&Webhook> for WebhookInfo {
+    fn from(webhook: &Webhook) -> Self {
+        Self {
+            id: webhook.id,
+            url: webhook.url.clone(),
+            events: webhook.events.clone(),
+        }
+    }
+}
+
+/// The body of every webhook request.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct Delivery {
+    pub webhook: usize,
+    pub sent_at: NaiveDateTime,
+    pub workspace: String,
+    pub event: Event,
+}
+
+impl TaskQueue {
+    /// Registers a webhook and returns its ID.
+    pub fn add_webhook(&mut self, webhook: NaiveWebhook) -> usize {
+        use std::collections::HashSet;
+
+        let ids: HashSet<usize> = self.webhooks.iter().map(|w| w.id).collect();
+        let id = (1..).find(|id| !ids.contains(id)).unwrap();
+        self.webhooks.push(Webhook::from_naive(webhook, id));
+
+        id
+    }
+
+    /// Removes the webhook corresponding to the given ID. If the webhook does
+    /// not exist, a `WebhookNotFound` error is returned.
+    pub fn delete_webhook(&mut self, id: usize) -> Result<(), crate::error::WebhookNotFound> {
+        let i = self
+            .webhooks
+            .iter()
+            .position(|w| w.id == id)
+            .ok_or(crate::error::WebhookNotFound)?;
+        self.webhooks.remove(i);
+
+        Ok(())
+    }
+
+    /// Returns an iterator over the registered webhooks.
+    pub fn webhooks(&self) -> impl Iterator<Item = &Webhook> {
+        self.webhooks.iter()
+    }
+}
+
+/// Computes the hex-encoded HMAC-SHA256 signature of `body` using `secret`.
+/// Receivers should compute the same signature and compare it against the
+/// `SIGNATURE_HEADER` to verify a delivery.
+pub fn sign(secret: &str, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any size");
+    mac.update(body);
+
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect()
+}
+
+/// `Dispatcher` listens to an `EventBus` and delivers events to every
+/// matching webhook registered by the user the event belongs to. Failed
+/// deliveries are retried with exponential backoff.
+pub struct Dispatcher {
+    registry: SharedRegistry,
+    events: EventBus,
+    client: Client<Connector>,
+    attempts: usize,
+    backoff: Duration,
+}
+
+impl Dispatcher {
+    /// Creates a new `Dispatcher` for the given registry and event bus. By
+    /// default, a delivery is attempted 5 times, starting with a 1 second
+    /// backoff that doubles after every failure.
+    pub fn new(registry: SharedRegistry, events: EventBus) -> Self {
+        #[cfg(feature = "https")]
+        let connector = hyper_rustls::HttpsConnectorBuilder::new()
+            .with_webpki_roots()
+            .https_or_http()
+            .enable_http1()
+            .build();
+
+        #[cfg(not(feature = "https"))]
+        let connector = HttpConnector::new();
+
+        Self {
+            registry,
+            events,
+            client: Client::builder().build(connector),
+            attempts: 5,
+            backoff: Duration::from_secs(1),
+        }
+    }
+
+    /// Sets the number of delivery attempts and the initial backoff between
+    /// them, and returns the `Dispatcher`.
+    pub fn with_retries(mut self, attempts: usize, backoff: Duration) -> Self {
+        self.attempts = attempts.max(1);
+        self.backoff = backoff;
+        self
+    }
+
+    /// Returns the webhooks of the workspace the event happened in that accept
+    /// it, or `None` if a lock could not be retrieved.
+    fn webhooks(&self, tagged: &TaggedEvent) -> Option<Vec<Webhook>> {
+        let workspace = self
+            .registry
+            .lock()
+            .ok()?
+            .workspace(&tagged.user, &tagged.workspace);
+        let queue = match workspace {
+            Some(queue) => queue,
+            None => return Some(Vec::new()),
+        };
+        let queue = queue.lock().ok()?;
+
+        Some(queue.webhooks().filter(|w| w.accepts(&tagged.event)).cloned().collect())
+    }
+
+    /// Delivers events until the event bus is closed. Each delivery runs in
+    /// its own task, so a slow webhook does not hold up the others.
+    pub async fn run(self) {
+        info!("Starting webhook dispatcher...");
+
+        let mut stream = Box::pin(self.events.stream_tagged());
+        while let Some(tagged) = stream.next().await {
+            let Some(webhooks) = self.webhooks(&tagged) else {
+                error!("Error retrieving lock for webhooks");
+                continue;
+            };
+
+            for webhook in webhooks {
+                let client = self.client.clone();
+                let tagged = tagged.clone();
+                let (attempts, backoff) = (self.attempts, self.backoff);
+
+                tokio::spawn(async move {
+                    if let Err(e) = deliver(&client, &webhook, tagged, attempts, backoff).await {
+                        error!("Webhook {} failed: {e}", webhook.id);
+                    }
+                });
+            }
+        }
+    }
+}
+
+/// Delivers a single event to a webhook, retrying with exponential backoff
+/// until it succeeds or runs out of attempts.
+async fn deliver(
+    client: &Client<Connector>,
+    webhook: &Webhook,
+    tagged: TaggedEvent,
+    attempts: usize,
+    backoff: Duration,
+) -> Result<(), WebhookError> {
+    let name = tagged.event.name();
+    let delivery = Delivery {
+        webhook: webhook.id,
+        sent_at: chrono::Local::now().naive_local(),
+        workspace: tagged.workspace,
+        event: tagged.event,
+    };
+    let body = serde_json::to_vec(&delivery).map_err(|e| WebhookError(e.to_string()))?;
+    let signature = sign(&webhook.secret, &body);
+
+    let mut delay = backoff;
+    let mut last_error = WebhookError("No delivery attempts were made".to_string());
+    for attempt in 1..=attempts {
+        let request = Request::builder()
+            .method(Method::POST)
+            .uri(&webhook.url)
+            .header("Content-Type", "application/json")
+            .header(EVENT_HEADER, name)
+            .header(SIGNATURE_HEADER, format!("sha256={signature}"))
+            .body(Body::from(body.clone()))
+            .map_err(|e| WebhookError(e.to_string()))?;
+
+        match client.request(request).await {
+            Ok(response) if response.status().is_success() => {
+                info!("Delivered {name} to webhook {}", webhook.id);
+                return Ok(());
+            }
+            Ok(response) => {
+                last_error = WebhookError(format!("Received status {}", response.status()));
+            }
+            Err(e) => last_error = WebhookError(e.to_string()),
+        }
+
+        if attempt < attempts {
+            error!(
+                "Delivery to webhook {} failed (attempt {attempt}/{attempts}): {last_error}",
+                webhook.id
+            );
+            tokio::time::sleep(delay).await;
+            delay *= 2;
+        }
+    }
+
+    Err(last_error)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::registry::{Registry, DEFAULT_USER, DEFAULT_WORKSPACE};
+    use crate::SharedQueue;
+    use std::sync::{Arc, Mutex};
+    use warp::Filter;
+
+    #[tokio::test]
+    async fn test_delivery() {
+        let queue: SharedQueue = Arc::new(Mutex::new(TaskQueue::new()));
+        let events = EventBus::new();
+
+        // a local stand-in for the receiving service, which fails the first
+        // request to exercise the retry logic
+        let received = Arc::new(Mutex::new(Vec::new()));
+        let log = Arc::clone(&received);
+        let receiver = warp::post()
+            .and(warp::header::<String>(SIGNATURE_HEADER))
+            .and(warp::body::bytes())
+            .map(move |signature: String, body: warp::hyper::body::Bytes| {
+                let mut log = log.lock().unwrap();
+                log.push((signature, body.to_vec()));
+                if log.len() == 1 {
+                    warp::http::StatusCode::INTERNAL_SERVER_ERROR
+                } else {
+                    warp::http::StatusCode::OK
+                }
+            });
+        let (address, server) = warp::serve(receiver).bind_ephemeral(([127, 0, 0, 1], 0));
+        tokio::spawn(server);
+
+        queue.lock().unwrap().add_webhook(NaiveWebhook {
+            url: format!("http://{address}/hook"),
+            events: vec!["scheduler_enabled".to_string()],
+            secret: "hunter2".to_string(),
+        });
+
+        // webhooks belong to the workspace the event happened in, not to the
+        // active workspace
+        let mut registry = Registry::with_queue(Arc::clone(&queue));
+        registry
+            .create_workspace(DEFAULT_USER, "work", TaskQueue::new())
+            .unwrap();
+        registry.activate(DEFAULT_USER, "work").unwrap();
+        let dispatcher = Dispatcher::new(Arc::new(Mutex::new(registry)), events.clone())
+            .with_retries(3, Duration::from_millis(10));
+        tokio::spawn(dispatcher.run());
+        tokio::task::yield_now().await;
+
+        // filtered out
+        events.publish(Event::SchedulerDisabled);
+        events
+            .scoped("alice", DEFAULT_WORKSPACE)
+            .publish(Event::SchedulerEnabled);
+        events
+            .scoped(DEFAULT_USER, "work")
+            .publish(Event::SchedulerEnabled);
+        events.publish(Event::SchedulerEnabled);
+
+        for _ in 0..100 {
+            if received.lock().unwrap().len() >= 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+
+        let received = received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+
+        let (signature, body) = &received[1];
+        assert_eq!(*signature, format!("sha256={}", sign("hunter2", body)));
+        let delivery: Delivery = serde_json::from_slice(body).unwrap();
+        assert_eq!(delivery.webhook, 1);
+        assert_eq!(delivery.workspace, DEFAULT_WORKSPACE);
+        assert!(matches!(delivery.event, Event::SchedulerEnabled));
+    }
+}